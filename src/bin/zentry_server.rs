@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+
+use zentry_db::{install, interface::http::Server, shared::SharedLedger};
+
+/// Address served on when none is given as the second argument
+const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Ledger logs go to stderr, warnings only unless RUST_LOG asks for more
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    // Data directory, defaults to `data/` when not given as the first argument
+    let mut args = std::env::args().skip(1);
+    let data_dir = args.next().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("data"));
+    let addr = args.next().unwrap_or_else(|| DEFAULT_ADDR.to_string());
+
+    let fresh = !data_dir.exists();
+    let created = install::install_at(&data_dir)?;
+    if !fresh {
+        for path in created {
+            println!("Created missing data file {}", path.display());
+        }
+    }
+    let ledger = SharedLedger::load_from_dir(&data_dir)?;
+
+    let server = Server::bind(&addr, ledger)?;
+    println!("Serving {} on http://{}", data_dir.display(), server.local_addr()?);
+    server.run()?;
+    Ok(())
+}
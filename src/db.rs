@@ -1,36 +1,89 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter};
-use std::path::Path;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, Utc};
-use once_cell::sync::Lazy;
+use log::{debug, info, warn};
+use serde::Serialize;
 use uuid::Uuid;
 
 use crate::{
-    account_layout, conversion_graph_layout, entry_layout, system_layout, transaction_layout,
-    BinaryStorage, TombstoneReader, TombstoneWriter
+    account_type_from_label, account_layout, conversion_graph_layout, entry_layout, system_layout, transaction_layout,
+    ACCOUNT_LAYOUT_VERSION, CONVERSION_GRAPH_LAYOUT_VERSION, ENTRY_LAYOUT_VERSION, SYSTEM_LAYOUT_VERSION, TRANSACTION_LAYOUT_VERSION,
+    classify_graph_key, count_tombstones, GraphKeyClass, read_file_with_offsets, RecordCursor,
+    BinaryRecord, BinaryStorage, FromBinary, TombstoneWriter, WalRecord, WriteAheadLog
 };
+use crate::error::ZentryError;
 use crate::util::uuid::generate_deterministic_uuid;
-use crate::index::BTreeIndex;
-use crate::model::{Transaction, Entry, Account, System, ConversionGraph};
+use crate::index::{BTreeIndex, SecondaryIndex, TimestampIndex};
+use crate::model::{Transaction, Entry, Account, AccountType, System, ConversionGraph, ExactRate, GraphDirection, GraphKey};
 
-static ACCOUNT_BIN_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/accounts.bin"));
-static TRANSACTION_BIN_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/transactions.bin"));
-static ENTRY_BIN_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/entries.bin"));
-static SYSTEM_BIN_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/systems.bin"));
-static CONVERSION_GRAPH_BIN_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/conversion_graphs.bin"));
+const ACCOUNT_BIN_FILE: &str = "accounts.bin";
+const TRANSACTION_BIN_FILE: &str = "transactions.bin";
+const ENTRY_BIN_FILE: &str = "entries.bin";
+const SYSTEM_BIN_FILE: &str = "systems.bin";
+const CONVERSION_GRAPH_BIN_FILE: &str = "conversion_graphs.bin";
 
-static ACCOUNT_IDX_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/accounts.idx"));
-static TRANSACTION_IDX_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/transactions.idx"));
-static ENTRY_IDX_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/entries.idx"));
-static SYSTEM_IDX_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/systems.idx"));
-static CONVERSION_GRAPH_IDX_PATH: Lazy<&'static Path> = Lazy::new(|| Path::new("data/conversion_graphs.idx"));
+const ACCOUNT_IDX_FILE: &str = "accounts.idx";
+const TRANSACTION_IDX_FILE: &str = "transactions.idx";
+const ENTRY_IDX_FILE: &str = "entries.idx";
+const SYSTEM_IDX_FILE: &str = "systems.idx";
+const CONVERSION_GRAPH_IDX_FILE: &str = "conversion_graphs.idx";
+const ACCOUNT_SYSTEM_IDX_FILE: &str = "accounts_by_system.idx";
+const TRANSACTION_TIMESTAMP_IDX_FILE: &str = "transactions_by_timestamp.idx";
+const ENTRY_TIMESTAMP_IDX_FILE: &str = "entries_by_timestamp.idx";
+const TRANSACTION_IDEMPOTENCY_IDX_FILE: &str = "transactions_by_idempotency_key.idx";
+
+const BIN_FILES: [&str; 5] = [ACCOUNT_BIN_FILE, TRANSACTION_BIN_FILE, ENTRY_BIN_FILE, SYSTEM_BIN_FILE, CONVERSION_GRAPH_BIN_FILE];
+const IDX_FILES: [&str; 9] = [
+    ACCOUNT_IDX_FILE,
+    TRANSACTION_IDX_FILE,
+    ENTRY_IDX_FILE,
+    SYSTEM_IDX_FILE,
+    CONVERSION_GRAPH_IDX_FILE,
+    ACCOUNT_SYSTEM_IDX_FILE,
+    TRANSACTION_TIMESTAMP_IDX_FILE,
+    ENTRY_TIMESTAMP_IDX_FILE,
+    TRANSACTION_IDEMPOTENCY_IDX_FILE,
+];
+
+/// Metadata field holding the key a transaction was recorded with, see [`Ledger::record_transaction_idempotent`]
+pub const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+
+/// Decimal places entry amounts are rounded to unless [`Ledger::set_amount_scale`] says otherwise
+pub const DEFAULT_AMOUNT_SCALE: u32 = 2;
+
+/// How far from zero `record_transaction` lets a transaction's legs sum unless
+/// [`Ledger::set_balance_tolerance`] says otherwise. Well under any amount scale, it only absorbs
+/// the float error that adding up many legs leaves behind.
+pub const DEFAULT_BALANCE_TOLERANCE: f64 = 1e-9;
+
+/// Checks a transaction's metadata before it is recorded, see [`Ledger::set_metadata_validator`].
+/// The message of an `Err` is what the rejected `record_transaction` reports.
+pub type MetadataValidator = fn(&serde_json::Value) -> Result<(), String>;
+
+const WAL_FILE: &str = "ledger.wal";
+
+const SYSTEM_JSONL_FILE: &str = "systems.jsonl";
+const ACCOUNT_JSONL_FILE: &str = "accounts.jsonl";
+const CONVERSION_GRAPH_JSONL_FILE: &str = "conversion_graphs.jsonl";
+const TRANSACTION_JSONL_FILE: &str = "transactions.jsonl";
+const ENTRY_JSONL_FILE: &str = "entries.jsonl";
+const JSONL_FILES: [&str; 5] = [SYSTEM_JSONL_FILE, ACCOUNT_JSONL_FILE, CONVERSION_GRAPH_JSONL_FILE, TRANSACTION_JSONL_FILE, ENTRY_JSONL_FILE];
+
+/// Records `BinaryStorage::read_single` keeps decoded
+const RECORD_CACHE_CAPACITY: usize = 1024;
 
 #[derive(Debug)]
 pub struct Ledger {
+    pub data_dir: PathBuf,
     pub storage: BinaryStorage,
+    pub wal: WriteAheadLog,
 
+    /// Keyed by `generate_deterministic_uuid(&account.id)`, the same key `account_index` uses.
+    /// Methods taking an account id expect the raw `Account::id` and derive the key themselves,
+    /// look accounts up with [`Ledger::find_account`] rather than indexing this directly.
     pub accounts: HashMap<Uuid, Account>,
     pub transactions: HashMap<Uuid, Transaction>,
     pub entries: Vec<Entry>,
@@ -39,13 +92,80 @@ pub struct Ledger {
 
     pub account_index: BTreeIndex,
     pub transaction_index: BTreeIndex,
+    /// Keyed by the raw `Entry::id`, unlike the other offset indexes, see [`Ledger::find_entry`]
     pub entry_index: BTreeIndex,
     pub system_index: BTreeIndex,
     pub conversion_graph_index: BTreeIndex,
+    /// `Transaction::timestamp` in microseconds to the transaction's offset.
+    pub transaction_timestamp_index: TimestampIndex,
+    /// Timestamp of each entry's transaction in microseconds, with the entry id, to the entry's
+    /// offset. Entries whose transaction is missing are left out.
+    pub entry_timestamp_index: TimestampIndex,
+
+    /// `system_id` to the `accounts` keys in that system. Loaded from `accounts_by_system.idx` and
+    /// rebuilt from `accounts` when the two disagree.
+    pub account_system_index: SecondaryIndex,
+    /// Account name to its `accounts` key. Names are unique across the ledger, not just within a
+    /// system, since lookups by name carry no system. Derived from `accounts` on load.
+    pub account_name_index: HashMap<String, Uuid>,
+    /// `metadata.idempotency_key` to the id of the transaction recorded with it, one per key.
+    /// Loaded from `transactions_by_idempotency_key.idx` and rebuilt from `transactions` when the
+    /// two disagree.
+    pub transaction_idempotency_index: SecondaryIndex,
+
+    /// Run on every transaction's metadata by `record_transaction`, none by default
+    pub metadata_validator: Option<MetadataValidator>,
+    /// Decimal places `record_transaction` rounds entry amounts to, and balances are compared at
+    pub amount_scale: u32,
+    /// Largest sum of a transaction's legs, overall and within each system, that still counts as balanced
+    pub balance_tolerance: f64,
+    /// Whether `record_transaction` collapses legs on the same account into one, off by default
+    pub merge_same_account_legs: bool,
 }
 
 impl Ledger {
-    pub fn load_from_disk() -> std::io::Result<Self> {
+    pub fn load_from_disk() -> Result<Self, ZentryError> {
+        Self::load_from_dir(Path::new("data"))
+    }
+
+    /// Loads the ledger whose `.bin` and `.idx` files live in `dir`, see [`crate::install_at`].
+    /// Problems found by [`Ledger::verify_integrity`] are logged as warnings and don't stop the load.
+    pub fn load_from_dir(dir: &Path) -> Result<Self, ZentryError> {
+        let ledger = Self::open(dir, false)?;
+
+        if let Err(problems) = ledger.verify_integrity() {
+            for problem in problems {
+                warn!("{}", problem);
+            }
+        }
+
+        Ok(ledger)
+    }
+
+    /// Like [`Ledger::load_from_dir`], but refuses to load a ledger that fails [`Ledger::verify_integrity`].
+    pub fn load_from_dir_strict(dir: &Path) -> Result<Self, ZentryError> {
+        let ledger = Self::open(dir, false)?;
+        ledger.verify_integrity().map_err(ZentryError::IntegrityViolation)?;
+        Ok(ledger)
+    }
+
+    /// Like [`Ledger::load_from_dir`], but nothing in `dir` is created or modified, to inspect a
+    /// ledger safely while another process may be using it. Stale indexes are rebuilt in memory
+    /// only, and an incomplete transaction left in the write-ahead log is left out rather than
+    /// rolled back. Every write fails with [`ZentryError::ReadOnly`].
+    pub fn load_read_only(dir: &Path) -> Result<Self, ZentryError> {
+        let ledger = Self::open(dir, true)?;
+
+        if let Err(problems) = ledger.verify_integrity() {
+            for problem in problems {
+                warn!("{}", problem);
+            }
+        }
+
+        Ok(ledger)
+    }
+
+    fn open(dir: &Path, read_only: bool) -> Result<Self, ZentryError> {
         let start = std::time::Instant::now();
 
         // ---------------------------------------------------------------------------------
@@ -54,86 +174,728 @@ impl Ledger {
         let mut writers = HashMap::new();
         let mut layouts = HashMap::new();
 
-        readers.insert("accounts".to_string(), BufReader::new(File::open(*ACCOUNT_BIN_PATH).unwrap()));
-        readers.insert("transactions".to_string(), BufReader::new(File::open(*TRANSACTION_BIN_PATH).unwrap()));
-        readers.insert("entries".to_string(), BufReader::new(File::open(*ENTRY_BIN_PATH).unwrap()));
-        readers.insert("systems".to_string(), BufReader::new(File::open(*SYSTEM_BIN_PATH).unwrap()));
-        readers.insert("conversion_graphs".to_string(), BufReader::new(File::open(*CONVERSION_GRAPH_BIN_PATH).unwrap()));
-
-        writers.insert("accounts".to_string(), BufWriter::new(OpenOptions::new().append(true).create(true).open(*ACCOUNT_BIN_PATH)?));
-        writers.insert("transactions".to_string(), BufWriter::new(OpenOptions::new().append(true).create(true).open(*TRANSACTION_BIN_PATH)?));
-        writers.insert("entries".to_string(), BufWriter::new(OpenOptions::new().append(true).create(true).open(*ENTRY_BIN_PATH)?));
-        writers.insert("systems".to_string(), BufWriter::new(OpenOptions::new().append(true).create(true).open(*SYSTEM_BIN_PATH)?));
-        writers.insert("conversion_graphs".to_string(), BufWriter::new(OpenOptions::new().append(true).create(true).open(*CONVERSION_GRAPH_BIN_PATH)?));
-
-        layouts.insert("accounts".to_string(), account_layout());
-        layouts.insert("transactions".to_string(), transaction_layout());
-        layouts.insert("entries".to_string(), entry_layout());
-        layouts.insert("systems".to_string(), system_layout());
-        layouts.insert("conversion_graphs".to_string(), conversion_graph_layout());
-
-        let storage = BinaryStorage::new(readers, writers, layouts);
-
-        let accounts_list = storage.read::<Account>()?;
-        let transactions_list = storage.read::<Transaction>()?;
-        let entries_list = storage.read::<Entry>()?;
-        let systems_list = storage.read::<System>()?;
-        let conversion_graphs_list = storage.read::<ConversionGraph>()?;
-
-        let accounts: HashMap<Uuid, Account> = accounts_list.into_iter().map(|account| (account.id, account)).collect();
-        let transactions: HashMap<Uuid, Transaction> = transactions_list.into_iter().map(|transaction| (transaction.id, transaction)).collect();
-        let systems: HashMap<Uuid, System> = systems_list.into_iter().map(|system| {
+        readers.insert(Account::TYPE_KEY.to_string(), open_record_file(&dir.join(ACCOUNT_BIN_FILE))?);
+        readers.insert(Transaction::TYPE_KEY.to_string(), open_record_file(&dir.join(TRANSACTION_BIN_FILE))?);
+        readers.insert(Entry::TYPE_KEY.to_string(), open_record_file(&dir.join(ENTRY_BIN_FILE))?);
+        readers.insert(System::TYPE_KEY.to_string(), open_record_file(&dir.join(SYSTEM_BIN_FILE))?);
+        readers.insert(ConversionGraph::TYPE_KEY.to_string(), open_record_file(&dir.join(CONVERSION_GRAPH_BIN_FILE))?);
+
+        // not opened in append mode so tombstones can be written in place, `write` seeks to the end itself
+        if !read_only {
+            writers.insert(Account::TYPE_KEY.to_string(), BufWriter::new(OpenOptions::new().write(true).create(true).truncate(false).open(dir.join(ACCOUNT_BIN_FILE))?));
+            writers.insert(Transaction::TYPE_KEY.to_string(), BufWriter::new(OpenOptions::new().write(true).create(true).truncate(false).open(dir.join(TRANSACTION_BIN_FILE))?));
+            writers.insert(Entry::TYPE_KEY.to_string(), BufWriter::new(OpenOptions::new().write(true).create(true).truncate(false).open(dir.join(ENTRY_BIN_FILE))?));
+            writers.insert(System::TYPE_KEY.to_string(), BufWriter::new(OpenOptions::new().write(true).create(true).truncate(false).open(dir.join(SYSTEM_BIN_FILE))?));
+            writers.insert(ConversionGraph::TYPE_KEY.to_string(), BufWriter::new(OpenOptions::new().write(true).create(true).truncate(false).open(dir.join(CONVERSION_GRAPH_BIN_FILE))?));
+        }
+
+        layouts.insert(Account::TYPE_KEY.to_string(), account_layout());
+        layouts.insert(Transaction::TYPE_KEY.to_string(), transaction_layout());
+        layouts.insert(Entry::TYPE_KEY.to_string(), entry_layout());
+        layouts.insert(System::TYPE_KEY.to_string(), system_layout());
+        layouts.insert(ConversionGraph::TYPE_KEY.to_string(), conversion_graph_layout());
+
+        let storage = if read_only {
+            BinaryStorage::new_read_only(readers, layouts, RECORD_CACHE_CAPACITY)?
+        } else {
+            BinaryStorage::new(readers, writers, layouts, RECORD_CACHE_CAPACITY)?
+        };
+
+        // The files are independent, so each is scanned on its own thread through its own handle
+        let (account_layout, account_offset) = storage.file_layout::<Account>()?;
+        let (transaction_layout, transaction_offset) = storage.file_layout::<Transaction>()?;
+        let (entry_layout, entry_offset) = storage.file_layout::<Entry>()?;
+        let (system_layout, system_offset) = storage.file_layout::<System>()?;
+        let (conversion_graph_layout, conversion_graph_offset) = storage.file_layout::<ConversionGraph>()?;
+
+        let (accounts_list, transactions_list, entries_list, systems_list, conversion_graphs_list) = std::thread::scope(|scope| {
+            let accounts = scope.spawn(|| read_file_with_offsets::<Account>(&dir.join(ACCOUNT_BIN_FILE), account_layout, account_offset));
+            let transactions = scope.spawn(|| read_file_with_offsets::<Transaction>(&dir.join(TRANSACTION_BIN_FILE), transaction_layout, transaction_offset));
+            let entries = scope.spawn(|| read_file_with_offsets::<Entry>(&dir.join(ENTRY_BIN_FILE), entry_layout, entry_offset));
+            let systems = scope.spawn(|| read_file_with_offsets::<System>(&dir.join(SYSTEM_BIN_FILE), system_layout, system_offset));
+            let conversion_graphs = scope.spawn(|| read_file_with_offsets::<ConversionGraph>(&dir.join(CONVERSION_GRAPH_BIN_FILE), conversion_graph_layout, conversion_graph_offset));
+
+            (join_reader(accounts), join_reader(transactions), join_reader(entries), join_reader(systems), join_reader(conversion_graphs))
+        });
+
+        let accounts_list = accounts_list?;
+        let mut transactions_list = transactions_list?;
+        let mut entries_list = entries_list?;
+        let systems_list = systems_list?;
+        let conversion_graphs_list = conversion_graphs_list?;
+
+        // Roll back transactions that were interrupted before their commit marker was logged
+        let mut wal = if read_only {
+            WriteAheadLog::open_read_only(&dir.join(WAL_FILE))?
+        } else {
+            WriteAheadLog::open(&dir.join(WAL_FILE))?
+        };
+        let pending = wal.pending()?;
+        for record in pending.iter() {
+            if let WalRecord::Begin { transaction_id, entry_ids } = record {
+                let rolled_back = roll_back(&storage, &mut transactions_list, &mut entries_list, *transaction_id, entry_ids)?;
+                if read_only {
+                    info!("Left out incomplete transaction {}: {} records", transaction_id, rolled_back);
+                } else {
+                    info!("Rolled back incomplete transaction {}: {} records tombstoned", transaction_id, rolled_back);
+                }
+            }
+        }
+        if !pending.is_empty() && !read_only {
+            wal.clear()?;
+        }
+
+        let account_index = load_or_rebuild_index(&dir.join(ACCOUNT_IDX_FILE), &dir.join(ACCOUNT_BIN_FILE), &accounts_list, |account| generate_deterministic_uuid(&account.id), !read_only)?;
+        let transaction_index = load_or_rebuild_index(&dir.join(TRANSACTION_IDX_FILE), &dir.join(TRANSACTION_BIN_FILE), &transactions_list, |tx| generate_deterministic_uuid(&tx.id), !read_only)?;
+        let entry_index = load_or_rebuild_index(&dir.join(ENTRY_IDX_FILE), &dir.join(ENTRY_BIN_FILE), &entries_list, |entry| entry.id, !read_only)?;
+        let system_index = load_or_rebuild_index(&dir.join(SYSTEM_IDX_FILE), &dir.join(SYSTEM_BIN_FILE), &systems_list, |system| generate_deterministic_uuid(&system.id), !read_only)?;
+        let transaction_timestamp_index = load_or_rebuild_timestamp_index(&dir.join(TRANSACTION_TIMESTAMP_IDX_FILE), &transaction_timestamp_keys(&transactions_list), !read_only)?;
+        let entry_timestamp_index = load_or_rebuild_timestamp_index(&dir.join(ENTRY_TIMESTAMP_IDX_FILE), &entry_timestamp_keys(&entries_list, &transactions_list), !read_only)?;
+        let conversion_graph_index = load_or_rebuild_index(&dir.join(CONVERSION_GRAPH_IDX_FILE), &dir.join(CONVERSION_GRAPH_BIN_FILE), &conversion_graphs_list, |graph| generate_deterministic_uuid(&graph.graph), !read_only)?;
+
+        let accounts: HashMap<Uuid, Account> = accounts_list.into_iter().map(|(_, account)| {
+            let uuid = generate_deterministic_uuid(&account.id);
+            (uuid, account)
+        }).collect();
+        let transactions: HashMap<Uuid, Transaction> = transactions_list.into_iter().map(|(_, transaction)| (transaction.id, transaction)).collect();
+        let entries: Vec<Entry> = entries_list.into_iter().map(|(_, entry)| entry).collect();
+        let systems: HashMap<Uuid, System> = systems_list.into_iter().map(|(_, system)| {
             let uuid = generate_deterministic_uuid(&system.id);
             (uuid, system)
         }).collect();
-        let conversion_graphs: HashMap<Uuid, ConversionGraph> = conversion_graphs_list.into_iter().map(|graph| {
+        let conversion_graphs: HashMap<Uuid, ConversionGraph> = conversion_graphs_list.into_iter().map(|(_, graph)| {
             let uuid = generate_deterministic_uuid(&graph.graph);
             (uuid, graph)
         }).collect();
 
+        let account_name_index = accounts.iter().map(|(uuid, account)| (account.name.clone(), *uuid)).collect();
+        let account_systems = accounts.iter().map(|(uuid, account)| (account.system_id.as_str(), *uuid));
+        let account_system_index = load_or_rebuild_secondary_index(&dir.join(ACCOUNT_SYSTEM_IDX_FILE), account_systems, !read_only)?;
+
+        let transaction_idempotency_keys = transactions.values().filter_map(|tx| Some((idempotency_key(tx)?, tx.id)));
+        let transaction_idempotency_index = load_or_rebuild_secondary_index(&dir.join(TRANSACTION_IDEMPOTENCY_IDX_FILE), transaction_idempotency_keys, !read_only)?;
+
         // ---------------------------------------------------------------------------------
 
 
         let duration = start.elapsed();
-        println!("Completed loading ledger data. Took: {:?}", duration);
+        debug!("Completed loading ledger data. Took: {:?}", duration);
 
         Ok(Self {
-            storage: storage,
+            data_dir: dir.to_path_buf(),
+            storage,
+            wal,
 
             accounts,
             transactions,
             systems,
             conversion_graphs,
-            entries: entries_list,
+            entries,
+
+            account_index,
+            transaction_index,
+            entry_index,
+            system_index,
+            conversion_graph_index,
+            transaction_timestamp_index,
+            entry_timestamp_index,
 
-            account_index: BTreeIndex::load(*ACCOUNT_IDX_PATH)?,
-            transaction_index: BTreeIndex::load(*TRANSACTION_IDX_PATH)?,
-            entry_index: BTreeIndex::load(*ENTRY_IDX_PATH)?,
-            system_index: BTreeIndex::load(*SYSTEM_IDX_PATH)?,
-            conversion_graph_index: BTreeIndex::load(*CONVERSION_GRAPH_IDX_PATH)?,
+            account_system_index,
+            account_name_index,
+            transaction_idempotency_index,
+
+            metadata_validator: None,
+            amount_scale: DEFAULT_AMOUNT_SCALE,
+            balance_tolerance: DEFAULT_BALANCE_TOLERANCE,
+            merge_same_account_legs: false,
         })
     }
 
-    pub fn persist_indexes(&self) -> std::io::Result<()> {
-        self.account_index.persist(*ACCOUNT_IDX_PATH)?;
-        self.transaction_index.persist(*TRANSACTION_IDX_PATH)?;
-        self.entry_index.persist(*ENTRY_IDX_PATH)?;
-        self.system_index.persist(*SYSTEM_IDX_PATH)?;
-        self.conversion_graph_index.persist(*CONVERSION_GRAPH_IDX_PATH)?;
+    /// Deletes every record, index, `.jsonl` and write-ahead log file in `dir`, installs a fresh
+    /// database there like [`crate::install_at`] does in an empty directory, and loads it. The
+    /// files are removed rather than truncated, so a ledger still open on `dir` only writes into
+    /// the removed ones; replace it with the ledger returned.
+    pub fn reset(dir: &Path) -> Result<Self, ZentryError> {
+        for file in BIN_FILES.iter().chain(IDX_FILES.iter()).chain(JSONL_FILES.iter()).chain([WAL_FILE].iter()) {
+            remove_if_exists(&dir.join(file))?;
+        }
+        crate::install_at(dir)?;
+
+        Self::load_from_dir(dir)
+    }
+
+    /// Has `record_transaction` reject transactions whose metadata `validator` returns an error
+    /// for, e.g. to require fields an application relies on. A transaction without metadata is
+    /// validated as `null`. Replaces any validator set before; `None` turns validation off.
+    pub fn set_metadata_validator(&mut self, validator: Option<MetadataValidator>) {
+        self.metadata_validator = validator;
+    }
+
+    /// Rounds entry amounts to `scale` decimal places from the next `record_transaction` on.
+    /// Entries already recorded keep the amounts they were stored with.
+    pub fn set_amount_scale(&mut self, scale: u32) {
+        self.amount_scale = scale;
+    }
+
+    /// Has `record_transaction` accept transactions whose legs sum to at most `tolerance` away
+    /// from zero, overall and within each system, from the next call on.
+    pub fn set_balance_tolerance(&mut self, tolerance: f64) {
+        self.balance_tolerance = tolerance.abs();
+    }
+
+    /// Has `record_transaction` store the legs a transaction posts to the same account as a single
+    /// leg with their net amount, keeping the id and position of the first. Off by default, when
+    /// every leg is stored as given.
+    pub fn set_merge_same_account_legs(&mut self, merge: bool) {
+        self.merge_same_account_legs = merge;
+    }
+
+    /// Syncs every record file to disk. Call before `persist_indexes` so the indexes never point
+    /// past data that was actually written.
+    pub fn flush(&self) -> Result<(), ZentryError> {
+        self.storage.flush()
+    }
+
+    pub fn persist_indexes(&self) -> Result<(), ZentryError> {
+        if self.storage.is_read_only() {
+            return Err(ZentryError::ReadOnly);
+        }
+
+        self.persist_indexes_to(&self.data_dir)
+    }
+
+    /// Writes every index into `dir`, whether or not the ledger lives there
+    fn persist_indexes_to(&self, dir: &Path) -> Result<(), ZentryError> {
+        self.account_index.persist(&dir.join(ACCOUNT_IDX_FILE))?;
+        self.transaction_index.persist(&dir.join(TRANSACTION_IDX_FILE))?;
+        self.entry_index.persist(&dir.join(ENTRY_IDX_FILE))?;
+        self.system_index.persist(&dir.join(SYSTEM_IDX_FILE))?;
+        self.conversion_graph_index.persist(&dir.join(CONVERSION_GRAPH_IDX_FILE))?;
+        self.transaction_timestamp_index.persist(&dir.join(TRANSACTION_TIMESTAMP_IDX_FILE))?;
+        self.entry_timestamp_index.persist(&dir.join(ENTRY_TIMESTAMP_IDX_FILE))?;
+        self.account_system_index.persist(&dir.join(ACCOUNT_SYSTEM_IDX_FILE))?;
+        self.transaction_idempotency_index.persist(&dir.join(TRANSACTION_IDEMPOTENCY_IDX_FILE))?;
+        Ok(())
+    }
+
+    /// Copies the ledger into a new directory under `dir` named after the current time, e.g.
+    /// `20261015T093000.123456Z`, and returns its path. The record files are flushed before they
+    /// are copied and the indexes are written from memory next to them, so the two always agree,
+    /// even for a read-only ledger whose own `.idx` files are stale. See [`Ledger::restore`].
+    pub fn snapshot(&self, dir: &Path) -> Result<PathBuf, ZentryError> {
+        self.storage.flush()?;
+
+        let snapshot_dir = dir.join(Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string());
+        std::fs::create_dir_all(dir)?;
+        std::fs::create_dir(&snapshot_dir)?;
+
+        for file in BIN_FILES {
+            std::fs::copy(self.data_dir.join(file), snapshot_dir.join(file))?;
+        }
+        self.persist_indexes_to(&snapshot_dir)?;
+
+        Ok(snapshot_dir)
+    }
+
+    /// Replaces the ledger's files with the ones in `snapshot`, a directory made by
+    /// [`Ledger::snapshot`], and reloads from them. Every file is copied next to the one it
+    /// replaces before any is renamed over, so a failed copy leaves the ledger as it was.
+    /// Indexes missing from the snapshot are removed and rebuilt on the reload.
+    pub fn restore(&mut self, snapshot: &Path) -> Result<(), ZentryError> {
+        if self.storage.is_read_only() {
+            return Err(ZentryError::ReadOnly);
+        }
+        if let Some(file) = BIN_FILES.iter().find(|file| !snapshot.join(file).is_file()) {
+            return Err(ZentryError::NotFound(format!("{} missing from snapshot {}", file, snapshot.display())));
+        }
+
+        self.storage.flush()?;
+
+        let mut staged = Vec::new();
+        for file in BIN_FILES.iter().chain(IDX_FILES.iter()) {
+            let source = snapshot.join(file);
+            if source.is_file() {
+                let restoring = self.data_dir.join(format!("{}.restore", file));
+                std::fs::copy(&source, &restoring)?;
+                staged.push((restoring, self.data_dir.join(file)));
+            } else {
+                remove_if_exists(&self.data_dir.join(file))?;
+            }
+        }
+        for (restoring, target) in staged {
+            std::fs::rename(restoring, target)?;
+        }
+
+        let mut restored = Self::load_from_dir(&self.data_dir)?;
+        restored.metadata_validator = self.metadata_validator;
+        restored.amount_scale = self.amount_scale;
+        restored.balance_tolerance = self.balance_tolerance;
+        restored.merge_same_account_legs = self.merge_same_account_legs;
+        *self = restored;
+
         Ok(())
     }
 
-    pub fn create_account(&mut self, account: Account) -> std::io::Result<()> {
+    /// Reads the accounts straight from `accounts.bin`, one at a time and in file order, instead
+    /// of from the `accounts` map. See [`RecordCursor`] for what is skipped.
+    pub fn iter_accounts(&self) -> Result<impl Iterator<Item = Result<Account, ZentryError>> + '_, ZentryError> {
+        self.iter_records::<Account>(ACCOUNT_BIN_FILE)
+    }
+
+    /// Like [`Ledger::iter_accounts`], for `transactions.bin`
+    pub fn iter_transactions(&self) -> Result<impl Iterator<Item = Result<Transaction, ZentryError>> + '_, ZentryError> {
+        self.iter_records::<Transaction>(TRANSACTION_BIN_FILE)
+    }
+
+    /// Like [`Ledger::iter_accounts`], for `entries.bin`
+    pub fn iter_entries(&self) -> Result<impl Iterator<Item = Result<Entry, ZentryError>> + '_, ZentryError> {
+        self.iter_records::<Entry>(ENTRY_BIN_FILE)
+    }
+
+    /// Like [`Ledger::iter_accounts`], for `systems.bin`
+    pub fn iter_systems(&self) -> Result<impl Iterator<Item = Result<System, ZentryError>> + '_, ZentryError> {
+        self.iter_records::<System>(SYSTEM_BIN_FILE)
+    }
+
+    /// Like [`Ledger::iter_accounts`], for `conversion_graphs.bin`, archived graphs included
+    pub fn iter_conversion_graphs(&self) -> Result<impl Iterator<Item = Result<ConversionGraph, ZentryError>> + '_, ZentryError> {
+        self.iter_records::<ConversionGraph>(CONVERSION_GRAPH_BIN_FILE)
+    }
+
+    fn iter_records<T: FromBinary + 'static>(&self, bin_file: &str) -> Result<impl Iterator<Item = Result<T, ZentryError>> + '_, ZentryError> {
+        let (layout, data_offset) = self.storage.file_layout::<T>()?;
+        let cursor = RecordCursor::<T>::open(&self.data_dir.join(bin_file), layout, data_offset)?;
+        Ok(cursor.map(|record| record.map(|(_, record)| record)))
+    }
+
+    /// Record counts, index lengths and file sizes for a quick look at the ledger's health.
+    /// Sizes are of the files as last flushed to disk, tombstones are counted by scanning each
+    /// `.bin` file.
+    pub fn stats(&self) -> Result<LedgerStats, ZentryError> {
+        let count = |class: GraphKeyClass| self.conversion_graphs.values().filter(|graph| classify_graph_key(graph) == class).count();
+
+        Ok(LedgerStats {
+            accounts: self.accounts.len(),
+            transactions: self.transactions.len(),
+            entries: self.entries.len(),
+            systems: self.systems.len(),
+            active_conversion_graphs: count(GraphKeyClass::Active),
+            historical_conversion_graphs: count(GraphKeyClass::Historical),
+            files: vec![
+                self.record_file_stats::<Account>(ACCOUNT_BIN_FILE, ACCOUNT_IDX_FILE, self.account_index.len())?,
+                self.record_file_stats::<Transaction>(TRANSACTION_BIN_FILE, TRANSACTION_IDX_FILE, self.transaction_index.len())?,
+                self.record_file_stats::<Entry>(ENTRY_BIN_FILE, ENTRY_IDX_FILE, self.entry_index.len())?,
+                self.record_file_stats::<System>(SYSTEM_BIN_FILE, SYSTEM_IDX_FILE, self.system_index.len())?,
+                self.record_file_stats::<ConversionGraph>(CONVERSION_GRAPH_BIN_FILE, CONVERSION_GRAPH_IDX_FILE, self.conversion_graph_index.len())?,
+            ],
+        })
+    }
+
+    fn record_file_stats<T: BinaryRecord>(&self, bin_file: &'static str, idx_file: &'static str, index_len: usize) -> Result<RecordFileStats, ZentryError> {
+        let bin_path = self.data_dir.join(bin_file);
+        let (layout, data_offset) = self.storage.file_layout::<T>()?;
+
+        // an index is only written by `persist_indexes`, until then it may not exist
+        let idx_bytes = match std::fs::metadata(self.data_dir.join(idx_file)) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(RecordFileStats {
+            file: bin_file,
+            bin_bytes: std::fs::metadata(&bin_path)?.len(),
+            tombstones: count_tombstones(&bin_path, layout, data_offset)?,
+            idx_bytes,
+            index_len,
+        })
+    }
+
+    /// Layout version each record file declares in its header, next to the version this build
+    /// writes new files with. Tells whether data written by another build can be read by this one.
+    pub fn format_versions(&self) -> Result<Vec<FormatVersion>, ZentryError> {
+        Ok(vec![
+            self.format_version::<Account>(ACCOUNT_BIN_FILE, ACCOUNT_LAYOUT_VERSION)?,
+            self.format_version::<Transaction>(TRANSACTION_BIN_FILE, TRANSACTION_LAYOUT_VERSION)?,
+            self.format_version::<Entry>(ENTRY_BIN_FILE, ENTRY_LAYOUT_VERSION)?,
+            self.format_version::<System>(SYSTEM_BIN_FILE, SYSTEM_LAYOUT_VERSION)?,
+            self.format_version::<ConversionGraph>(CONVERSION_GRAPH_BIN_FILE, CONVERSION_GRAPH_LAYOUT_VERSION)?,
+        ])
+    }
+
+    fn format_version<T: BinaryRecord>(&self, bin_file: &'static str, current: u8) -> Result<FormatVersion, ZentryError> {
+        let (layout, data_offset) = self.storage.file_layout::<T>()?;
+
+        Ok(FormatVersion {
+            file: bin_file,
+            // records of a legacy file start right at offset 0, there is no header to read
+            on_disk: (data_offset > 0).then_some(layout.version),
+            current,
+        })
+    }
+
+    /// Reclaims the space held by tombstoned records by rewriting every `.bin` file with only its
+    /// live records, then rebuilds and persists each index against the new offsets.
+    pub fn compact(&mut self) -> Result<(), ZentryError> {
+        let accounts = self.storage.compact::<Account>(&self.data_dir.join(ACCOUNT_BIN_FILE))?;
+        self.account_index = index_from_records(&accounts, |account| generate_deterministic_uuid(&account.id));
+
+        let transactions = self.storage.compact::<Transaction>(&self.data_dir.join(TRANSACTION_BIN_FILE))?;
+        self.transaction_index = index_from_records(&transactions, |tx| generate_deterministic_uuid(&tx.id));
+        self.transaction_timestamp_index = timestamp_index_from_keys(&transaction_timestamp_keys(&transactions));
+
+        let entries = self.storage.compact::<Entry>(&self.data_dir.join(ENTRY_BIN_FILE))?;
+        self.entry_index = index_from_records(&entries, |entry| entry.id);
+        self.entry_timestamp_index = timestamp_index_from_keys(&entry_timestamp_keys(&entries, &transactions));
+
+        let systems = self.storage.compact::<System>(&self.data_dir.join(SYSTEM_BIN_FILE))?;
+        self.system_index = index_from_records(&systems, |system| generate_deterministic_uuid(&system.id));
+
+        let conversion_graphs = self.storage.compact::<ConversionGraph>(&self.data_dir.join(CONVERSION_GRAPH_BIN_FILE))?;
+        self.conversion_graph_index = index_from_records(&conversion_graphs, |graph| generate_deterministic_uuid(&graph.graph));
+
+        self.persist_indexes()
+    }
+
+    /// Regenerates every offset index by scanning its `.bin` file from the start and persists them,
+    /// for recovering from lost or corrupted `.idx` files without restarting.
+    pub fn rebuild_indexes(&mut self) -> Result<(), ZentryError> {
+        self.storage.flush()?;
+
+        let accounts = self.storage.read_with_offsets::<Account>()?;
+        self.account_index = index_from_records(&accounts, |account| generate_deterministic_uuid(&account.id));
+
+        let transactions = self.storage.read_with_offsets::<Transaction>()?;
+        self.transaction_index = index_from_records(&transactions, |tx| generate_deterministic_uuid(&tx.id));
+        self.transaction_timestamp_index = timestamp_index_from_keys(&transaction_timestamp_keys(&transactions));
+
+        let entries = self.storage.read_with_offsets::<Entry>()?;
+        self.entry_index = index_from_records(&entries, |entry| entry.id);
+        self.entry_timestamp_index = timestamp_index_from_keys(&entry_timestamp_keys(&entries, &transactions));
+
+        let systems = self.storage.read_with_offsets::<System>()?;
+        self.system_index = index_from_records(&systems, |system| generate_deterministic_uuid(&system.id));
+
+        let conversion_graphs = self.storage.read_with_offsets::<ConversionGraph>()?;
+        self.conversion_graph_index = index_from_records(&conversion_graphs, |graph| generate_deterministic_uuid(&graph.graph));
+
+        self.persist_indexes()
+    }
+
+    /// Rejects an account whose id or name is already taken, see `account_name_index`, or whose
+    /// parent doesn't exist. Ids from [`Account::deterministic`] repeat for the same system and
+    /// name, so creating such an account twice leaves a single record.
+    pub fn create_account(&mut self, account: Account) -> Result<(), ZentryError> {
+        if self.find_account(account.id).is_some() {
+            return Err(ZentryError::InvalidData(format!("account already exists: {}", account.id)));
+        }
+        if self.account_name_index.contains_key(&account.name) {
+            return Err(ZentryError::InvalidData(format!("account name already exists: {}", account.name)));
+        }
+        if let Some(parent_id) = account.parent_id {
+            if self.find_account(parent_id).is_none() {
+                return Err(ZentryError::NotFound(format!("parent account not found: {}", parent_id)));
+            }
+        }
+
         let (offset, account) = self.storage.write(account)?;
 
         let uuid = generate_deterministic_uuid(&account.id);
+        self.account_system_index.insert(&account.system_id, uuid);
+        self.account_name_index.insert(account.name.clone(), uuid);
         self.accounts.insert(uuid, account);
         self.account_index.insert(uuid, offset);
 
         Ok(())
     }
 
-    pub fn create_system(&mut self, system: System) -> std::io::Result<()> {
+    /// Creates one account per row of a `name,account_type,system_id` CSV file and returns how many
+    /// were created. A header row is skipped if present. Every row is validated before any account
+    /// is written, so a bad row leaves the ledger untouched. Errors name the 1-based line number.
+    pub fn import_accounts_csv(&mut self, path: &Path) -> Result<usize, ZentryError> {
+        let content = std::fs::read_to_string(path)?;
+
+        let mut accounts = Vec::new();
+        let mut names = std::collections::HashSet::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let columns: Vec<&str> = line.split(',').map(str::trim).collect();
+
+            if line.trim().is_empty() || (line_number == 1 && columns == ["name", "account_type", "system_id"]) {
+                continue;
+            }
+
+            let [name, account_type, system_id] = columns[..] else {
+                return Err(ZentryError::InvalidData(format!(
+                    "line {}: expected 3 columns (name,account_type,system_id), found {}", line_number, columns.len()
+                )));
+            };
+
+            let account_type = account_type_from_label(account_type).ok_or_else(|| {
+                ZentryError::InvalidData(format!("line {}: unknown account type: {}", line_number, account_type))
+            })?;
+            if !self.systems.contains_key(&generate_deterministic_uuid(&system_id)) {
+                return Err(ZentryError::NotFound(format!("line {}: system not found: {}", line_number, system_id)));
+            }
+            if self.account_name_index.contains_key(name) || !names.insert(name) {
+                return Err(ZentryError::InvalidData(format!("line {}: account name already exists: {}", line_number, name)));
+            }
+
+            accounts.push(Account::deterministic(system_id, name, account_type, Utc::now()));
+        }
+
+        let imported = accounts.len();
+        for account in accounts {
+            self.create_account(account)?;
+        }
+
+        Ok(imported)
+    }
+
+    /// Creates one system per line of `path` and returns how many were created along with the ids
+    /// skipped as duplicates. Lines are either `id,description` (a `id,description` header is
+    /// skipped) or a JSON `System` object, and both may be mixed. Ids that already exist, or that
+    /// appear earlier in the file, are skipped rather than failing the import. Every line is
+    /// validated before any system is written, so a malformed line leaves the ledger untouched.
+    /// Errors name the 1-based line number.
+    pub fn import_systems(&mut self, path: &Path) -> Result<(usize, Vec<String>), ZentryError> {
+        let content = std::fs::read_to_string(path)?;
+
+        let mut systems = Vec::new();
+        let mut skipped = Vec::new();
+        let mut ids = HashSet::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+
+            if line.is_empty() || (line_number == 1 && line.replace(' ', "") == "id,description") {
+                continue;
+            }
+
+            let system = if line.starts_with('{') {
+                serde_json::from_str::<System>(line).map_err(|e| {
+                    ZentryError::InvalidData(format!("line {}: invalid system JSON: {}", line_number, e))
+                })?
+            } else {
+                let Some((id, description)) = line.split_once(',') else {
+                    return Err(ZentryError::InvalidData(format!(
+                        "line {}: expected `id,description` or a JSON system", line_number
+                    )));
+                };
+                System { id: id.trim().to_string(), description: description.trim().to_string(), symbol: None, decimal_places: None }
+            };
+
+            // checked up front too, so a bad line fails the import before any system is created
+            if !System::is_valid_id(&system.id) {
+                return Err(ZentryError::InvalidData(format!(
+                    "line {}: system id must be non-empty without whitespace, `<` or `>`: {:?}", line_number, system.id
+                )));
+            }
+
+            if self.systems.contains_key(&generate_deterministic_uuid(&system.id)) || !ids.insert(system.id.clone()) {
+                skipped.push(system.id);
+                continue;
+            }
+            systems.push(system);
+        }
+
+        let created = systems.len();
+        for system in systems {
+            self.create_system(system)?;
+        }
+
+        Ok((created, skipped))
+    }
+
+    /// Moves the records of the `.jsonl` files in the data directory, one JSON record per line, into
+    /// the binary store. Every record goes through the same path a new one would, so it is
+    /// validated and indexed: systems, then accounts, conversion graphs, and transactions with the
+    /// entries of `entries.jsonl` attached by `transaction_id`. Records already in the store are
+    /// counted as duplicates, so running it twice is harmless. Rows that don't parse or are
+    /// rejected are skipped and listed in the report; a missing file counts as empty.
+    pub fn migrate_from_jsonl(&mut self) -> Result<MigrationReport, ZentryError> {
+        let mut report = MigrationReport::default();
+
+        for (line, system) in read_jsonl::<System>(&self.data_dir.join(SYSTEM_JSONL_FILE), &mut report.skipped)? {
+            if self.systems.contains_key(&generate_deterministic_uuid(&system.id)) {
+                report.systems.duplicates += 1;
+                continue;
+            }
+            match self.create_system(system) {
+                Ok(()) => report.systems.migrated += 1,
+                Err(e) => report.skip(SYSTEM_JSONL_FILE, line, e),
+            }
+        }
+
+        // a child can come before its parent in the file, so accounts are retried until a pass
+        // makes no progress
+        let mut pending = read_jsonl::<Account>(&self.data_dir.join(ACCOUNT_JSONL_FILE), &mut report.skipped)?;
+        loop {
+            let mut failed = Vec::new();
+            let before = pending.len();
+
+            for (line, account) in pending {
+                if self.find_account(account.id).is_some() {
+                    report.accounts.duplicates += 1;
+                    continue;
+                }
+                // `create_account` leaves the system to its callers, like `import_accounts_csv` checks it
+                if self.find_system(&account.system_id).is_none() {
+                    let e = ZentryError::NotFound(format!("system not found: {}", account.system_id));
+                    failed.push((line, account, e));
+                    continue;
+                }
+                match self.create_account(account.clone()) {
+                    Ok(()) => report.accounts.migrated += 1,
+                    Err(e) => failed.push((line, account, e)),
+                }
+            }
+
+            if failed.is_empty() || failed.len() == before {
+                for (line, _, e) in failed {
+                    report.skip(ACCOUNT_JSONL_FILE, line, e);
+                }
+                break;
+            }
+            pending = failed.into_iter().map(|(line, account, _)| (line, account)).collect();
+        }
+
+        for (line, graph) in read_jsonl::<ConversionGraph>(&self.data_dir.join(CONVERSION_GRAPH_JSONL_FILE), &mut report.skipped)? {
+            let uuid = generate_deterministic_uuid(&graph.graph);
+            if self.conversion_graphs.get(&uuid).is_some_and(|existing| existing.rate_since == graph.rate_since) {
+                report.conversion_graphs.duplicates += 1;
+                continue;
+            }
+
+            // archived graphs keep their key, they are history rather than something to create
+            let migrated = if graph.is_historical() {
+                if self.conversion_graphs.contains_key(&uuid) {
+                    report.conversion_graphs.duplicates += 1;
+                    continue;
+                }
+                self.storage.write(graph).map(|(offset, graph)| {
+                    self.conversion_graphs.insert(uuid, graph);
+                    self.conversion_graph_index.insert(uuid, offset);
+                })
+            } else {
+                self.create_conversion_graph(graph)
+            };
+
+            match migrated {
+                Ok(()) => report.conversion_graphs.migrated += 1,
+                Err(e) => report.skip(CONVERSION_GRAPH_JSONL_FILE, line, e),
+            }
+        }
+
+        let mut entries_by_transaction: HashMap<Uuid, Vec<Entry>> = HashMap::new();
+        for (_, entry) in read_jsonl::<Entry>(&self.data_dir.join(ENTRY_JSONL_FILE), &mut report.skipped)? {
+            entries_by_transaction.entry(entry.transaction_id).or_default().push(entry);
+        }
+
+        for (line, tx) in read_jsonl::<Transaction>(&self.data_dir.join(TRANSACTION_JSONL_FILE), &mut report.skipped)? {
+            let entries = entries_by_transaction.remove(&tx.id).unwrap_or_default();
+            if self.transactions.contains_key(&tx.id) {
+                report.transactions.duplicates += 1;
+                continue;
+            }
+            match self.record_transaction(tx, entries) {
+                Ok(()) => report.transactions.migrated += 1,
+                Err(e) => report.skip(TRANSACTION_JSONL_FILE, line, e),
+            }
+        }
+
+        for (transaction_id, entries) in entries_by_transaction {
+            report.skipped.push(format!(
+                "{}: {} entries of transaction {}, which is not in {}",
+                ENTRY_JSONL_FILE, entries.len(), transaction_id, TRANSACTION_JSONL_FILE
+            ));
+        }
+        report.skipped.sort();
+
+        Ok(report)
+    }
+
+    /// Replaces the account with the given id by `new`, keeping `id` whatever `new.id` says.
+    /// The updated record is written before the old one is tombstoned, so a crash in between
+    /// leaves the account readable. The new name must be free, and an account with entries can't
+    /// move to another system.
+    pub fn update_account(&mut self, id: Uuid, mut new: Account) -> Result<(), ZentryError> {
+        let uuid = generate_deterministic_uuid(&id);
+        new.id = id;
+
+        let offset = self.account_index.get(&uuid)
+            .ok_or_else(|| ZentryError::NotFound(format!("account not found: {}", id)))?;
+        let old = self.storage.read_single::<Account>(offset)?;
+
+        if new.name != old.name && self.account_name_index.contains_key(&new.name) {
+            return Err(ZentryError::InvalidData(format!("account name already exists: {}", new.name)));
+        }
+        if let Some(parent_id) = new.parent_id {
+            if self.find_account(parent_id).is_none() {
+                return Err(ZentryError::NotFound(format!("parent account not found: {}", parent_id)));
+            }
+            if parent_id == id || self.account_subtree(id).iter().any(|account| account.id == parent_id) {
+                return Err(ZentryError::InvalidData(format!("account {} cannot be grouped under its own descendant {}", id, parent_id)));
+            }
+        }
+        if new.system_id != old.system_id && self.entries.iter().any(|entry| entry.account_id == id) {
+            return Err(ZentryError::InvalidData(format!("account {} has entries and cannot move to another system", id)));
+        }
+
+        let (new_offset, new) = self.storage.write(new)?;
+        self.storage.tombstone(old.clone(), offset)?;
+
+        self.account_system_index.remove(&old.system_id, &uuid);
+        self.account_system_index.insert(&new.system_id, uuid);
+        self.account_name_index.remove(&old.name);
+        self.account_name_index.insert(new.name.clone(), uuid);
+        self.account_index.insert(uuid, new_offset);
+        self.accounts.insert(uuid, new);
+
+        Ok(())
+    }
+
+    /// Tombstones the account with the given id and drops it from memory and the index.
+    /// Accounts that still have entries posted against them cannot be deleted.
+    pub fn delete_account(&mut self, id: Uuid) -> Result<(), ZentryError> {
+        let uuid = generate_deterministic_uuid(&id);
+
+        let offset = self.account_index.get(&uuid)
+            .ok_or_else(|| ZentryError::NotFound(format!("account not found: {}", id)))?;
+
+        if self.entries.iter().any(|entry| entry.account_id == id) {
+            return Err(ZentryError::InvalidData(format!("account {} still has entries and cannot be deleted", id)));
+        }
+
+        if self.accounts().any(|account| account.parent_id == Some(id)) {
+            return Err(ZentryError::InvalidData(format!("account {} still has child accounts and cannot be deleted", id)));
+        }
+
+        let account = self.storage.read_single::<Account>(offset)?;
+        self.storage.tombstone(account, offset)?;
+
+        if let Some(account) = self.accounts.remove(&uuid) {
+            self.account_system_index.remove(&account.system_id, &uuid);
+            self.account_name_index.remove(&account.name);
+        }
+        self.account_index.remove(&uuid);
+
+        Ok(())
+    }
+
+    /// Rejects an id [`System::is_valid_id`] doesn't allow, since no conversion graph could name it
+    pub fn create_system(&mut self, system: System) -> Result<(), ZentryError> {
+        if !System::is_valid_id(&system.id) {
+            return Err(ZentryError::InvalidData(format!(
+                "system id must be non-empty without whitespace, `<` or `>`: {:?}", system.id
+            )));
+        }
+        if self.systems.contains_key(&generate_deterministic_uuid(&system.id)) {
+            return Err(ZentryError::AlreadyExists(format!("system already exists: {}", system.id)));
+        }
+
         let (offset, system) = self.storage.write(system)?;
 
         let uuid = generate_deterministic_uuid(&system.id);
@@ -143,25 +905,45 @@ impl Ledger {
         Ok(())
     }
 
-    /// Archives an existing conversion graph by appending it to history with a time range key
-    fn archive_conversion_graph(&mut self, graph: &ConversionGraph, expired_at: DateTime<Utc>) -> std::io::Result<()> {
+    /// Key an active graph expiring at `expired_at` would be archived under
+    fn historical_key(&self, graph: &ConversionGraph, expired_at: DateTime<Utc>) -> String {
+        let window_key = format!("{}[{}]{}",
+            graph.rate_since.to_rfc3339(),
+            graph.graph,
+            expired_at.to_rfc3339()
+        );
+
+        // A relation deleted and recreated with an earlier `rate_since` can archive the same window
+        // twice. Later archives get a `#n` suffix instead of overwriting the earlier one.
+        let mut historical_key = window_key.clone();
+        let mut discriminator = 1;
+        while self.conversion_graphs.contains_key(&generate_deterministic_uuid(&historical_key)) {
+            historical_key = format!("{}#{}", window_key, discriminator);
+            discriminator += 1;
+        }
+
+        historical_key
+    }
+
+    /// Archives an existing conversion graph by appending it to history under `historical_key`,
+    /// see [`Ledger::historical_key`]
+    fn archive_conversion_graph(&mut self, graph: &ConversionGraph, historical_key: String) -> Result<(), ZentryError> {
         // Create historical version of the old graph
         let historical_graph = ConversionGraph {
-            graph: format!("{}[{}]{}", 
-                graph.rate_since.to_rfc3339(),
-                graph.graph,
-                expired_at.to_rfc3339()
-            ),
+            graph: historical_key,
             rate: graph.rate,
             rate_since: graph.rate_since,
+            exact_rate: graph.exact_rate,
         };
 
-        println!("Archiving conversion graph: {:?}", historical_graph);
+        debug!("Archiving conversion graph: {:?}", historical_graph);
 
-        // Get the old graph's offset from index and zero out old record if it exists
+        // Get the old graph's offset from index and zero out old record if it exists.
+        // The index entry goes with it, callers replacing the graph insert the new offset.
         let old_uuid = generate_deterministic_uuid(&graph.graph);
         if let Some(offset) = self.conversion_graph_index.get(&old_uuid) {
             self.storage.tombstone(graph.clone(), offset)?;
+            self.conversion_graph_index.remove(&old_uuid);
         }
 
         // Append historical version to storage
@@ -179,177 +961,1318 @@ impl Ledger {
     /// Accepts formats:
     /// - One-way: "USD -> IDR" or "USD <- IDR"
     /// - Two-way: "USD <-> SGD"
-    pub fn create_conversion_graph(&mut self, mut graph: ConversionGraph) -> std::io::Result<()> {
-        // Parse the graph string to get systems and direction
-        let parts: Vec<&str> = graph.graph.split_whitespace().collect();
-        if parts.len() != 3 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("invalid graph format: {}", graph.graph),
-            ));
+    ///
+    /// Applies what [`Ledger::plan_conversion_graph`] returns for the graph.
+    pub fn create_conversion_graph(&mut self, graph: ConversionGraph) -> Result<(), ZentryError> {
+        let plan = self.plan_conversion_graph(&graph)?;
+        self.apply_conversion_plan(plan)
+    }
+
+    /// Validates `graph` and works out what [`Ledger::create_conversion_graph`] would do with it:
+    /// which active graphs it replaces and the keys they would be archived under, and the graphs
+    /// that would become active, including the computed reverse rate for `<->`. Nothing is written.
+    pub fn plan_conversion_graph(&self, graph: &ConversionGraph) -> Result<ConversionPlan, ZentryError> {
+        let key = GraphKey::parse(&graph.graph)?;
+
+        // A self-loop converts nothing and only adds a cycle for path finding to walk into
+        if key.from == key.to {
+            return Err(ZentryError::InvalidData(format!("cannot convert system {} to itself", key.from)));
         }
 
-        let (from_system, direction, to_system) = (parts[0], parts[1], parts[2]);
+        // An exact rate is the rate, `rate` is recomputed from it so the two can't disagree
+        let rate = graph.exact_rate.map_or(graph.rate, |exact| exact.to_f64());
+
+        // A zero, negative or non-finite rate can't be inverted for `<->` and poisons every conversion through it
+        if !rate.is_finite() || rate <= 0.0 || graph.exact_rate.is_some_and(|exact| exact.numerator() == 0 || exact.denominator() == 0) {
+            return Err(ZentryError::InvalidData(format!("invalid rate: {}. must be a finite number greater than 0", rate)));
+        }
         
         // Validate both systems exist
-        let from_uuid = generate_deterministic_uuid(&from_system);
-        let to_uuid = generate_deterministic_uuid(&to_system);
-
-        if !self.systems.contains_key(&from_uuid) {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                format!("source system not found: {}", from_system),
-            ));
+        if !self.systems.contains_key(&generate_deterministic_uuid(&key.from)) {
+            return Err(ZentryError::NotFound(format!("source system not found: {}", key.from)));
         }
-        if !self.systems.contains_key(&to_uuid) {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                format!("target system not found: {}", to_system),
-            ));
+        if !self.systems.contains_key(&generate_deterministic_uuid(&key.to)) {
+            return Err(ZentryError::NotFound(format!("target system not found: {}", key.to)));
         }
 
-        let now = Utc::now();
+        // Every relation is stored as "A -> B"; a bidirectional graph becomes two records
+        let conversions = match key.direction {
+            GraphDirection::Both => vec![
+                (key.to_storage_key(), rate, graph.exact_rate),
+                (key.reversed().to_storage_key(), 1.0 / rate, graph.exact_rate.map(|exact| exact.reciprocal())),
+            ],
+            GraphDirection::Forward | GraphDirection::Backward => vec![(key.to_storage_key(), rate, graph.exact_rate)],
+        };
 
-        match direction {
-            "->" => {
-                // Check if conversion already exists
-                let graph_key = format!("{} -> {}", from_system, to_system);
-                let existing_uuid = generate_deterministic_uuid(&graph_key);
-                
-                // Clone existing graph if it exists, then archive it
-                if let Some(existing) = self.conversion_graphs.get(&existing_uuid).cloned() {
-                    self.archive_conversion_graph(&existing, now)?;
-                }
-                
-                // Create new conversion
-                graph.graph = graph_key;
-                graph.rate_since = now;
-                let uuid = generate_deterministic_uuid(&graph.graph);
-                let (offset, graph) = self.storage.write(graph)?;
-                self.conversion_graphs.entry(uuid).and_modify(|e| *e = graph.clone()).or_insert(graph);
-                self.conversion_graph_index.insert(uuid, offset);
-            }
-            "<-" => {
-                // Check if conversion already exists
-                let graph_key = format!("{} -> {}", to_system, from_system);
-                let existing_uuid = generate_deterministic_uuid(&graph_key);
-                
-                // Clone existing graph if it exists, then archive it
-                if let Some(existing) = self.conversion_graphs.get(&existing_uuid).cloned() {
-                    self.archive_conversion_graph(&existing, now)?;
-                }
-                
-                // Create new conversion
-                graph.graph = graph_key;
-                graph.rate_since = now;
-                let uuid = generate_deterministic_uuid(&graph.graph);
-                let (offset, graph) = self.storage.write(graph)?;
-                self.conversion_graphs.entry(uuid).and_modify(|e| *e = graph.clone()).or_insert(graph);
-                self.conversion_graph_index.insert(uuid, offset);
-            }
-            "<->" => {
-                // Check and archive both directions if they exist
-                let forward_key = format!("{} -> {}", from_system, to_system);
-                let reverse_key = format!("{} -> {}", to_system, from_system);
-                
-                let forward_uuid = generate_deterministic_uuid(&forward_key);
-                let reverse_uuid = generate_deterministic_uuid(&reverse_key);
-                
-                // Clone and archive existing conversions
-                if let Some(existing) = self.conversion_graphs.get(&forward_uuid).cloned() {
-                    self.archive_conversion_graph(&existing, now)?;
-                }
-                if let Some(existing) = self.conversion_graphs.get(&reverse_uuid).cloned() {
-                    self.archive_conversion_graph(&existing, now)?;
+        // Keep the archived history monotonic: a new rate may not start in the future
+        // or before the rate it replaces. Checked up front so nothing is archived on failure.
+        if graph.rate_since > Utc::now() {
+            return Err(ZentryError::InvalidData(format!("rate_since {} is in the future", graph.rate_since.to_rfc3339())));
+        }
+        for (graph_key, _, _) in &conversions {
+            if let Some(active) = self.conversion_graphs.get(&generate_deterministic_uuid(graph_key)) {
+                if graph.rate_since <= active.rate_since {
+                    return Err(ZentryError::InvalidData(format!(
+                        "rate_since {} must be after the active rate for {} (since {})",
+                        graph.rate_since.to_rfc3339(), graph_key, active.rate_since.to_rfc3339()
+                    )));
                 }
-
-                // Create new bidirectional conversions
-                let forward = ConversionGraph {
-                    graph: forward_key,
-                    rate: graph.rate,
-                    rate_since: graph.rate_since,
-                };
-                let uuid = generate_deterministic_uuid(&forward.graph);
-                let (offset, forward) = self.storage.write(forward)?;
-                self.conversion_graphs.entry(uuid).and_modify(|e| *e = forward.clone()).or_insert(forward);
-                self.conversion_graph_index.insert(uuid, offset);
-
-                let reverse = ConversionGraph {
-                    graph: reverse_key,
-                    rate: 1.0 / graph.rate,
-                    rate_since: graph.rate_since,
-                };
-                let uuid = generate_deterministic_uuid(&reverse.graph);
-                let (offset, reverse) = self.storage.write(reverse)?;
-                self.conversion_graphs.entry(uuid).and_modify(|e| *e = reverse.clone()).or_insert(reverse);
-                self.conversion_graph_index.insert(uuid, offset);
-            }
-            _ => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("invalid direction: {}. must be ->, <-, or <->", direction),
-                ));
             }
         }
 
-        Ok(())
-    }
+        let mut plan = ConversionPlan { archived: Vec::new(), created: Vec::new() };
+        for (graph_key, rate, exact_rate) in conversions {
+            let uuid = generate_deterministic_uuid(&graph_key);
 
-    pub fn record_transaction(&mut self, tx: Transaction, entries: Vec<Entry>) -> std::io::Result<()> {
-        let sum: f64 = entries.iter().map(|e| e.amount).sum();
-        if sum.abs() > f64::EPSILON {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("unbalanced transaction: total = {}", sum),
-            ));
+            // The replaced rate stays valid until the new one takes effect
+            if let Some(existing) = self.conversion_graphs.get(&uuid) {
+                let historical_key = self.historical_key(existing, graph.rate_since);
+                plan.archived.push(PlannedArchive {
+                    historical_uuid: generate_deterministic_uuid(&historical_key),
+                    historical_key,
+                    graph: existing.clone(),
+                });
+            }
+
+            plan.created.push(PlannedConversion {
+                uuid,
+                graph: ConversionGraph {
+                    graph: graph_key,
+                    rate,
+                    rate_since: graph.rate_since,
+                    exact_rate,
+                },
+            });
         }
 
-        let mut system_entries: HashMap<Uuid, Vec<&Entry>> = HashMap::new();
-        for entry in entries.iter() {
-            let acc_uuid = generate_deterministic_uuid(&entry.account_id);
+        Ok(plan)
+    }
+
+    fn apply_conversion_plan(&mut self, plan: ConversionPlan) -> Result<(), ZentryError> {
+        for archive in plan.archived {
+            self.archive_conversion_graph(&archive.graph, archive.historical_key)?;
+        }
+
+        for conversion in plan.created {
+            let (offset, graph) = self.storage.write(conversion.graph)?;
+            self.conversion_graphs.insert(conversion.uuid, graph);
+            self.conversion_graph_index.insert(conversion.uuid, offset);
+        }
+
+        Ok(())
+    }
+
+    /// Retires the active conversion between two systems without replacing it. The graph is
+    /// archived with the current time as its expiry, so rates before now still resolve through
+    /// [`Ledger::rate_at`]. Takes the same graph format as [`Ledger::create_conversion_graph`];
+    /// `<->` deletes both directions.
+    pub fn delete_conversion_graph(&mut self, graph: &str) -> Result<(), ZentryError> {
+        let key = GraphKey::parse(graph)?;
+
+        let graph_keys = match key.direction {
+            GraphDirection::Both => vec![key.to_storage_key(), key.reversed().to_storage_key()],
+            GraphDirection::Forward | GraphDirection::Backward => vec![key.to_storage_key()],
+        };
+
+        // Checked up front so a missing direction of `<->` leaves the other one in place
+        for graph_key in &graph_keys {
+            if !self.conversion_graphs.contains_key(&generate_deterministic_uuid(graph_key)) {
+                return Err(ZentryError::NotFound(format!("no active conversion graph: {}", graph_key)));
+            }
+        }
+
+        let expired_at = Utc::now();
+        for graph_key in graph_keys {
+            let uuid = generate_deterministic_uuid(&graph_key);
+
+            if let Some(existing) = self.conversion_graphs.get(&uuid).cloned() {
+                let historical_key = self.historical_key(&existing, expired_at);
+                self.archive_conversion_graph(&existing, historical_key)?;
+            }
+            self.conversion_graphs.remove(&uuid);
+        }
+
+        Ok(())
+    }
+
+    /// An entry posting `amount` to the account with the raw `Account::id` `account_id`, with a
+    /// fresh id. The transaction id is left nil for [`Ledger::record_transaction`] to fill in.
+    pub fn new_entry(&self, account_id: Uuid, amount: f64) -> Result<Entry, ZentryError> {
+        if self.find_account(account_id).is_none() {
+            return Err(ZentryError::NotFound(format!("account not found: {}", account_id)));
+        }
 
-            let account = self.accounts.get(&acc_uuid).ok_or_else(|| {
-                std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("account not found: {}", entry.account_id),
-                )
+        Ok(Entry {
+            id: Uuid::new_v4(),
+            transaction_id: Uuid::nil(),
+            account_id,
+            amount,
+        })
+    }
+
+    /// Entries with a nil `transaction_id`, e.g. from [`Ledger::new_entry`], are assigned to `tx`.
+    /// Two entries with the same id are rejected; two on the same account are kept apart unless
+    /// [`Ledger::set_merge_same_account_legs`] is on. A transaction carrying an idempotency key already used by another is rejected, see
+    /// [`Ledger::record_transaction_idempotent`], and so is one failing the metadata validator,
+    /// see [`Ledger::set_metadata_validator`].
+    pub fn record_transaction(&mut self, tx: Transaction, mut entries: Vec<Entry>) -> Result<(), ZentryError> {
+        if let Some(validator) = self.metadata_validator {
+            validator(tx.metadata.as_ref().unwrap_or(&serde_json::Value::Null)).map_err(|message| {
+                ZentryError::InvalidData(format!("invalid metadata for transaction {}: {}", tx.id, message))
+            })?;
+        }
+
+        if let Some(key) = idempotency_key(&tx) {
+            if let Some(existing) = self.transaction_idempotency_index.get(key).next() {
+                return Err(ZentryError::AlreadyExists(format!(
+                    "idempotency key {} is already used by transaction {}", key, existing
+                )));
+            }
+        }
+
+        for entry in entries.iter_mut() {
+            // rounded before the balance checks and before being stored, so what is checked is
+            // exactly what gets persisted
+            entry.amount = round_to_scale(entry.amount, self.amount_scale);
+
+            if entry.transaction_id.is_nil() {
+                entry.transaction_id = tx.id;
+            } else if entry.transaction_id != tx.id {
+                return Err(ZentryError::InvalidData(format!(
+                    "entry {} belongs to transaction {}, not {}",
+                    entry.id, entry.transaction_id, tx.id
+                )));
+            }
+        }
+
+        let mut entry_ids = HashSet::new();
+        if let Some(duplicate) = entries.iter().find(|entry| !entry_ids.insert(entry.id)) {
+            return Err(ZentryError::InvalidData(format!(
+                "entry {} appears more than once in transaction {}", duplicate.id, tx.id
+            )));
+        }
+
+        if self.merge_same_account_legs {
+            entries = merge_legs(entries, self.amount_scale);
+        }
+
+        // amounts at the scale can still sum to a few ulps off zero, which the tolerance absorbs.
+        // The sum is reported rounded, at the scale the amounts are in
+        let sum: f64 = entries.iter().map(|e| e.amount).sum();
+        if sum.abs() > self.balance_tolerance {
+            return Err(ZentryError::UnbalancedTransaction(round_to_scale(sum, self.amount_scale)));
+        }
+
+        // Each system has to net to zero on its own, amounts in different systems aren't comparable
+        let mut system_entries: HashMap<&str, Vec<&Entry>> = HashMap::new();
+        for entry in entries.iter() {
+            let account = self.find_account(entry.account_id).ok_or_else(|| {
+                ZentryError::NotFound(format!("account not found: {}", entry.account_id))
             })?;
 
-            let uuid = generate_deterministic_uuid(&account.id);
-            
             system_entries
-                .entry(uuid)
+                .entry(account.system_id.as_str())
                 .or_default()
                 .push(entry);
         }
 
         for (system_id, entries) in system_entries.iter() {
-            if !self.systems.contains_key(&system_id) {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("system not found: {}", system_id),
-                ));
+            if !self.systems.contains_key(&generate_deterministic_uuid(system_id)) {
+                return Err(ZentryError::NotFound(format!("system not found: {}", system_id)));
             }
 
             let system_sum: f64 = entries.iter().map(|e| e.amount).sum();
-            if system_sum.abs() > f64::EPSILON {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("unbalanced entries in system {}: sum = {}", system_id, system_sum),
-                ));
+            if system_sum.abs() > self.balance_tolerance {
+                return Err(ZentryError::UnbalancedSystem {
+                    system_id: system_id.to_string(),
+                    sum: round_to_scale(system_sum, self.amount_scale),
+                });
             }
         }
 
-        for entry in entries.iter() {
-            let (offset, entry) = self.storage.write(entry.clone())?;
+        // Nothing is applied in memory until the commit marker is logged. If a write fails
+        // the operation stays pending in the WAL and is rolled back on the next load.
+        self.wal.begin(tx.id, entries.iter().map(|entry| entry.id).collect())?;
+
+        let written = self.storage.write_batch(entries)?;
+        let (offset, tx) = self.storage.write(tx)?;
 
-            self.entry_index.insert(generate_deterministic_uuid(&entry.id), offset);
+        // the commit marker may only reach the disk after the records it covers
+        self.storage.flush()?;
+        self.wal.commit(tx.id)?;
+
+        for (offset, entry) in written {
+            self.entry_index.insert(entry.id, offset);
+            self.entry_timestamp_index.insert(tx.timestamp.timestamp_micros(), entry.id, offset);
             self.entries.push(entry);
         }
-
-        let (offset, tx) = self.storage.write(tx)?;
         self.transaction_index.insert(generate_deterministic_uuid(&tx.id), offset);
+        self.transaction_timestamp_index.insert(tx.timestamp.timestamp_micros(), tx.id, offset);
+        if let Some(key) = idempotency_key(&tx) {
+            self.transaction_idempotency_index.insert(key, tx.id);
+        }
         self.transactions.insert(tx.id, tx);
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Records the transaction under `key` unless a transaction was already recorded with it, in
+    /// which case nothing is written and that earlier transaction is returned. Lets a caller retry
+    /// a write whose outcome it didn't learn without posting it twice. The key is kept in the
+    /// transaction's metadata as `idempotency_key`, so `tx.metadata` has to be an object or `None`.
+    pub fn record_transaction_idempotent(&mut self, key: &str, mut tx: Transaction, entries: Vec<Entry>) -> Result<Transaction, ZentryError> {
+        if let Some(existing) = self.transaction_idempotency_index.get(key).next() {
+            return self.transactions.get(existing).cloned()
+                .ok_or_else(|| ZentryError::NotFound(format!("transaction not found: {}", existing)));
+        }
+
+        let metadata = tx.metadata.get_or_insert_with(|| serde_json::json!({}));
+        let Some(fields) = metadata.as_object_mut() else {
+            return Err(ZentryError::InvalidData(format!(
+                "metadata of transaction {} must be an object to hold an idempotency key", tx.id
+            )));
+        };
+        fields.insert(IDEMPOTENCY_KEY_FIELD.to_string(), serde_json::Value::String(key.to_string()));
+
+        self.record_transaction(tx.clone(), entries)?;
+        Ok(tx)
+    }
+
+    /// Corrects a recorded transaction by posting its opposite: a new transaction, timestamped now,
+    /// with every entry of the original negated and `{"reverses": "<original id>"}` as metadata.
+    /// The original stays as it is. A transaction can only be reversed once.
+    pub fn reverse_transaction(&mut self, transaction_id: Uuid) -> Result<Transaction, ZentryError> {
+        let original = self.transactions.get(&transaction_id)
+            .ok_or_else(|| ZentryError::NotFound(format!("transaction not found: {}", transaction_id)))?;
+
+        let reverses = serde_json::Value::String(transaction_id.to_string());
+        if let Some(reversal) = self.transactions.values().find(|tx| {
+            tx.metadata.as_ref().and_then(|metadata| metadata.get("reverses")) == Some(&reverses)
+        }) {
+            return Err(ZentryError::AlreadyExists(format!(
+                "transaction {} is already reversed by {}", transaction_id, reversal.id
+            )));
+        }
+
+        let reversal = Transaction {
+            id: Uuid::new_v4(),
+            description: format!("Reversal of {}", original.description),
+            timestamp: Utc::now(),
+            metadata: Some(serde_json::json!({ "reverses": reverses })),
+        };
+        let entries = self.entries.iter()
+            .filter(|entry| entry.transaction_id == transaction_id)
+            .map(|entry| Entry {
+                id: Uuid::new_v4(),
+                transaction_id: reversal.id,
+                account_id: entry.account_id,
+                amount: -entry.amount,
+            })
+            .collect();
+
+        self.record_transaction(reversal.clone(), entries)?;
+        Ok(reversal)
+    }
+
+    /// Closes the books at `as_of`: posts one transaction, timestamped `as_of`, that brings every
+    /// revenue and expense account's balance as of then to zero and moves the net into the equity
+    /// account with the raw `Account::id` `equity_account`, so net income shows up as a credit to it.
+    /// Only accounts in the equity account's system are closed, since each system has to balance
+    /// on its own; other systems are closed with an equity account of their own.
+    pub fn close_period(&mut self, as_of: DateTime<Utc>, equity_account: Uuid) -> Result<Transaction, ZentryError> {
+        let equity = self.find_account(equity_account)
+            .ok_or_else(|| ZentryError::NotFound(format!("account not found: {}", equity_account)))?;
+        if equity.account_type != AccountType::Equity {
+            return Err(ZentryError::InvalidData(format!(
+                "account {} is {}, closing entries go to an Equity account", equity.name, equity.account_type.label()
+            )));
+        }
+        if as_of > Utc::now() {
+            return Err(ZentryError::InvalidData(format!("as_of {} is in the future", as_of.to_rfc3339())));
+        }
+
+        let mut closed: Vec<&Account> = self.accounts.values()
+            .filter(|account| account.system_id == equity.system_id)
+            .filter(|account| matches!(account.account_type, AccountType::Revenue | AccountType::Expense))
+            .collect();
+        closed.sort_by_key(|account| account.id);
+
+        let closing = Transaction {
+            id: Uuid::new_v4(),
+            description: format!("Closing entries as of {}", as_of.to_rfc3339()),
+            timestamp: as_of,
+            metadata: Some(serde_json::json!({ "closes_period": as_of.to_rfc3339() })),
+        };
+
+        let mut entries: Vec<Entry> = closed.iter()
+            .map(|account| (account.id, round_to_scale(self.account_balance_as_of(account.id, as_of), self.amount_scale)))
+            .filter(|(_, balance)| *balance != 0.0)
+            .map(|(account_id, balance)| Entry {
+                id: Uuid::new_v4(),
+                transaction_id: closing.id,
+                account_id,
+                amount: -balance,
+            })
+            .collect();
+        if entries.is_empty() {
+            return Err(ZentryError::InvalidData(format!(
+                "no revenue or expense balance in system {} to close as of {}", equity.system_id, as_of.to_rfc3339()
+            )));
+        }
+
+        // summed in the same order `record_transaction` checks the balance in, so they cancel exactly
+        let closed_total = entries.iter().fold(0.0, |total, entry| total + entry.amount);
+        entries.push(Entry {
+            id: Uuid::new_v4(),
+            transaction_id: closing.id,
+            account_id: equity_account,
+            amount: -closed_total,
+        });
+
+        self.record_transaction(closing.clone(), entries)?;
+        Ok(closing)
+    }
+
+    /// Records the transaction like [`Ledger::record_transaction`], and also returns a warning for
+    /// every entry posted against its account's normal balance, e.g. a revenue account debited.
+    /// Such postings are legitimate (refunds, reversals), so they are reported, not rejected.
+    pub fn record_transaction_checked(&mut self, tx: Transaction, entries: Vec<Entry>) -> Result<Vec<String>, ZentryError> {
+        let warnings = entries.iter().filter_map(|entry| {
+            let account = self.find_account(entry.account_id)?;
+            // classified at the amount `record_transaction` stores, so one rounding to zero isn't flagged
+            let amount = round_to_scale(entry.amount, self.amount_scale);
+            let is_debit = amount > 0.0;
+            let is_credit = amount < 0.0;
+
+            let unusual = if account.account_type.is_debit_normal() { is_credit } else { is_debit };
+            unusual.then(|| format!(
+                "{} account {} ({}) is {} {}",
+                account.account_type.label(), account.name, account.id,
+                if is_debit { "debited" } else { "credited" },
+                amount.abs()
+            ))
+        }).collect();
+
+        self.record_transaction(tx, entries)?;
+        Ok(warnings)
+    }
+
+    /// Sums every entry into the account type of the account it is posted to.
+    /// Entries whose account no longer exists are left out.
+    pub fn trial_balance(&self) -> HashMap<AccountType, f64> {
+        let mut balances: HashMap<AccountType, f64> = HashMap::new();
+
+        for (account_id, balance) in self.all_balances() {
+            if let Some(account) = self.find_account(account_id) {
+                *balances.entry(account.account_type.clone()).or_default() += balance;
+            }
+        }
+
+        balances
+    }
+
+    /// Total revenue, total expense and net income over transactions with `start <= timestamp < end`.
+    /// Revenue is credit-normal, so it is negated to come out positive; net is revenue minus expense.
+    pub fn income_statement(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> (f64, f64, f64) {
+        let in_window: HashSet<Uuid> = self.transactions_between(start, end).into_iter().map(|tx| tx.id).collect();
+        let mut revenue = 0.0;
+        let mut expense = 0.0;
+
+        for entry in self.entries.iter().filter(|entry| in_window.contains(&entry.transaction_id)) {
+            match self.find_account(entry.account_id).map(|a| &a.account_type) {
+                Some(AccountType::Revenue) => revenue -= entry.amount,
+                Some(AccountType::Expense) => expense += entry.amount,
+                _ => {}
+            }
+        }
+
+        (revenue, expense, revenue - expense)
+    }
+
+    /// Like [`Ledger::trial_balance`], per `system_id`, since amounts of different systems can't be
+    /// added up. Systems whose accounts have no entries are left out.
+    pub fn trial_balance_by_system(&self) -> BTreeMap<String, HashMap<AccountType, f64>> {
+        let mut balances: BTreeMap<String, HashMap<AccountType, f64>> = BTreeMap::new();
+
+        for (account_id, balance) in self.all_balances() {
+            if let Some(account) = self.find_account(account_id) {
+                *balances.entry(account.system_id.clone()).or_default().entry(account.account_type.clone()).or_default() += balance;
+            }
+        }
+
+        balances
+    }
+
+    /// Like [`Ledger::income_statement`], per `system_id` of the revenue and expense accounts.
+    /// Systems without revenue or expense in the window are left out.
+    pub fn income_statement_by_system(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> BTreeMap<String, (f64, f64, f64)> {
+        let in_window: HashSet<Uuid> = self.transactions_between(start, end).into_iter().map(|tx| tx.id).collect();
+        let mut totals: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+
+        for entry in self.entries.iter().filter(|entry| in_window.contains(&entry.transaction_id)) {
+            let Some(account) = self.find_account(entry.account_id) else { continue };
+            match account.account_type {
+                AccountType::Revenue => totals.entry(account.system_id.clone()).or_default().0 -= entry.amount,
+                AccountType::Expense => totals.entry(account.system_id.clone()).or_default().1 += entry.amount,
+                _ => {}
+            }
+        }
+
+        totals.into_iter().map(|(system_id, (revenue, expense))| (system_id, (revenue, expense, revenue - expense))).collect()
+    }
+
+    /// Whether debits and credits across all entries cancel out.
+    pub fn is_balanced(&self) -> bool {
+        let total: f64 = self.entries.iter().map(|e| e.amount).sum();
+        round_to_scale(total, self.amount_scale) == 0.0
+    }
+
+    /// Every account, sorted by name. Unlike iterating `accounts` the order is the same on every call.
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        let mut accounts: Vec<&Account> = self.accounts.values().collect();
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+        accounts.into_iter()
+    }
+
+    /// Every system, sorted by `System::id`.
+    pub fn systems(&self) -> impl Iterator<Item = &System> {
+        let mut systems: Vec<&System> = self.systems.values().collect();
+        systems.sort_by(|a, b| a.id.cmp(&b.id));
+        systems.into_iter()
+    }
+
+    /// Every conversion graph, archived ones included, sorted by graph key.
+    pub fn conversion_graphs(&self) -> impl Iterator<Item = &ConversionGraph> {
+        let mut conversion_graphs: Vec<&ConversionGraph> = self.conversion_graphs.values().collect();
+        conversion_graphs.sort_by(|a, b| a.graph.cmp(&b.graph));
+        conversion_graphs.into_iter()
+    }
+
+    /// The conversion graphs in effect now, sorted by graph key. Archived graphs and keys that
+    /// parse as neither are left out; every view of the active rates goes through this.
+    pub fn active_conversion_graphs(&self) -> impl Iterator<Item = &ConversionGraph> {
+        self.conversion_graphs().filter(|graph| classify_graph_key(graph) == GraphKeyClass::Active)
+    }
+
+    /// Active conversion graphs with `system_id` on either side, sorted by graph key.
+    /// Archived graphs are left out.
+    pub fn conversions_for_system(&self, system_id: &str) -> Vec<&ConversionGraph> {
+        self.active_conversion_graphs()
+            .filter(|graph| match graph.graph.split_once(" -> ") {
+                Some((from, to)) => from == system_id || to == system_id,
+                None => false,
+            })
+            .collect()
+    }
+
+    /// Compares the active rates against each other and describes every disagreement larger than
+    /// `tolerance`, a relative deviation (0.01 is 1%): round trips `A -> B -> A` that don't come
+    /// back to 1, and direct rates `A -> C` that differ from going through `A -> B -> C`.
+    pub fn detect_rate_inconsistencies(&self, tolerance: f64) -> Vec<String> {
+        let rates: BTreeMap<(&str, &str), f64> = self.active_conversion_graphs()
+            .filter_map(|graph| graph.graph.split_once(" -> ").map(|(from, to)| ((from, to), graph.rate)))
+            .collect();
+
+        let mut problems = Vec::new();
+
+        for (&(a, b), &rate_ab) in rates.iter() {
+            // each pair is checked once, from its smaller side
+            if a < b {
+                if let Some(&rate_ba) = rates.get(&(b, a)) {
+                    let round_trip = rate_ab * rate_ba;
+                    if (round_trip - 1.0).abs() > tolerance {
+                        problems.push(format!(
+                            "{} -> {} -> {} round trip gives {} instead of 1 ({} * {})",
+                            a, b, a, round_trip, rate_ab, rate_ba
+                        ));
+                    }
+                }
+            }
+
+            for (&(_, c), &rate_bc) in rates.range((b, "")..).take_while(|((from, _), _)| *from == b) {
+                if c == a {
+                    continue;
+                }
+                if let Some(&rate_ac) = rates.get(&(a, c)) {
+                    let through_b = rate_ab * rate_bc;
+                    if (through_b / rate_ac - 1.0).abs() > tolerance {
+                        problems.push(format!(
+                            "{} -> {} is {} directly but {} through {}",
+                            a, c, rate_ac, through_b, b
+                        ));
+                    }
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Every account below `root` in the hierarchy, children before grandchildren and siblings
+    /// sorted by name. Takes the raw `Account::id`; `root` itself is not included.
+    pub fn account_subtree(&self, root: Uuid) -> Vec<&Account> {
+        let mut children: HashMap<Uuid, Vec<&Account>> = HashMap::new();
+        for account in self.accounts() {
+            if let Some(parent_id) = account.parent_id {
+                children.entry(parent_id).or_default().push(account);
+            }
+        }
+
+        let mut subtree = Vec::new();
+        let mut visited = std::collections::HashSet::from([root]);
+        let mut queue = std::collections::VecDeque::from([root]);
+
+        while let Some(id) = queue.pop_front() {
+            for child in children.get(&id).map(Vec::as_slice).unwrap_or_default() {
+                // a parent chain looping back on itself is cut at the first repeat
+                if visited.insert(child.id) {
+                    subtree.push(*child);
+                    queue.push_back(child.id);
+                }
+            }
+        }
+
+        subtree
+    }
+
+    pub fn accounts_in_system(&self, system_id: &str) -> Vec<&Account> {
+        self.account_system_index.get(system_id)
+            .filter_map(|uuid| self.accounts.get(uuid))
+            .collect()
+    }
+
+    /// Looks a system up by its `System::id`, e.g. `USD`
+    pub fn find_system(&self, system_id: &str) -> Option<&System> {
+        self.systems.get(&generate_deterministic_uuid(system_id))
+    }
+
+    /// Looks an account up by its raw `Account::id`, the id entries and every other `Ledger`
+    /// method take. `accounts` is keyed by `generate_deterministic_uuid` of that id instead,
+    /// which this derives so callers don't have to.
+    pub fn find_account(&self, account_id: Uuid) -> Option<&Account> {
+        self.accounts.get(&generate_deterministic_uuid(&account_id))
+    }
+
+    /// Looks an entry up by its `Entry::id` through `entry_index`, reading it from `entries.bin`.
+    /// `None` when no live entry has that id or its record can't be read.
+    pub fn find_entry(&self, id: Uuid) -> Option<Entry> {
+        let offset = self.entry_index.get(&id)?;
+        self.storage.read_single::<Entry>(offset).ok()
+    }
+
+    pub fn find_account_by_name(&self, name: &str) -> Option<&Account> {
+        self.account_name_index.get(name).and_then(|uuid| self.accounts.get(uuid))
+    }
+
+    /// [`Ledger::account_balance`] of every account with entries, keyed by raw `Account::id`, in a
+    /// single pass over `entries`. Prefer it to calling `account_balance` per account, which scans
+    /// every entry once for each account. Accounts without entries are left out.
+    pub fn all_balances(&self) -> HashMap<Uuid, f64> {
+        let mut balances: HashMap<Uuid, f64> = HashMap::new();
+
+        for entry in self.entries.iter() {
+            *balances.entry(entry.account_id).or_default() += entry.amount;
+        }
+
+        balances
+    }
+
+    /// Net of all entries posted to the account, debits positive and credits negative.
+    /// Takes the raw `Account::id`, as stored on each `Entry`.
+    pub fn account_balance(&self, account_id: Uuid) -> f64 {
+        self.entries.iter()
+            .filter(|entry| entry.account_id == account_id)
+            .fold(0.0, |balance, entry| balance + entry.amount)
+    }
+
+    /// Balance of the account from transactions timestamped at or before `at`, for point-in-time
+    /// statements. Takes the raw `Account::id`. Entries whose transaction is missing are left out.
+    pub fn account_balance_as_of(&self, account_id: Uuid, at: DateTime<Utc>) -> f64 {
+        self.entries.iter()
+            .filter(|entry| entry.account_id == account_id)
+            .filter(|entry| self.transactions.get(&entry.transaction_id).is_some_and(|tx| tx.timestamp <= at))
+            .fold(0.0, |balance, entry| balance + entry.amount)
+    }
+
+    /// Balance of the account together with every account below it in the hierarchy, the total a
+    /// parent account reports. Takes the raw `Account::id`. Cycles in the parent chain are cut by
+    /// [`Ledger::account_subtree`], so every account is counted at most once.
+    pub fn rolled_up_balance(&self, account_id: Uuid) -> f64 {
+        let mut ids: std::collections::HashSet<Uuid> = self.account_subtree(account_id).iter()
+            .map(|account| account.id)
+            .collect();
+        ids.insert(account_id);
+
+        self.entries.iter()
+            .filter(|entry| ids.contains(&entry.account_id))
+            .fold(0.0, |balance, entry| balance + entry.amount)
+    }
+
+    /// Checks that all entries net to zero, that every entry is posted to an existing account and
+    /// that every account belongs to an existing system. Returns one message per problem found.
+    pub fn verify_integrity(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        let total: f64 = self.entries.iter().map(|entry| entry.amount).sum();
+        if round_to_scale(total, self.amount_scale) != 0.0 {
+            problems.push(format!("ledger is unbalanced: entries total {}", total));
+        }
+
+        for entry in self.entries.iter() {
+            if self.find_account(entry.account_id).is_none() {
+                problems.push(format!("entry {} references missing account {}", entry.id, entry.account_id));
+            }
+        }
+
+        for account in self.accounts() {
+            if !self.systems.contains_key(&generate_deterministic_uuid(&account.system_id)) {
+                problems.push(format!("account {} ({}) references missing system {}", account.name, account.id, account.system_id));
+            }
+            if let Some(parent_id) = account.parent_id {
+                if self.find_account(parent_id).is_none() {
+                    problems.push(format!("account {} ({}) references missing parent account {}", account.name, account.id, parent_id));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Checks every primary index against its `.bin` file: each indexed offset has to hold a live
+    /// record whose key is the one it is indexed under, and each live record has to be indexed at
+    /// its offset. Returns one message per problem found, none for consistent indexes.
+    pub fn verify_indexes(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        self.verify_index::<Account>(ACCOUNT_BIN_FILE, &self.account_index, |account| generate_deterministic_uuid(&account.id), &mut problems);
+        self.verify_index::<Transaction>(TRANSACTION_BIN_FILE, &self.transaction_index, |tx| generate_deterministic_uuid(&tx.id), &mut problems);
+        self.verify_index::<Entry>(ENTRY_BIN_FILE, &self.entry_index, |entry| entry.id, &mut problems);
+        self.verify_index::<System>(SYSTEM_BIN_FILE, &self.system_index, |system| generate_deterministic_uuid(&system.id), &mut problems);
+        self.verify_index::<ConversionGraph>(CONVERSION_GRAPH_BIN_FILE, &self.conversion_graph_index, |graph| generate_deterministic_uuid(&graph.graph), &mut problems);
+
+        problems
+    }
+
+    fn verify_index<T>(&self, bin_file: &str, index: &BTreeIndex, key: impl Fn(&T) -> Uuid, problems: &mut Vec<String>)
+    where
+        T: FromBinary + Clone + Send + 'static,
+    {
+        for (uuid, offset) in index.iter() {
+            match self.storage.read_single::<T>(offset) {
+                Ok(record) if key(&record) == uuid => {}
+                Ok(record) => problems.push(format!(
+                    "{}: {} is indexed at offset {}, which holds {}", bin_file, uuid, offset, key(&record)
+                )),
+                Err(ZentryError::DeadRecord) => problems.push(format!(
+                    "{}: {} is indexed at offset {}, which holds a tombstoned record", bin_file, uuid, offset
+                )),
+                Err(e) => problems.push(format!(
+                    "{}: {} is indexed at offset {}, which holds no readable record: {}", bin_file, uuid, offset, e
+                )),
+            }
+        }
+
+        let records = self.storage.file_layout::<T>()
+            .and_then(|(layout, data_offset)| RecordCursor::<T>::open(&self.data_dir.join(bin_file), layout, data_offset));
+        let records = match records {
+            Ok(records) => records,
+            Err(e) => {
+                problems.push(format!("{}: could not be scanned: {}", bin_file, e));
+                return;
+            }
+        };
+
+        for record in records {
+            match record {
+                Ok((offset, record)) => {
+                    let uuid = key(&record);
+                    match index.get(&uuid) {
+                        Some(indexed) if indexed == offset => {}
+                        Some(indexed) => problems.push(format!(
+                            "{}: live record {} at offset {} is indexed at offset {}", bin_file, uuid, offset, indexed
+                        )),
+                        None => problems.push(format!(
+                            "{}: live record {} at offset {} is missing from the index", bin_file, uuid, offset
+                        )),
+                    }
+                }
+                Err(e) => problems.push(format!("{}: scan stopped: {}", bin_file, e)),
+            }
+        }
+    }
+
+    /// Writes every record in the ledger as one JSON object, one array per record type.
+    /// Records are sorted so exporting the same ledger twice gives the same output.
+    pub fn export_json(&self, writer: impl Write) -> Result<(), ZentryError> {
+        let mut accounts: Vec<&Account> = self.accounts.values().collect();
+        accounts.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)));
+
+        let mut transactions: Vec<&Transaction> = self.transactions.values().collect();
+        transactions.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+
+        let mut conversion_graphs: Vec<&ConversionGraph> = self.conversion_graphs.values().collect();
+        conversion_graphs.sort_by(|a, b| a.rate_since.cmp(&b.rate_since).then_with(|| a.graph.cmp(&b.graph)));
+
+        let export = LedgerExport {
+            accounts,
+            transactions,
+            entries: &self.entries,
+            systems: self.systems().collect(),
+            conversion_graphs,
+        };
+
+        serde_json::to_writer_pretty(writer, &export)?;
+        Ok(())
+    }
+
+    /// Transactions whose metadata is an object with `key` set to `value`, oldest first.
+    /// Transactions without metadata, or with metadata that isn't an object, never match.
+    pub fn find_transactions_by_metadata(&self, key: &str, value: &serde_json::Value) -> Vec<&Transaction> {
+        let mut transactions: Vec<&Transaction> = self.transactions.values()
+            .filter(|tx| tx.metadata.as_ref().and_then(|metadata| metadata.get(key)) == Some(value))
+            .collect();
+        transactions.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+        transactions
+    }
+
+    /// Transactions with at least one entry on the account, each once, oldest first.
+    /// Takes the raw `Account::id` as stored on each `Entry`, not the hashed key of `accounts`.
+    pub fn transactions_for_account(&self, account_id: Uuid) -> Vec<&Transaction> {
+        let transaction_ids: HashSet<Uuid> = self.entries.iter()
+            .filter(|entry| entry.account_id == account_id)
+            .map(|entry| entry.transaction_id)
+            .collect();
+
+        let mut transactions: Vec<&Transaction> = transaction_ids.iter()
+            .filter_map(|id| self.transactions.get(id))
+            .collect();
+        transactions.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+        transactions
+    }
+
+    /// Transactions with `start <= timestamp < end`, oldest first.
+    pub fn transactions_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<&Transaction> {
+        self.transaction_timestamp_index.range(start.timestamp_micros(), end.timestamp_micros())
+            .into_iter()
+            .filter_map(|(_, id, _)| self.transactions.get(&id))
+            .collect()
+    }
+
+    /// Entries of transactions with `start <= timestamp < end`, in timestamp order. Read from
+    /// their offsets in `entries.bin`, so only the entries in the window are looked at.
+    pub fn entries_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<Entry>, ZentryError> {
+        self.entry_timestamp_index.range(start.timestamp_micros(), end.timestamp_micros())
+            .into_iter()
+            .map(|(_, _, offset)| self.storage.read_single::<Entry>(offset))
+            .collect()
+    }
+
+    /// Rate of the stored `from -> to` relation in effect at `at`.
+    /// Archived graphs cover `[start, end)`; the active graph covers everything from its `rate_since` on.
+    /// Where windows overlap, e.g. after a relation was deleted and recreated with an earlier
+    /// `rate_since`, the graph that took effect last wins.
+    pub fn rate_at(&self, from: &str, to: &str, at: DateTime<Utc>) -> Option<f64> {
+        self.graph_at(from, to, at).map(|graph| graph.rate)
+    }
+
+    /// Like [`Ledger::rate_at`], for graphs that carry an [`ExactRate`]. `None` when the graph in
+    /// effect has only an `f64` rate.
+    pub fn exact_rate_at(&self, from: &str, to: &str, at: DateTime<Utc>) -> Option<ExactRate> {
+        self.graph_at(from, to, at).and_then(|graph| graph.exact_rate)
+    }
+
+    /// The `from -> to` graph in effect at `at` with the latest start. Ties go to the active graph,
+    /// then to the greatest key, so the same graph is picked whatever order `conversion_graphs` has.
+    fn graph_at(&self, from: &str, to: &str, at: DateTime<Utc>) -> Option<&ConversionGraph> {
+        let relation = format!("{} -> {}", from, to);
+
+        self.conversion_graphs.values()
+            .filter_map(|graph| match graph.historical_window() {
+                Some((since, graph_key, until)) => (graph_key == relation && since <= at && at < until).then_some((since, false, graph)),
+                None => (graph.graph == relation && graph.rate_since <= at).then_some((graph.rate_since, true, graph)),
+            })
+            .max_by(|(a_since, a_active, a), (b_since, b_active, b)| {
+                a_since.cmp(b_since).then(a_active.cmp(b_active)).then_with(|| a.graph.cmp(&b.graph))
+            })
+            .map(|(_, _, graph)| graph)
+    }
+
+    /// Converts `amount` of system `from` into system `to` at the active rates, chaining through
+    /// other systems when there's no direct relation. Takes the route with the fewest hops, ties
+    /// going to the alphabetically first system at each step. `None` when no route exists.
+    /// Only relations stored in the direction they're crossed are used, see
+    /// [`Ledger::convert_explained`] for deriving the reverse.
+    ///
+    /// When every graph on the route has an exact rate the result is [`Ledger::convert_exact`]'s,
+    /// otherwise, or when that overflows, the `f64` rates are multiplied hop by hop.
+    pub fn convert(&self, amount: f64, from: &str, to: &str) -> Option<f64> {
+        self.convert_explained(amount, from, to, false).map(|path| path.result)
+    }
+
+    /// [`Ledger::convert`] along with the route it took, one hop per graph in order.
+    /// A conversion from a system to itself has no hops.
+    ///
+    /// With `derive_reciprocal_rates` an active `A -> B` graph can also be crossed as `B -> A` at
+    /// `1 / rate` when no `B -> A` graph is stored; legs taken this way are listed in
+    /// [`ConversionPath::derived`]. Without it every leg is a relation someone entered.
+    pub fn convert_explained(&self, amount: f64, from: &str, to: &str, derive_reciprocal_rates: bool) -> Option<ConversionPath> {
+        let path = self.conversion_path(from, to, derive_reciprocal_rates)?;
+
+        let result = exact_path_rate(&path)
+            .and_then(|exact| exact.apply(amount, self.amount_scale))
+            .unwrap_or_else(|| path.iter().fold(amount, |value, leg| value * leg.rate));
+        let hops = path.iter().map(|leg| (leg.from.to_string(), leg.to.to_string(), leg.rate)).collect();
+        let derived = path.iter().enumerate().filter(|(_, leg)| leg.derived).map(|(index, _)| index).collect();
+
+        Some(ConversionPath { hops, derived, result })
+    }
+
+    /// [`Ledger::convert`] over exact rates only: the rates along the route are multiplied as
+    /// fractions and applied to `amount` in integers, rounding once to the amount scale, see
+    /// [`ExactRate::apply`]. So e.g. `USD -> EUR -> USD` over a `<->` gives back exactly `amount`.
+    /// `None` when no route exists, a graph on it has no exact rate, or the product overflows.
+    pub fn convert_exact(&self, amount: f64, from: &str, to: &str) -> Option<f64> {
+        let path = self.conversion_path(from, to, false)?;
+        exact_path_rate(&path)?.apply(amount, self.amount_scale)
+    }
+
+    /// The legs [`Ledger::convert`] goes through, in order. Empty when `from == to`.
+    /// `derive_reciprocal_rates` adds the reverse of every relation stored one way only.
+    fn conversion_path(&self, from: &str, to: &str, derive_reciprocal_rates: bool) -> Option<Vec<ConversionLeg<'_>>> {
+        if from == to {
+            return Some(Vec::new());
+        }
+
+        let mut edges: BTreeMap<&str, Vec<ConversionLeg>> = BTreeMap::new();
+        for graph in self.active_conversion_graphs() {
+            if let Some((source, target)) = graph.graph.split_once(" -> ") {
+                edges.entry(source).or_default().push(ConversionLeg {
+                    from: source,
+                    to: target,
+                    rate: graph.rate,
+                    exact_rate: graph.exact_rate,
+                    derived: false,
+                });
+            }
+        }
+
+        if derive_reciprocal_rates {
+            let stored: Vec<ConversionLeg> = edges.values().flatten().copied().collect();
+            for leg in stored {
+                let reverse_stored = edges.get(leg.to).is_some_and(|targets| targets.iter().any(|reverse| reverse.to == leg.from));
+                let rate = 1.0 / leg.rate;
+                if reverse_stored || !rate.is_finite() {
+                    continue;
+                }
+
+                edges.entry(leg.to).or_default().push(ConversionLeg {
+                    from: leg.to,
+                    to: leg.from,
+                    rate,
+                    exact_rate: leg.exact_rate.map(|exact| exact.reciprocal()),
+                    derived: true,
+                });
+            }
+        }
+
+        for targets in edges.values_mut() {
+            targets.sort_by(|a, b| a.to.cmp(b.to));
+        }
+
+        // breadth-first, so the first time `to` is reached is over the fewest hops.
+        // Each reached system remembers the leg it was reached through to walk the route back.
+        let mut reached: HashMap<&str, Option<ConversionLeg>> = HashMap::from([(from, None)]);
+        let mut queue = std::collections::VecDeque::from([from]);
+        while let Some(system) = queue.pop_front() {
+            for leg in edges.get(system).into_iter().flatten() {
+                if reached.contains_key(leg.to) {
+                    continue;
+                }
+                reached.insert(leg.to, Some(*leg));
+
+                if leg.to == to {
+                    let mut path = Vec::new();
+                    let mut current = leg.to;
+                    while let Some(Some(leg)) = reached.get(current) {
+                        path.push(*leg);
+                        current = leg.from;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                queue.push_back(leg.to);
+            }
+        }
+
+        None
+    }
+}
+
+fn idempotency_key(tx: &Transaction) -> Option<&str> {
+    tx.metadata.as_ref()?.get(IDEMPOTENCY_KEY_FIELD)?.as_str()
+}
+
+/// One entry per account with the net amount of its legs, rounded to `scale`, in the order each
+/// account first appears. The merged entry keeps the id of the account's first leg.
+fn merge_legs(entries: Vec<Entry>, scale: u32) -> Vec<Entry> {
+    let mut merged: Vec<Entry> = Vec::with_capacity(entries.len());
+    let mut positions: HashMap<Uuid, usize> = HashMap::new();
+
+    for entry in entries {
+        match positions.get(&entry.account_id) {
+            Some(&position) => merged[position].amount = round_to_scale(merged[position].amount + entry.amount, scale),
+            None => {
+                positions.insert(entry.account_id, merged.len());
+                merged.push(entry);
+            }
+        }
+    }
+
+    merged
+}
+
+/// Removes the file at `path`, a missing one is already where it should be
+fn remove_if_exists(path: &Path) -> Result<(), ZentryError> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// `amount` rounded half away from zero to `scale` decimal places
+fn round_to_scale(amount: f64, scale: u32) -> f64 {
+    let factor = 10f64.powi(scale as i32);
+    (amount * factor).round() / factor
+}
+
+/// One step of a conversion route: an active graph, or the reciprocal of one crossed the other way
+#[derive(Debug, Clone, Copy)]
+struct ConversionLeg<'a> {
+    from: &'a str,
+    to: &'a str,
+    rate: f64,
+    exact_rate: Option<ExactRate>,
+    /// Not stored but derived from the `to -> from` graph, see [`Ledger::convert_explained`]
+    derived: bool,
+}
+
+/// Product of the exact rates along `path`, `None` if one is missing or it overflows
+fn exact_path_rate(path: &[ConversionLeg]) -> Option<ExactRate> {
+    path.iter().try_fold(ExactRate::ONE, |product, leg| product.checked_mul(&leg.exact_rate?))
+}
+
+/// Reads one JSON record per non-empty line of `path`, paired with its 1-based line number.
+/// Lines that don't parse are described in `skipped`; a missing file reads as empty.
+fn read_jsonl<T: serde::de::DeserializeOwned>(path: &Path, skipped: &mut Vec<String>) -> Result<Vec<(usize, T)>, ZentryError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let file = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+
+    let mut records = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<T>(line) {
+            Ok(record) => records.push((index + 1, record)),
+            Err(e) => skipped.push(format!("{}:{}: {}", file, index + 1, e)),
+        }
+    }
+
+    Ok(records)
+}
+
+/// What [`Ledger::migrate_from_jsonl`] did with each file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MigrationReport {
+    pub systems: MigrationCounts,
+    pub accounts: MigrationCounts,
+    pub conversion_graphs: MigrationCounts,
+    pub transactions: MigrationCounts,
+    /// `file:line: reason` for every row left out, sorted
+    pub skipped: Vec<String>,
+}
+
+impl MigrationReport {
+    fn skip(&mut self, file: &str, line: usize, error: ZentryError) {
+        self.skipped.push(format!("{}:{}: {}", file, line, error));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct MigrationCounts {
+    pub migrated: usize,
+    /// Rows whose record was already in the binary store
+    pub duplicates: usize,
+}
+
+/// What [`Ledger::stats`] reports
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerStats {
+    pub accounts: usize,
+    pub transactions: usize,
+    pub entries: usize,
+    pub systems: usize,
+    pub active_conversion_graphs: usize,
+    pub historical_conversion_graphs: usize,
+    /// One per record type
+    pub files: Vec<RecordFileStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordFileStats {
+    /// The `.bin` file, e.g. `accounts.bin`
+    pub file: &'static str,
+    pub bin_bytes: u64,
+    pub tombstones: usize,
+    pub idx_bytes: u64,
+    /// Entries in the in-memory primary index, which `idx_bytes` lags until the next persist
+    pub index_len: usize,
+}
+
+/// The route [`Ledger::convert_explained`] took and where it ended up
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversionPath {
+    /// `(from, to, rate)` for each leg, in the order they were applied
+    pub hops: Vec<(String, String, f64)>,
+    /// Positions in `hops` of the legs whose rate was derived as the reciprocal of the opposite
+    /// relation rather than stored, see [`Ledger::convert_explained`]
+    pub derived: Vec<usize>,
+    /// The converted amount, the same as [`Ledger::convert`]'s
+    pub result: f64,
+}
+
+/// A record file's layout version, see [`Ledger::format_versions`]
+#[derive(Debug, Clone, Serialize)]
+pub struct FormatVersion {
+    /// The `.bin` file, e.g. `accounts.bin`
+    pub file: &'static str,
+    /// Version in the file's header, `None` for legacy files written before headers existed
+    pub on_disk: Option<u8>,
+    /// Version this build writes new files with and the newest it can read
+    pub current: u8,
+}
+
+/// What [`Ledger::create_conversion_graph`] would change for a graph, see [`Ledger::plan_conversion_graph`]
+#[derive(Debug, Clone)]
+pub struct ConversionPlan {
+    /// Active graphs the new rates replace, archived before anything is created
+    pub archived: Vec<PlannedArchive>,
+    /// Graphs that become active, one per stored direction
+    pub created: Vec<PlannedConversion>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedArchive {
+    /// The active graph as it is now
+    pub graph: ConversionGraph,
+    /// `since[A -> B]until` key it would be archived under, and the `conversion_graphs` key for it
+    pub historical_key: String,
+    pub historical_uuid: Uuid,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedConversion {
+    pub graph: ConversionGraph,
+    /// `conversion_graphs` key of the new graph
+    pub uuid: Uuid,
+}
+
+#[derive(Serialize)]
+struct LedgerExport<'a> {
+    accounts: Vec<&'a Account>,
+    transactions: Vec<&'a Transaction>,
+    entries: &'a [Entry],
+    systems: Vec<&'a System>,
+    conversion_graphs: Vec<&'a ConversionGraph>,
+}
+
+/// Opens a `.bin` file for reading. A missing file is an error rather than an empty ledger, since
+/// an empty file in its place would silently drop every record it held.
+fn open_record_file(path: &Path) -> Result<BufReader<File>, ZentryError> {
+    match File::open(path) {
+        Ok(file) => Ok(BufReader::new(file)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(ZentryError::NotFound(format!(
+            "data file {} is missing, run install to create it", path.display()
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Loads the index at `path`, rebuilding it from `records` if it is missing or disagrees with
+/// them, e.g. because the ledger exited without persisting or the key derivation changed. A
+/// truncated index, or one pointing past the end of the record file at `bin_path`, is reported and
+/// rebuilt. A rebuilt index is written back unless `persist` is false.
+fn load_or_rebuild_index<T>(path: &Path, bin_path: &Path, records: &[(u64, T)], key: impl Fn(&T) -> Uuid, persist: bool) -> Result<BTreeIndex, ZentryError> {
+    let index = match BTreeIndex::load(path) {
+        Ok(index) => index,
+        // the index is derived from the records, a missing one is rebuilt like a stale one
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeIndex::new(),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+            warn!("Rebuilding index: {}", e);
+            BTreeIndex::new()
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let bin_len = std::fs::metadata(bin_path)?.len();
+    if let Some((id, offset)) = index.iter().find(|(_, offset)| *offset >= bin_len) {
+        warn!(
+            "Rebuilding index: {} maps {} to offset {}, past the end of {} ({} bytes)",
+            path.display(), id, offset, bin_path.display(), bin_len
+        );
+        let index = index_from_records(records, key);
+        if persist {
+            index.persist(path)?;
+        }
+        return Ok(index);
+    }
+
+    let in_sync = index.len() == records.len()
+        && records.iter().all(|(offset, record)| index.get(&key(record)) == Some(*offset));
+    if in_sync {
+        return Ok(index);
+    }
+
+    let index = index_from_records(records, key);
+    if persist {
+        index.persist(path)?;
+    }
+    Ok(index)
+}
+
+fn join_reader<T>(handle: std::thread::ScopedJoinHandle<'_, Result<T, ZentryError>>) -> Result<T, ZentryError> {
+    handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// Tombstones the records of an interrupted `record_transaction` and drops them from the loaded
+/// lists. Returns how many records were rolled back. Read-only storage is left as it is, the
+/// records are only dropped from the lists.
+fn roll_back(
+    storage: &BinaryStorage,
+    transactions: &mut Vec<(u64, Transaction)>,
+    entries: &mut Vec<(u64, Entry)>,
+    transaction_id: Uuid,
+    entry_ids: &[Uuid],
+) -> Result<usize, ZentryError> {
+    let (orphaned_entries, kept_entries): (Vec<_>, Vec<_>) = std::mem::take(entries).into_iter()
+        .partition(|(_, entry)| entry.transaction_id == transaction_id && entry_ids.contains(&entry.id));
+    let (orphaned_transactions, kept_transactions): (Vec<_>, Vec<_>) = std::mem::take(transactions).into_iter()
+        .partition(|(_, tx)| tx.id == transaction_id);
+
+    *entries = kept_entries;
+    *transactions = kept_transactions;
+
+    let rolled_back = orphaned_entries.len() + orphaned_transactions.len();
+    if storage.is_read_only() {
+        return Ok(rolled_back);
+    }
+    for (offset, entry) in orphaned_entries {
+        storage.tombstone(entry, offset)?;
+    }
+    for (offset, tx) in orphaned_transactions {
+        storage.tombstone(tx, offset)?;
+    }
+
+    Ok(rolled_back)
+}
+
+/// Same as `load_or_rebuild_index` for a timestamp index, given the `(timestamp, id, offset)`
+/// keys it should hold. A missing file, as in data directories created before the index existed,
+/// or a truncated one is rebuilt like a stale one.
+fn load_or_rebuild_timestamp_index(path: &Path, keys: &[(i64, Uuid, u64)], persist: bool) -> Result<TimestampIndex, ZentryError> {
+    let index = match TimestampIndex::load(path) {
+        Ok(index) => index,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => TimestampIndex::new(),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+            warn!("Rebuilding index: {}", e);
+            TimestampIndex::new()
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let in_sync = index.len() == keys.len()
+        && keys.iter().all(|(timestamp, id, offset)| index.get(*timestamp, id) == Some(*offset));
+    if in_sync {
+        return Ok(index);
+    }
+
+    let index = timestamp_index_from_keys(keys);
+    if persist {
+        index.persist(path)?;
+    }
+    Ok(index)
+}
+
+/// Same as `load_or_rebuild_index` for a secondary index, given the `(key, id)` pairs of the
+/// loaded records. Every record has at most one key, so the persisted index is in sync when it
+/// holds each pair and nothing else; only a stale one is rebuilt from `pairs`.
+fn load_or_rebuild_secondary_index<'a>(
+    path: &Path,
+    pairs: impl Iterator<Item = (&'a str, Uuid)> + Clone,
+    persist: bool,
+) -> Result<SecondaryIndex, ZentryError> {
+    let index = match SecondaryIndex::load(path) {
+        Ok(index) => index,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => SecondaryIndex::new(),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+            warn!("Rebuilding index: {}", e);
+            SecondaryIndex::new()
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let in_sync = index.id_count() == pairs.clone().count()
+        && pairs.clone().all(|(key, id)| index.contains(key, &id));
+    if in_sync {
+        return Ok(index);
+    }
+
+    let mut index = SecondaryIndex::new();
+    for (key, id) in pairs {
+        index.insert(key, id);
+    }
+    if persist {
+        index.persist(path)?;
+    }
+    Ok(index)
+}
+
+fn timestamp_index_from_keys(keys: &[(i64, Uuid, u64)]) -> TimestampIndex {
+    let mut index = TimestampIndex::new();
+    for (timestamp, id, offset) in keys.iter() {
+        index.insert(*timestamp, *id, *offset);
+    }
+    index
+}
+
+fn transaction_timestamp_keys(transactions: &[(u64, Transaction)]) -> Vec<(i64, Uuid, u64)> {
+    transactions.iter()
+        .map(|(offset, tx)| (tx.timestamp.timestamp_micros(), tx.id, *offset))
+        .collect()
+}
+
+/// Entries are keyed by the timestamp of their transaction, entries without one are left out
+fn entry_timestamp_keys(entries: &[(u64, Entry)], transactions: &[(u64, Transaction)]) -> Vec<(i64, Uuid, u64)> {
+    let timestamps: HashMap<Uuid, i64> = transactions.iter()
+        .map(|(_, tx)| (tx.id, tx.timestamp.timestamp_micros()))
+        .collect();
+
+    entries.iter()
+        .filter_map(|(offset, entry)| timestamps.get(&entry.transaction_id).map(|timestamp| (*timestamp, entry.id, *offset)))
+        .collect()
+}
+
+fn index_from_records<T>(records: &[(u64, T)], key: impl Fn(&T) -> Uuid) -> BTreeIndex {
+    let mut index = BTreeIndex::new();
+    for (offset, record) in records.iter() {
+        index.insert(key(record), *offset);
+    }
+    index
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+
+/// Errors surfaced by the storage layer and the ledger.
+///
+/// Conditions the reader needs to react to (tombstones, truncated data) get their own
+/// variant so callers match on them instead of on message text.
+#[derive(Debug)]
+pub enum ZentryError {
+    Io(std::io::Error),
+    /// The record at the read position has been tombstoned
+    DeadRecord,
+    /// A length prefix points past the end of the available data
+    NotEnoughData,
+    /// The record body does not match its stored CRC32
+    ChecksumMismatch,
+    /// The byte a record starts with is neither `STATUS_LIVE` nor `STATUS_DEAD`
+    CorruptStatus { offset: u64, status: u8 },
+    /// The record found at the offset is not the one asked to be tombstoned
+    TombstoneMismatch,
+    /// No reader, writer or layout is registered for the record type
+    MissingHandle(&'static str),
+    /// A write was attempted on storage opened read-only, see `Ledger::load_read_only`
+    ReadOnly,
+    InvalidData(String),
+    NotFound(String),
+    /// A record with the same key is already stored
+    AlreadyExists(String),
+    UnbalancedTransaction(f64),
+    UnbalancedSystem { system_id: String, sum: f64 },
+    /// Problems found by `Ledger::verify_integrity` when loading strictly
+    IntegrityViolation(Vec<String>),
+}
+
+impl fmt::Display for ZentryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZentryError::Io(e) => write!(f, "{}", e),
+            ZentryError::DeadRecord => write!(f, "dead record"),
+            ZentryError::NotEnoughData => write!(f, "not enough data for length prefix"),
+            ZentryError::ChecksumMismatch => write!(f, "record checksum mismatch"),
+            ZentryError::CorruptStatus { offset, status } => {
+                write!(f, "corrupt record at offset {}: unexpected status byte {:#04x}", offset, status)
+            }
+            ZentryError::TombstoneMismatch => write!(f, "trying to tombstone wrong record"),
+            ZentryError::MissingHandle(handle) => write!(f, "no {} found for type", handle),
+            ZentryError::ReadOnly => write!(f, "ledger is opened read-only"),
+            ZentryError::InvalidData(msg) => write!(f, "{}", msg),
+            ZentryError::NotFound(msg) => write!(f, "{}", msg),
+            ZentryError::AlreadyExists(msg) => write!(f, "{}", msg),
+            ZentryError::UnbalancedTransaction(sum) => write!(f, "unbalanced transaction: total = {}", sum),
+            ZentryError::UnbalancedSystem { system_id, sum } => {
+                write!(f, "unbalanced entries in system {}: sum = {}", system_id, sum)
+            }
+            ZentryError::IntegrityViolation(problems) => write!(f, "integrity check failed: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ZentryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ZentryError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ZentryError {
+    fn from(error: std::io::Error) -> Self {
+        ZentryError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ZentryError {
+    fn from(error: serde_json::Error) -> Self {
+        ZentryError::InvalidData(error.to_string())
+    }
+}
+
+impl ZentryError {
+    pub fn is_eof(&self) -> bool {
+        matches!(self, ZentryError::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+    }
+}
@@ -1,7 +1,10 @@
 use uuid::Uuid;
 use std::collections::BTreeMap;
 
-#[derive(Debug)]
+/// Bytes per persisted entry: a 16-byte id followed by a little-endian u64 offset
+const ENTRY_LEN: usize = 24;
+
+#[derive(Debug, Default)]
 pub struct BTreeIndex {
     tree: BTreeMap<Uuid, u64>,
 }
@@ -17,34 +20,74 @@ impl BTreeIndex {
         self.tree.insert(id, offset);
     }
 
+    pub fn remove(&mut self, id: &Uuid) -> Option<u64> {
+        self.tree.remove(id)
+    }
+
     pub fn get(&self, id: &Uuid) -> Option<u64> {
         self.tree.get(id).copied()
     }
 
     pub fn range(&self, start: &Uuid, end: &Uuid) -> Vec<(Uuid, u64)> {
-        self.tree.range(start.clone()..end.clone()).map(|(k, v)| (*k, *v)).collect()
+        self.tree.range(*start..*end).map(|(k, v)| (*k, *v)).collect()
+    }
+
+    /// Every entry, in id order
+    pub fn iter(&self) -> impl Iterator<Item = (Uuid, u64)> + '_ {
+        self.tree.iter().map(|(id, offset)| (*id, *offset))
     }
 
     pub fn len(&self) -> usize {
         self.tree.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Writes the index to a temporary file next to `path` and renames it over `path`, so a crash
+    /// partway through leaves the previous index in place instead of a truncated one.
     pub fn persist(&self, path: &std::path::Path) -> std::io::Result<()> {
         use std::io::Write;
-        let mut file = std::fs::File::create(path)?;
+        let tmp_path = path.with_extension("idx.tmp");
+
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
         for (id, offset) in &self.tree {
-            file.write_all(id.as_bytes())?;
-            file.write_all(&offset.to_le_bytes())?;
+            writer.write_all(id.as_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+
+        let expected_len = (self.tree.len() * ENTRY_LEN) as u64;
+        let written_len = file.metadata()?.len();
+        if written_len != expected_len {
+            std::fs::remove_file(&tmp_path)?;
+            return Err(std::io::Error::other(format!(
+                "index file {} came out {} bytes long instead of {}",
+                tmp_path.display(), written_len, expected_len
+            )));
         }
-        Ok(())
+
+        std::fs::rename(&tmp_path, path)
     }
 
     pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
         use std::io::{Read, BufReader};
         let file = std::fs::File::open(path)?;
+
+        // a partial trailing entry means the file was cut short, the entries before it can't be trusted either
+        let file_len = file.metadata()?.len();
+        if file_len % ENTRY_LEN as u64 != 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!(
+                "index file {} is {} bytes, not a whole number of {}-byte entries",
+                path.display(), file_len, ENTRY_LEN
+            )));
+        }
+
         let mut reader = BufReader::new(file);
         let mut tree = BTreeMap::new();
-        let mut buf = [0u8; 24];
+        let mut buf = [0u8; ENTRY_LEN];
 
         while reader.read_exact(&mut buf).is_ok() {
             let id = Uuid::from_bytes(buf[0..16].try_into().map_err(|_| {
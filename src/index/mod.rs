@@ -1,3 +1,7 @@
 pub mod btree;
+pub mod secondary;
+pub mod timestamp;
 
 pub use btree::*;
+pub use secondary::*;
+pub use timestamp::*;
@@ -0,0 +1,117 @@
+use uuid::Uuid;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Maps a non-unique field value (e.g. an account's `system_id`) to the keys of every record holding it.
+#[derive(Debug, Default, PartialEq)]
+pub struct SecondaryIndex {
+    tree: BTreeMap<String, BTreeSet<Uuid>>,
+}
+
+impl SecondaryIndex {
+    pub fn new() -> Self {
+        Self {
+            tree: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, key: &str, id: Uuid) {
+        self.tree.entry(key.to_string()).or_default().insert(id);
+    }
+
+    pub fn remove(&mut self, key: &str, id: &Uuid) {
+        if let Some(ids) = self.tree.get_mut(key) {
+            ids.remove(id);
+            if ids.is_empty() {
+                self.tree.remove(key);
+            }
+        }
+    }
+
+    /// Ids recorded under `key`, in id order
+    pub fn get(&self, key: &str) -> impl Iterator<Item = &Uuid> {
+        self.tree.get(key).into_iter().flatten()
+    }
+
+    /// Whether `id` is recorded under `key`
+    pub fn contains(&self, key: &str, id: &Uuid) -> bool {
+        self.tree.get(key).is_some_and(|ids| ids.contains(id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Ids recorded across every key, where `len` counts the keys
+    pub fn id_count(&self) -> usize {
+        self.tree.values().map(BTreeSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Layout per key: `[u16 key length][key bytes][u32 id count][16-byte id]*`, all little-endian.
+    /// Written to a temporary file next to `path` and renamed over it, like `BTreeIndex::persist`.
+    pub fn persist(&self, path: &std::path::Path) -> std::io::Result<()> {
+        use std::io::Write;
+        let tmp_path = path.with_extension("idx.tmp");
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        for (key, ids) in &self.tree {
+            let key_len = u16::try_from(key.len()).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "Index key too long")
+            })?;
+            file.write_all(&key_len.to_le_bytes())?;
+            file.write_all(key.as_bytes())?;
+            file.write_all(&(ids.len() as u32).to_le_bytes())?;
+            for id in ids {
+                file.write_all(id.as_bytes())?;
+            }
+        }
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        std::fs::rename(&tmp_path, path)
+    }
+
+    /// Fails with `InvalidData` when the file ends partway through a key's record, since a cut
+    /// short file can't be trusted to hold every key.
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        use std::io::{BufRead, BufReader, Read};
+        let file = std::fs::File::open(path)?;
+        let mut reader = BufReader::new(file);
+        let mut tree = BTreeMap::new();
+
+        let truncated = |e: std::io::Error| {
+            if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!(
+                    "index file {} ends partway through a record", path.display()
+                ))
+            } else {
+                e
+            }
+        };
+
+        while !reader.fill_buf()?.is_empty() {
+            let mut len_buf = [0u8; 2];
+            reader.read_exact(&mut len_buf).map_err(truncated)?;
+            let mut key_buf = vec![0u8; u16::from_le_bytes(len_buf) as usize];
+            reader.read_exact(&mut key_buf).map_err(truncated)?;
+            let key = String::from_utf8(key_buf).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid UTF-8 index key")
+            })?;
+
+            let mut count_buf = [0u8; 4];
+            reader.read_exact(&mut count_buf).map_err(truncated)?;
+
+            let mut ids = BTreeSet::new();
+            let mut id_buf = [0u8; 16];
+            for _ in 0..u32::from_le_bytes(count_buf) {
+                reader.read_exact(&mut id_buf).map_err(truncated)?;
+                ids.insert(Uuid::from_bytes(id_buf));
+            }
+            tree.insert(key, ids);
+        }
+
+        Ok(Self { tree })
+    }
+}
@@ -0,0 +1,106 @@
+use uuid::Uuid;
+use std::collections::BTreeMap;
+
+/// Bytes per persisted entry: a little-endian i64 timestamp, a 16-byte id and a little-endian u64 offset
+const ENTRY_LEN: usize = 32;
+
+/// Orders records by a timestamp in microseconds since the epoch. The record id is part of the key
+/// so records sharing a timestamp don't overwrite each other.
+#[derive(Debug, Default)]
+pub struct TimestampIndex {
+    tree: BTreeMap<(i64, Uuid), u64>,
+}
+
+impl TimestampIndex {
+    pub fn new() -> Self {
+        Self {
+            tree: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, timestamp: i64, id: Uuid, offset: u64) {
+        self.tree.insert((timestamp, id), offset);
+    }
+
+    pub fn remove(&mut self, timestamp: i64, id: &Uuid) -> Option<u64> {
+        self.tree.remove(&(timestamp, *id))
+    }
+
+    pub fn get(&self, timestamp: i64, id: &Uuid) -> Option<u64> {
+        self.tree.get(&(timestamp, *id)).copied()
+    }
+
+    /// Entries with `start <= timestamp < end`, in timestamp order.
+    pub fn range(&self, start: i64, end: i64) -> Vec<(i64, Uuid, u64)> {
+        if start >= end {
+            return Vec::new();
+        }
+
+        self.tree.range((start, Uuid::nil())..(end, Uuid::nil()))
+            .map(|((timestamp, id), offset)| (*timestamp, *id, *offset))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Writes the index to a temporary file next to `path` and renames it over `path`, like
+    /// `BTreeIndex::persist`.
+    pub fn persist(&self, path: &std::path::Path) -> std::io::Result<()> {
+        use std::io::Write;
+        let tmp_path = path.with_extension("idx.tmp");
+
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        for ((timestamp, id), offset) in &self.tree {
+            writer.write_all(&timestamp.to_le_bytes())?;
+            writer.write_all(id.as_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+
+        let expected_len = (self.tree.len() * ENTRY_LEN) as u64;
+        let written_len = file.metadata()?.len();
+        if written_len != expected_len {
+            std::fs::remove_file(&tmp_path)?;
+            return Err(std::io::Error::other(format!(
+                "index file {} came out {} bytes long instead of {}",
+                tmp_path.display(), written_len, expected_len
+            )));
+        }
+
+        std::fs::rename(&tmp_path, path)
+    }
+
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        use std::io::{Read, BufReader};
+        let file = std::fs::File::open(path)?;
+
+        // a partial trailing entry means the file was cut short, the entries before it can't be trusted either
+        let file_len = file.metadata()?.len();
+        if file_len % ENTRY_LEN as u64 != 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!(
+                "index file {} is {} bytes, not a whole number of {}-byte entries",
+                path.display(), file_len, ENTRY_LEN
+            )));
+        }
+
+        let mut reader = BufReader::new(file);
+        let mut tree = BTreeMap::new();
+        let mut buf = [0u8; ENTRY_LEN];
+
+        while reader.read_exact(&mut buf).is_ok() {
+            let timestamp = i64::from_le_bytes(buf[0..8].try_into().unwrap());
+            let id = Uuid::from_bytes(buf[8..24].try_into().unwrap());
+            let offset = u64::from_le_bytes(buf[24..32].try_into().unwrap());
+            tree.insert((timestamp, id), offset);
+        }
+
+        Ok(Self { tree })
+    }
+}
@@ -1,38 +1,52 @@
-fn create_data_files() -> std::io::Result<()> {
-    std::fs::create_dir_all("data")?;
-    
-    let files = [
-        "data/accounts.bin",
-        "data/accounts.idx",
-        "data/accounts.jsonl",
-        "data/entries.bin",
-        "data/entries.idx",
-        "data/entries.jsonl",
-        "data/transactions.bin",
-        "data/transactions.idx",
-        "data/transactions.jsonl",
-        "data/conversion_graphs.bin",
-        "data/conversion_graphs.idx",
-        "data/conversion_graphs.jsonl",
-        "data/systems.bin",
-        "data/systems.idx",
-        "data/systems.jsonl",
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::storage::layout::{
+    file_header, ACCOUNT_LAYOUT_VERSION, CONVERSION_GRAPH_LAYOUT_VERSION, ENTRY_LAYOUT_VERSION,
+    SYSTEM_LAYOUT_VERSION, TRANSACTION_LAYOUT_VERSION,
+};
+
+fn create_data_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+
+    // record files paired with the layout version stamped in their header
+    let record_files = [
+        ("accounts", ACCOUNT_LAYOUT_VERSION),
+        ("entries", ENTRY_LAYOUT_VERSION),
+        ("transactions", TRANSACTION_LAYOUT_VERSION),
+        ("conversion_graphs", CONVERSION_GRAPH_LAYOUT_VERSION),
+        ("systems", SYSTEM_LAYOUT_VERSION),
     ];
 
-    for file in &files {
-        if std::path::Path::new(file).exists() {
-            return Ok(());
+    // each file is checked on its own so a partial data dir gets only its missing files,
+    // existing ones are never truncated
+    let mut created = Vec::new();
+    for (name, version) in &record_files {
+        let bin_path = dir.join(format!("{}.bin", name));
+        if !bin_path.exists() {
+            let mut bin = std::fs::File::create(&bin_path)?;
+            bin.write_all(&file_header(*version))?;
+            created.push(bin_path);
         }
-    }
 
-    for file in &files {
-        std::fs::File::create(file)?;
+        // no `.jsonl` files: records only live in the `.bin` files, JSONL is read once by
+        // `Ledger::migrate_from_jsonl` and never written
+        let idx_path = dir.join(format!("{}.idx", name));
+        if !idx_path.exists() {
+            std::fs::File::create(&idx_path)?;
+            created.push(idx_path);
+        }
     }
 
-    Ok(())
+    Ok(created)
+}
+
+pub fn install() -> std::io::Result<Vec<PathBuf>> {
+    install_at(Path::new("data"))
 }
 
-pub fn install() -> std::io::Result<()> {
-    create_data_files()?;
-    Ok(())
+/// Creates the data files of a fresh database under `dir`, see [`crate::db::Ledger::load_from_dir`].
+/// Files that already exist are left as they are, only the missing ones are created and returned.
+pub fn install_at(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    create_data_files(dir)
 }
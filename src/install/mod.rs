@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod install;
 
 pub use install::*;
\ No newline at end of file
@@ -0,0 +1,73 @@
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::db::Ledger;
+use crate::error::ZentryError;
+use crate::model::Transaction;
+
+/// A transaction put together one leg per line in the REPL, between `tx begin` and `tx commit`
+#[derive(Debug, Clone, Default)]
+pub struct TransactionDraft {
+    pub description: String,
+    /// Raw `Account::id` and amount of each leg, in the order they were added
+    pub legs: Vec<(Uuid, f64)>,
+}
+
+impl TransactionDraft {
+    pub fn new(description: &str) -> Self {
+        TransactionDraft {
+            description: description.to_string(),
+            legs: Vec::new(),
+        }
+    }
+
+    /// Adds the leg described by `<account> <amount>`, the account given by id or by name, and
+    /// returns the running total. Nothing is added when the account doesn't exist.
+    pub fn add_leg(&mut self, ledger: &Ledger, args: &str) -> Result<f64, ZentryError> {
+        let (account, amount) = args.trim().rsplit_once(' ')
+            .ok_or_else(|| ZentryError::InvalidData(format!("expected <account> <amount>, got `{}`", args.trim())))?;
+
+        let amount = match amount.parse::<f64>() {
+            Ok(amount) if amount.is_finite() => amount,
+            _ => return Err(ZentryError::InvalidData(format!("invalid amount: {}", amount))),
+        };
+        let account = account.trim();
+        let account_id = match account.parse::<Uuid>() {
+            Ok(account_id) if ledger.find_account(account_id).is_some() => account_id,
+            _ => ledger.find_account_by_name(account)
+                .map(|account| account.id)
+                .ok_or_else(|| ZentryError::NotFound(format!("account not found: {}", account)))?,
+        };
+
+        self.legs.push((account_id, amount));
+        Ok(self.total())
+    }
+
+    /// Sum of the legs so far, 0 once debits and credits cancel out
+    pub fn total(&self) -> f64 {
+        self.legs.iter().map(|(_, amount)| amount).sum()
+    }
+
+    /// Records the legs as one transaction through [`Ledger::record_transaction_checked`] and
+    /// returns its id with the warnings. The ledger rejects an unbalanced draft; the draft is
+    /// left as it is either way, so the caller decides whether to keep it open for more legs.
+    pub fn commit(&self, ledger: &mut Ledger) -> Result<(Uuid, Vec<String>), ZentryError> {
+        if self.legs.len() < 2 {
+            return Err(ZentryError::InvalidData("a transaction needs at least two legs".to_string()));
+        }
+
+        let entries = self.legs.iter()
+            .map(|(account_id, amount)| ledger.new_entry(*account_id, *amount))
+            .collect::<Result<Vec<_>, _>>()?;
+        let transaction = Transaction {
+            id: Uuid::new_v4(),
+            description: self.description.clone(),
+            timestamp: Utc::now(),
+            metadata: None,
+        };
+        let transaction_id = transaction.id;
+
+        let warnings = ledger.record_transaction_checked(transaction, entries)?;
+        Ok((transaction_id, warnings))
+    }
+}
@@ -1,3 +1,7 @@
+pub mod draft;
+pub mod render;
 pub mod tabled_rowtype;
 
+pub use draft::*;
+pub use render::*;
 pub use tabled_rowtype::*;
\ No newline at end of file
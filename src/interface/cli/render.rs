@@ -0,0 +1,52 @@
+use serde::Serialize;
+use tabled::{Table, Tabled};
+
+use crate::model::AccountType;
+
+/// How the CLI prints rows, switched with the `mode` command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    #[default]
+    Table,
+    /// A pretty-printed JSON array of the rows, for scripting
+    Json,
+}
+
+impl OutputMode {
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "table" => Some(OutputMode::Table),
+            "json" => Some(OutputMode::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Renders `rows` the way `mode` asks for
+pub fn render_rows<T: Tabled + Serialize>(rows: Vec<T>, mode: OutputMode) -> Result<String, serde_json::Error> {
+    match mode {
+        OutputMode::Table => Ok(Table::new(rows).to_string()),
+        OutputMode::Json => serde_json::to_string_pretty(&rows),
+    }
+}
+
+/// Slices out 1-based page `page` of `size` items, along with the number of pages.
+/// An empty list still has one (empty) page; a page past the last one comes back empty.
+pub fn paginate<T>(items: Vec<T>, page: usize, size: usize) -> (Vec<T>, usize) {
+    let page_count = items.len().div_ceil(size.max(1)).max(1);
+    let start = page.saturating_sub(1).saturating_mul(size);
+    let rows = items.into_iter().skip(start).take(size).collect();
+    (rows, page_count)
+}
+
+/// A debit-positive balance the way it reads on a report: credit-normal types (liabilities,
+/// equity, revenue and credit-normal custom types) are negated, so an account that carries its
+/// usual balance shows a positive number. Debit-normal balances come through unchanged.
+pub fn normalized_balance(account_type: &AccountType, raw: f64) -> f64 {
+    if account_type.is_debit_normal() {
+        raw
+    } else {
+        // not `-raw`, which would show an untouched account as `-0`
+        0.0 - raw
+    }
+}
@@ -1,9 +1,113 @@
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 use tabled::Tabled;
+use uuid::Uuid;
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 pub struct ConversionGraphRow {
     pub graph: String,
     pub rate: f64,
     pub rate_since: DateTime<Utc>,
 }
+
+/// A row of `show conversions --all`, where archived graphs are listed with their validity window
+#[derive(Tabled, Serialize)]
+pub struct ConversionGraphHistoryRow {
+    /// The relation, e.g. `USD -> EUR`, without the window an archived graph's key carries
+    pub graph: String,
+    /// `active`, `historical` or `unknown`
+    pub status: &'static str,
+    pub rate: f64,
+    pub start: DateTime<Utc>,
+    /// `None` for a graph still in effect
+    #[tabled(display_with = "display_option")]
+    pub end: Option<DateTime<Utc>>,
+}
+
+#[derive(Tabled, Serialize)]
+pub struct SystemRow {
+    pub id: String,
+    pub description: String,
+    #[tabled(display_with = "display_option")]
+    pub symbol: Option<String>,
+    #[tabled(display_with = "display_option")]
+    pub decimal_places: Option<u8>,
+}
+
+/// Empty cell for `None`
+fn display_option<T: std::fmt::Display>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+#[derive(Tabled, Serialize)]
+pub struct TrialBalanceRow {
+    pub system_id: String,
+    pub account_type: String,
+    #[tabled(skip)]
+    pub balance: f64,
+    /// `balance` formatted by its system, what the table shows
+    #[serde(skip)]
+    #[tabled(rename = "balance")]
+    pub shown_balance: String,
+}
+
+#[derive(Tabled, Serialize)]
+pub struct IncomeStatementRow {
+    pub system_id: String,
+    pub line: String,
+    #[tabled(skip)]
+    pub amount: f64,
+    /// `amount` formatted by its system, what the table shows
+    #[serde(skip)]
+    #[tabled(rename = "amount")]
+    pub shown_amount: String,
+}
+
+#[derive(Tabled, Serialize)]
+pub struct StatRow {
+    pub stat: String,
+    pub value: usize,
+}
+
+#[derive(Tabled, Serialize)]
+pub struct RecordFileRow {
+    pub file: String,
+    pub bin_bytes: u64,
+    pub tombstones: usize,
+    pub idx_bytes: u64,
+    pub index_len: usize,
+}
+
+#[derive(Tabled, Serialize)]
+pub struct FormatVersionRow {
+    pub file: String,
+    pub on_disk: String,
+    pub current: u8,
+}
+
+#[derive(Tabled, Serialize)]
+pub struct AccountRow {
+    pub id: Uuid,
+    pub name: String,
+    pub account_type: String,
+    pub system_id: String,
+    pub created_at: DateTime<Utc>,
+    /// Credit-normal types negated, see `normalized_balance`
+    #[tabled(skip)]
+    pub balance: f64,
+    /// `balance` formatted by the account's system, what the table shows
+    #[serde(skip)]
+    #[tabled(rename = "balance")]
+    pub shown_balance: String,
+}
+
+#[derive(Tabled, Serialize)]
+pub struct TransactionRow {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub description: String,
+    /// One `account: amount` line per entry, the amount formatted by the account's system
+    pub entries: String,
+    pub net: f64,
+    pub metadata: String,
+}
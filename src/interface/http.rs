@@ -0,0 +1,135 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use log::warn;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::shared::SharedLedger;
+
+/// Longest request line plus headers accepted, anything past it is treated as a bad request
+const MAX_REQUEST_HEAD: u64 = 8 * 1024;
+/// How long a connection may take to send its request before it is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Read-only JSON API over a [`SharedLedger`]:
+/// - `GET /systems`, `GET /accounts` and `GET /conversions` list the records, sorted like
+///   [`Ledger::systems`](crate::db::Ledger::systems) and friends
+/// - `GET /convert?amount=<n>&from=<system>&to=<system>` runs [`Ledger::convert`](crate::db::Ledger::convert)
+///
+/// Speaks just enough HTTP/1.1 for `curl` and scripts: one request per connection, served one
+/// connection at a time, query values taken as-is without percent-decoding.
+pub struct Server {
+    listener: TcpListener,
+    ledger: SharedLedger,
+}
+
+impl Server {
+    /// Binds `addr`, use port 0 to let the OS pick a free one and read it back with [`Server::local_addr`]
+    pub fn bind(addr: impl ToSocketAddrs, ledger: SharedLedger) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            ledger,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves connections until accepting one fails. A connection that fails midway only
+    /// drops that connection.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            if let Err(e) = self.handle(stream?) {
+                warn!("Error serving request: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_HEAD));
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        // drain the headers, nothing in them changes the response
+        let mut header = String::new();
+        loop {
+            header.clear();
+            if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let (status, body) = match request_line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["GET", target, _version] => self.respond(target),
+            [_method, _target, _version] => (405, error_body("only GET is supported")),
+            _ => (400, error_body("malformed request line")),
+        };
+
+        write_response(stream, status, &body)
+    }
+
+    fn respond(&self, target: &str) -> (u16, String) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        match path {
+            "/systems" => self.ledger.with(|ledger| ok_body(&ledger.systems().collect::<Vec<_>>())),
+            "/accounts" => self.ledger.with(|ledger| ok_body(&ledger.accounts().collect::<Vec<_>>())),
+            "/conversions" => self.ledger.with(|ledger| ok_body(&ledger.conversion_graphs().collect::<Vec<_>>())),
+            "/convert" => {
+                let param = |name: &str| {
+                    query.split('&')
+                        .filter_map(|pair| pair.split_once('='))
+                        .find_map(|(key, value)| (key == name).then_some(value))
+                };
+
+                let (Some(amount), Some(from), Some(to)) = (param("amount"), param("from"), param("to")) else {
+                    return (400, error_body("`amount`, `from` and `to` are required"));
+                };
+                let amount = match amount.parse::<f64>() {
+                    Ok(amount) if amount.is_finite() => amount,
+                    _ => return (400, error_body(&format!("invalid amount: {}", amount))),
+                };
+
+                match self.ledger.with(|ledger| ledger.convert(amount, from, to)) {
+                    Some(result) => ok_body(&json!({ "amount": amount, "from": from, "to": to, "result": result })),
+                    None => (404, error_body(&format!("no conversion from {} to {}", from, to))),
+                }
+            }
+            _ => (404, error_body(&format!("no such endpoint: {}", path))),
+        }
+    }
+}
+
+fn ok_body<T: Serialize + ?Sized>(value: &T) -> (u16, String) {
+    match serde_json::to_string(value) {
+        Ok(body) => (200, body),
+        Err(e) => (500, error_body(&e.to_string())),
+    }
+}
+
+fn error_body(message: &str) -> String {
+    json!({ "error": message }).to_string()
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, body.len(), body
+    )?;
+    stream.flush()
+}
@@ -1,3 +1,5 @@
 pub mod cli;
+#[cfg(feature = "server")]
+pub mod http;
 
 pub use cli::*;
\ No newline at end of file
@@ -3,6 +3,8 @@ pub mod storage;
 pub mod index;
 pub mod util;
 pub mod db;
+pub mod shared;
+pub mod error;
 pub mod install;
 pub mod interface;
 
@@ -10,5 +12,6 @@ pub use model::*;
 pub use storage::*;
 pub use index::*;
 pub use util::*;
+pub use error::*;
 pub use install::*;
 pub use interface::*;
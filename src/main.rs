@@ -1,21 +1,54 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
 use chrono::Utc;
 use colored::*;
 use rustyline::DefaultEditor;
 
-use tabled::Table;
+use serde::Serialize;
+use tabled::{Table, Tabled};
+use uuid::Uuid;
 use zentry_db::{
     db::Ledger,
     install,
-    model::{System, ConversionGraph},
-    interface::cli::ConversionGraphRow,
+    model::{AccountType, System, ConversionGraph, ExactRate, Transaction, Entry},
+    interface::cli::{normalized_balance, paginate, render_rows, TransactionDraft, AccountRow, ConversionGraphHistoryRow, ConversionGraphRow, IncomeStatementRow, OutputMode, FormatVersionRow, RecordFileRow, StatRow, SystemRow, TransactionRow, TrialBalanceRow},
+    storage::{account_type_from_label, classify_graph_key, ACCOUNT_LAYOUT_VERSION, CONVERSION_GRAPH_LAYOUT_VERSION, ENTRY_LAYOUT_VERSION, SYSTEM_LAYOUT_VERSION, TRANSACTION_LAYOUT_VERSION},
 };
 
+/// Relative deviation `check rates` accepts between conversion rates when none is given
+const DEFAULT_RATE_TOLERANCE: f64 = 0.01;
+/// Rows per page of `show conversions page` when no size is given
+const DEFAULT_PAGE_SIZE: usize = 50;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Ledger logs go to stderr, warnings only unless RUST_LOG asks for more
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    // `--version` answers without touching any data directory
+    if std::env::args().nth(1).as_deref() == Some("--version") {
+        println!("zentry_db {}", env!("CARGO_PKG_VERSION"));
+        println!("layout versions: accounts {}, transactions {}, entries {}, systems {}, conversion graphs {}",
+            ACCOUNT_LAYOUT_VERSION, TRANSACTION_LAYOUT_VERSION, ENTRY_LAYOUT_VERSION, SYSTEM_LAYOUT_VERSION, CONVERSION_GRAPH_LAYOUT_VERSION);
+        return Ok(());
+    }
+
+    // Data directory, defaults to `data/` when not given as the first argument
+    let data_dir = std::env::args().nth(1).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("data"));
+
     // Database installation
-    install::install()?;
+    let fresh = !data_dir.exists();
+    let created = install::install_at(&data_dir)?;
+    if !fresh {
+        for path in created {
+            println!("Created missing data file {}", path.display());
+        }
+    }
 
     // Initialize ledger
-    let mut ledger = Ledger::load_from_disk()?;
+    let mut ledger = Ledger::load_from_dir(&data_dir)?;
 
     let mut r1 = DefaultEditor::new()?;
 
@@ -78,19 +111,88 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!();
     println!();
 
+    let mut output_mode = OutputMode::default();
+    // open between `tx begin` and `tx commit` / `tx abort`, when only legs are accepted
+    let mut draft: Option<TransactionDraft> = None;
+
     loop {
-        let readline = r1.readline("zentry> ");
+        let readline = r1.readline(if draft.is_some() { "zentry tx> " } else { "zentry> " });
         match readline {
             Ok(line) => {
                 let input = line.trim();
+                if let Some(open) = draft.as_mut() {
+                    if let Some(rest) = input.strip_prefix("leg ") {
+                        match open.add_leg(&ledger, rest) {
+                            Ok(total) => println!("Running total: {}", total),
+                            Err(e) => {
+                                println!("Invalid leg. Use leg <account id or name> <amount>");
+                                println!("  {}", e);
+                            },
+                        }
+                    } else if input == "tx commit" {
+                        match open.commit(&mut ledger) {
+                            Ok((transaction_id, warnings)) => {
+                                println!("Transaction recorded successfully: {}", transaction_id);
+                                for warning in warnings {
+                                    println!("  Warning: {}", warning);
+                                }
+                                draft = None;
+                            },
+                            Err(e) => {
+                                println!("Error recording transaction, it is still open");
+                                println!("  {}", e);
+                            },
+                        }
+                    } else if input == "tx abort" {
+                        draft = None;
+                        println!("Transaction discarded");
+                    } else if input == "exit" {
+                        println!("Open transaction discarded");
+                        break;
+                    } else if !input.is_empty() {
+                        println!("A transaction is open. Use leg <account> <amount>, tx commit or tx abort");
+                    }
+                    continue;
+                }
+
                 if input == "exit" {
                     break;
                 } else if input == "help" {
                     println!("{}", "Commands:".cyan().bold());
                     println!("{}", "  system <id> <desc>                                        - Create a currency system".cyan());
                     println!("{}", "  conv <system1> <relation> <system2> <rate> [<rate since>] - Add a conversion graph".cyan());
+                    println!("{}", "  conv delete <system1> <relation> <system2>                - Retire a conversion graph".cyan());
+                    println!("{}", "  conv plan <system1> <relation> <system2> <rate> [<since>] - Show what `conv` would archive and create".cyan());
+                    println!("{}", "  tx \"<description>\" <account id>:<amount> ...            - Record a transaction".cyan());
+                    println!("{}", "  tx begin [\"<description>\"]                                - Start a transaction entered one leg per line".cyan());
+                    println!("{}", "    leg <account id or name> <amount>                       - Add a leg and show the running total".cyan());
+                    println!("{}", "    tx commit | tx abort                                    - Record the open transaction, or discard it".cyan());
+                    println!("{}", "  reverse <transaction id>                                  - Post a transaction undoing another one".cyan());
+                    println!("{}", "  close <as of> <equity account id>                         - Move revenue and expense balances into equity".cyan());
+                    println!("{}", "  show accounts [system <id>] [type <type>]                 - List accounts, optionally filtered".cyan());
+                    println!("{}", "  show transactions [where <key>=<value>]                   - List transactions with their entries, optionally by metadata".cyan());
+                    println!("{}", "  show account <account id> transactions                    - List the transactions that touched an account".cyan());
                     println!("{}", "  show systems                                              - List all systems".cyan());
-                    println!("{}", "  show conversions                                          - List all conversion graphs".cyan());
+                    println!("{}", "  show conversions [<system>]                               - List the active conversion graphs, optionally of one system".cyan());
+                    println!("{}", "  show conversions --all                                    - List conversion graphs with archived ones and their validity windows".cyan());
+                    println!("{}", "  show conversions page <n> [size <m>]                      - List one page of the active conversion graphs".cyan());
+                    println!("{}", "  mode <table|json>                                         - Print `show` output as tables or as JSON".cyan());
+                    println!("{}", "  convert <amount> <from> <to> [--explain] [--derive]       - Convert at the active rates, --explain lists each leg, --derive crosses one-way rates backwards at 1 / rate".cyan());
+                    println!("{}", "  check rates [<tolerance>]                                 - List conversion rates that disagree, 0.01 = 1%".cyan());
+                    println!("{}", "  check index                                               - Verify every index against its data file".cyan());
+                    println!("{}", "  balance                                                   - Show the trial balance of each system by account type, credit-normal types negated".cyan());
+                    println!("{}", "  balance account <account id> [at <time>]                  - Show the balance of an account, optionally as of a time".cyan());
+                    println!("{}", "  report income <start> <end>                               - Show revenue, expense and net income of each system for [start, end)".cyan());
+                    println!("{}", "  import accounts <csv path>                                - Import accounts from name,account_type,system_id rows".cyan());
+                    println!("{}", "  import systems <path>                                     - Import systems from id,description or JSON lines".cyan());
+                    println!("{}", "  migrate                                                   - Move the records of the data dir's .jsonl files into the binary store".cyan());
+                    println!("{}", "  export <path>                                             - Export the whole ledger as JSON".cyan());
+                    println!("{}", "  stats                                                     - Show record counts, file sizes and tombstones".cyan());
+                    println!("{}", "  reindex                                                   - Rebuild every index from the record files".cyan());
+                    println!("{}", "  snapshot <dir>                                            - Copy the ledger into a new timestamped directory under <dir>".cyan());
+                    println!("{}", "  restore <snapshot dir>                                    - Replace the ledger with a snapshot".cyan());
+                    println!("{}", "  reset --yes                                               - Delete every record and start from an empty database".cyan());
+                    println!("{}", "  version                                                   - Show the build version and each file's format version".cyan());
                     println!("{}", "  exit                                                      - Quit".cyan());
                 } else if let Some(rest) = input.strip_prefix("system ") {
                     let mut parts = rest.splitn(2, ' ');
@@ -99,6 +201,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         let system = System {
                             id: id.to_string(),
                             description: desc.to_string(),
+                            symbol: None,
+                            decimal_places: None,
                         };
                         match ledger.create_system(system) {
                             Ok(_) => println!("System created successfully"),
@@ -108,7 +212,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     } else {
                         println!("Invalid command format. Type 'help' for list of commands");
                     }
+                } else if let Some(rest) = input.strip_prefix("conv delete ") {
+                    match ledger.delete_conversion_graph(rest) {
+                        Ok(_) => println!("Conversion graph deleted successfully"),
+                        Err(e) => {
+                            println!("Error deleting conversion graph");
+                            println!("  {}", e);
+                        },
+                    }
                 } else if let Some(rest) = input.strip_prefix("conv ") {
+                    let (dry_run, rest) = match rest.strip_prefix("plan ") {
+                        Some(rest) => (true, rest),
+                        None => (false, rest),
+                    };
                     let mut parts = rest.split_whitespace();
 
                     let system1 = parts.next();
@@ -139,12 +255,32 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 Utc::now()
                             };
 
+                            // a rate written as a plain decimal is also kept exactly
                             let conversion_graph = ConversionGraph {
                                 graph: format!("{} {} {}", system1, relation, system2),
                                 rate,
                                 rate_since,
+                                exact_rate: ExactRate::parse(rate_str),
                             };
 
+                            if dry_run {
+                                match ledger.plan_conversion_graph(&conversion_graph) {
+                                    Ok(plan) => {
+                                        for archive in &plan.archived {
+                                            println!("Archive {} (rate {}) as {}", archive.graph.graph, archive.graph.rate, archive.historical_key);
+                                        }
+                                        for created in &plan.created {
+                                            println!("Create {} (rate {}) since {}", created.graph.graph, created.graph.rate, created.graph.rate_since.to_rfc3339());
+                                        }
+                                    }
+                                    Err(e) => {
+                                        println!("Error planning conversion graph");
+                                        println!("  {}", e);
+                                    },
+                                }
+                                continue;
+                            }
+
                             match ledger.create_conversion_graph(conversion_graph) {
                                 Ok(_) => println!("Conversion graph added successfully"),
                                 Err(e) => {
@@ -159,22 +295,655 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             continue;
                         }
                     }
+                } else if let Some(rest) = input.strip_prefix("tx begin").filter(|rest| rest.is_empty() || rest.starts_with(' ')) {
+                    let description = rest.trim().trim_matches('"');
+                    draft = Some(TransactionDraft::new(description));
+                    println!("Transaction started. Add legs with leg <account> <amount>, then tx commit or tx abort");
+                    continue;
+                } else if input == "tx commit" || input == "tx abort" {
+                    println!("No open transaction. Start one with tx begin");
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("tx ") {
+                    // Description is quoted so it can contain spaces, the legs follow it
+                    let (description, legs) = match rest.trim_start().strip_prefix('"').and_then(|s| s.split_once('"')) {
+                        Some(parts) => parts,
+                        None => {
+                            println!("Invalid command format. Wrap the description in double quotes");
+                            continue;
+                        }
+                    };
+
+                    let transaction_id = Uuid::new_v4();
+                    let mut entries = Vec::new();
+                    let mut invalid_leg = None;
+                    let mut leg_error = None;
+
+                    for leg in legs.split_whitespace() {
+                        let parsed = leg.split_once(':').and_then(|(account_id, amount)| {
+                            Some((account_id.parse::<Uuid>().ok()?, amount.parse::<f64>().ok()?))
+                        });
+
+                        match parsed {
+                            Some((account_id, amount)) => match ledger.new_entry(account_id, amount) {
+                                Ok(entry) => entries.push(entry),
+                                Err(e) => {
+                                    leg_error = Some(e);
+                                    break;
+                                }
+                            },
+                            None => {
+                                invalid_leg = Some(leg);
+                                break;
+                            }
+                        }
+                    }
+
+                    if let Some(leg) = invalid_leg {
+                        println!("Invalid leg `{}`. Use <account id>:<amount>, e.g. 4f1c...:-25.5", leg);
+                        continue;
+                    }
+                    if let Some(e) = leg_error {
+                        println!("Error recording transaction");
+                        println!("  {}", e);
+                        continue;
+                    }
+                    if entries.len() < 2 {
+                        println!("A transaction needs at least two legs");
+                        continue;
+                    }
+
+                    let transaction = Transaction {
+                        id: transaction_id,
+                        description: description.to_string(),
+                        timestamp: Utc::now(),
+                        metadata: None,
+                    };
+
+                    match ledger.record_transaction_checked(transaction, entries) {
+                        Ok(warnings) => {
+                            println!("Transaction recorded successfully: {}", transaction_id);
+                            for warning in warnings {
+                                println!("  Warning: {}", warning);
+                            }
+                        },
+                        Err(e) => {
+                            println!("Error recording transaction");
+                            println!("  {}", e);
+                        },
+                    }
+                } else if let Some(rest) = input.strip_prefix("balance account ") {
+                    let mut args = rest.split_whitespace();
+
+                    let account_id: Uuid = match args.next().map(str::parse) {
+                        Some(Ok(id)) => id,
+                        _ => {
+                            println!("Invalid `account id` format. Use a UUID");
+                            continue;
+                        }
+                    };
+
+                    let as_of = match (args.next(), args.next()) {
+                        (None, _) => None,
+                        (Some("at"), Some(at_str)) => match at_str.parse::<chrono::DateTime<Utc>>() {
+                            Ok(at) => Some(at),
+                            Err(_) => {
+                                println!("Invalid `at` format. Use RFC3339 format (e.g., 2023-04-23T12:00:00Z)");
+                                continue;
+                            }
+                        },
+                        _ => {
+                            println!("Invalid command format. Type 'help' for list of commands");
+                            continue;
+                        }
+                    };
+
+                    if let Some(at) = as_of {
+                        match ledger.find_account(account_id) {
+                            Some(account) => println!(
+                                "Balance of {} at {}: {}",
+                                account.name,
+                                at.to_rfc3339(),
+                                format_amount(&ledger, &account.system_id, normalized_balance(&account.account_type, ledger.account_balance_as_of(account_id, at)))
+                            ),
+                            None => println!("Account not found: {}", account_id),
+                        }
+                        continue;
+                    }
+
+                    match ledger.find_account(account_id) {
+                        Some(account) if ledger.account_subtree(account_id).is_empty() => {
+                            let balance = normalized_balance(&account.account_type, ledger.account_balance(account_id));
+                            println!("Balance of {}: {}", account.name, format_amount(&ledger, &account.system_id, balance));
+                        }
+                        Some(account) => println!(
+                            "Balance of {}: {} ({} including sub-accounts)",
+                            account.name,
+                            format_amount(&ledger, &account.system_id, normalized_balance(&account.account_type, ledger.account_balance(account_id))),
+                            format_amount(&ledger, &account.system_id, normalized_balance(&account.account_type, ledger.rolled_up_balance(account_id)))
+                        ),
+                        None => println!("Account not found: {}", account_id),
+                    }
+                    continue;
+                } else if input == "balance" {
+                    // one block of rows per system, amounts of different systems don't add up
+                    let trial_balances = ledger.trial_balance_by_system();
+                    let mut rows: Vec<TrialBalanceRow> = Vec::new();
+                    for system in ledger.systems() {
+                        let trial_balance = trial_balances.get(&system.id).cloned().unwrap_or_default();
+                        let mut account_types = vec![
+                            AccountType::Asset,
+                            AccountType::Liability,
+                            AccountType::Equity,
+                            AccountType::Revenue,
+                            AccountType::Expense,
+                        ];
+                        // custom types only show up once an account of that type has entries
+                        let mut custom_types: Vec<AccountType> = trial_balance.keys()
+                            .filter(|account_type| matches!(account_type, AccountType::Custom(_)))
+                            .cloned()
+                            .collect();
+                        custom_types.sort();
+                        account_types.extend(custom_types);
+
+                        rows.extend(account_types.iter().map(|account_type| {
+                            let balance = normalized_balance(account_type, trial_balance.get(account_type).copied().unwrap_or(0.0));
+                            TrialBalanceRow {
+                                system_id: system.id.clone(),
+                                account_type: account_type.label(),
+                                balance,
+                                shown_balance: system.format_amount(balance),
+                            }
+                        }));
+                    }
+
+                    let table = Table::new(rows);
+                    println!("{}", table);
+
+                    if !ledger.is_balanced() {
+                        println!("{}", "Ledger is not balanced".red());
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("report income ") {
+                    let bounds: Vec<_> = rest.split_whitespace().map(|s| s.parse::<chrono::DateTime<Utc>>()).collect();
+                    let (start, end) = match bounds.as_slice() {
+                        [Ok(start), Ok(end)] => (*start, *end),
+                        [_, _] => {
+                            println!("Invalid `start`/`end` format. Use RFC3339 format (e.g., 2023-04-23T12:00:00Z)");
+                            continue;
+                        }
+                        _ => {
+                            println!("Invalid command format. Type 'help' for list of commands");
+                            continue;
+                        }
+                    };
+
+                    let statements = ledger.income_statement_by_system(start, end);
+                    let mut rows: Vec<IncomeStatementRow> = Vec::new();
+                    for system in ledger.systems() {
+                        let (revenue, expense, net) = statements.get(&system.id).copied().unwrap_or_default();
+                        rows.extend([("Revenue", revenue), ("Expense", expense), ("Net income", net)].map(|(line, amount)| IncomeStatementRow {
+                            system_id: system.id.clone(),
+                            line: line.to_string(),
+                            amount,
+                            shown_amount: system.format_amount(amount),
+                        }));
+                    }
+
+                    println!("Income statement from {} to {}", start.to_rfc3339(), end.to_rfc3339());
+                    println!("{}", Table::new(rows));
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("convert ") {
+                    let mut parts: Vec<&str> = rest.split_whitespace().collect();
+                    let explain = parts.contains(&"--explain");
+                    let derive = parts.contains(&"--derive");
+                    parts.retain(|part| *part != "--explain" && *part != "--derive");
+                    let [amount_str, from, to] = parts.as_slice() else {
+                        println!("Invalid command format. Type 'help' for list of commands");
+                        continue;
+                    };
+                    let amount = match amount_str.parse::<f64>() {
+                        Ok(amount) if amount.is_finite() => amount,
+                        _ => {
+                            println!("Invalid `amount` format. Use a number");
+                            continue;
+                        }
+                    };
+
+                    // strict unless asked, a conversion only uses relations someone entered
+                    match ledger.convert_explained(amount, from, to, derive) {
+                        Some(path) => {
+                            if explain {
+                                for (index, (source, target, rate)) in path.hops.iter().enumerate() {
+                                    let derived = if path.derived.contains(&index) { " (derived from the reverse rate)" } else { "" };
+                                    println!("  {}. {} -> {} @ {}{}", index + 1, source, target, rate, derived);
+                                }
+                            }
+                            println!("{} {} = {} {}", format_amount(&ledger, from, amount), from, format_amount(&ledger, to, path.result), to);
+                        }
+                        None => println!("No conversion from {} to {}", from, to),
+                    }
+                    continue;
+                } else if input == "check index" {
+                    let problems = ledger.verify_indexes();
+                    if problems.is_empty() {
+                        println!("{}", "Indexes are consistent".green());
+                    } else {
+                        for problem in problems {
+                            println!("{}", problem.red());
+                        }
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("check rates").filter(|rest| rest.is_empty() || rest.starts_with(' ')) {
+                    let tolerance = match rest.trim() {
+                        "" => DEFAULT_RATE_TOLERANCE,
+                        tolerance_str => match tolerance_str.parse::<f64>() {
+                            Ok(tolerance) if tolerance >= 0.0 => tolerance,
+                            _ => {
+                                println!("Invalid `tolerance` format. Use a non-negative number, e.g. 0.01 for 1%");
+                                continue;
+                            }
+                        },
+                    };
+
+                    let problems = ledger.detect_rate_inconsistencies(tolerance);
+                    if problems.is_empty() {
+                        println!("{}", "Conversion rates are consistent".green());
+                    } else {
+                        for problem in problems {
+                            println!("{}", problem.red());
+                        }
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("reverse ") {
+                    let transaction_id = match rest.trim().parse::<Uuid>() {
+                        Ok(id) => id,
+                        Err(_) => {
+                            println!("Invalid transaction id `{}`", rest.trim());
+                            continue;
+                        }
+                    };
+
+                    match ledger.reverse_transaction(transaction_id) {
+                        Ok(reversal) => println!("Transaction reversed by {}", reversal.id),
+                        Err(e) => {
+                            println!("Error reversing transaction");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("close ") {
+                    let parts: Vec<&str> = rest.split_whitespace().collect();
+                    let (as_of, equity_account) = match parts.as_slice() {
+                        [as_of, equity_account] => (as_of.parse::<chrono::DateTime<Utc>>(), equity_account.parse::<Uuid>()),
+                        _ => {
+                            println!("Invalid command format. Use close <as of> <equity account id>");
+                            continue;
+                        }
+                    };
+                    let (Ok(as_of), Ok(equity_account)) = (as_of, equity_account) else {
+                        println!("Invalid arguments. Use an RFC3339 time (e.g., 2023-12-31T23:59:59Z) and an account id");
+                        continue;
+                    };
+
+                    match ledger.close_period(as_of, equity_account) {
+                        Ok(closing) => println!("Period closed by {}", closing.id),
+                        Err(e) => {
+                            println!("Error closing period");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if input == "migrate" {
+                    match ledger.migrate_from_jsonl() {
+                        Ok(report) => {
+                            let counts = [
+                                ("systems", report.systems),
+                                ("accounts", report.accounts),
+                                ("conversion graphs", report.conversion_graphs),
+                                ("transactions", report.transactions),
+                            ];
+                            for (records, count) in counts {
+                                println!("Migrated {} {} ({} already present)", count.migrated, records, count.duplicates);
+                            }
+                            for skipped in report.skipped {
+                                println!("  Skipped {}", skipped);
+                            }
+                        }
+                        Err(e) => {
+                            println!("Error migrating JSONL data");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if input == "stats" {
+                    match ledger.stats() {
+                        Ok(stats) => {
+                            let counts = [
+                                ("accounts", stats.accounts),
+                                ("transactions", stats.transactions),
+                                ("entries", stats.entries),
+                                ("systems", stats.systems),
+                                ("active conversion graphs", stats.active_conversion_graphs),
+                                ("historical conversion graphs", stats.historical_conversion_graphs),
+                            ];
+                            print_rows(counts.iter().map(|(stat, value)| StatRow {
+                                stat: stat.to_string(),
+                                value: *value,
+                            }).collect(), output_mode);
+
+                            print_rows(stats.files.iter().map(|file| RecordFileRow {
+                                file: file.file.to_string(),
+                                bin_bytes: file.bin_bytes,
+                                tombstones: file.tombstones,
+                                idx_bytes: file.idx_bytes,
+                                index_len: file.index_len,
+                            }).collect(), output_mode);
+                        }
+                        Err(e) => {
+                            println!("Error collecting stats");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if input == "version" {
+                    println!("zentry_db {}", env!("CARGO_PKG_VERSION"));
+                    match ledger.format_versions() {
+                        Ok(versions) => print_rows(versions.iter().map(|version| FormatVersionRow {
+                            file: version.file.to_string(),
+                            on_disk: version.on_disk.map_or_else(|| "unversioned / pre-1.0".to_string(), |on_disk| on_disk.to_string()),
+                            current: version.current,
+                        }).collect(), output_mode),
+                        Err(e) => {
+                            println!("Error reading format versions");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("snapshot ") {
+                    match ledger.snapshot(Path::new(rest.trim())) {
+                        Ok(snapshot_dir) => println!("Snapshot written to {}", snapshot_dir.display()),
+                        Err(e) => {
+                            println!("Error taking snapshot");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("restore ") {
+                    match ledger.restore(Path::new(rest.trim())) {
+                        Ok(_) => println!("Ledger restored from {}", rest.trim()),
+                        Err(e) => {
+                            println!("Error restoring snapshot");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if input == "reset" {
+                    println!("This deletes every record in {}. Run `reset --yes` to go ahead", data_dir.display());
+                    continue;
+                } else if input == "reset --yes" {
+                    match Ledger::reset(&data_dir) {
+                        Ok(fresh) => {
+                            ledger = fresh;
+                            println!("Ledger reset, {} holds an empty database", data_dir.display());
+                        },
+                        Err(e) => {
+                            println!("Error resetting ledger");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if input == "reindex" {
+                    match ledger.rebuild_indexes() {
+                        Ok(_) => println!("Indexes rebuilt successfully"),
+                        Err(e) => {
+                            println!("Error rebuilding indexes");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("import accounts ") {
+                    match ledger.import_accounts_csv(Path::new(rest.trim())) {
+                        Ok(count) => println!("Imported {} accounts", count),
+                        Err(e) => {
+                            println!("Error importing accounts");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("import systems ") {
+                    match ledger.import_systems(Path::new(rest.trim())) {
+                        Ok((count, skipped)) => {
+                            println!("Imported {} systems", count);
+                            for id in skipped {
+                                println!("  Skipped duplicate system: {}", id);
+                            }
+                        }
+                        Err(e) => {
+                            println!("Error importing systems");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("export ") {
+                    let path = rest.trim();
+                    let file = match File::create(path) {
+                        Ok(file) => file,
+                        Err(e) => {
+                            println!("Error creating export file");
+                            println!("  {}", e);
+                            continue;
+                        }
+                    };
+
+                    match ledger.export_json(BufWriter::new(file)) {
+                        Ok(_) => println!("Ledger exported to {}", path),
+                        Err(e) => {
+                            println!("Error exporting ledger");
+                            println!("  {}", e);
+                        },
+                    }
+                    continue;
+                } else if let Some(label) = input.strip_prefix("mode ") {
+                    match OutputMode::from_label(label.trim()) {
+                        Some(mode) => {
+                            output_mode = mode;
+                            println!("Output mode set to {}", label.trim());
+                        }
+                        None => println!("Invalid `mode`. Use `table` or `json`"),
+                    }
+                    continue;
+                } else if let Some(rest) = input.strip_prefix("show accounts").filter(|rest| rest.is_empty() || rest.starts_with(' ')) {
+                    let mut args = rest.split_whitespace();
+                    let mut system_filter = None;
+                    let mut type_filter = None;
+                    let mut invalid = false;
+
+                    while let Some(arg) = args.next() {
+                        match (arg, args.next()) {
+                            ("system", Some(system_id)) => system_filter = Some(system_id),
+                            ("type", Some(label)) => match account_type_from_label(label) {
+                                Some(account_type) => type_filter = Some(account_type),
+                                None => {
+                                    println!("Invalid `type`. Use one of Asset, Liability, Equity, Revenue or Expense");
+                                    invalid = true;
+                                    break;
+                                }
+                            },
+                            _ => {
+                                println!("Invalid command format. Type 'help' for list of commands");
+                                invalid = true;
+                                break;
+                            }
+                        }
+                    }
+                    if invalid {
+                        continue;
+                    }
+
+                    let mut accounts = match system_filter {
+                        Some(system_id) => ledger.accounts_in_system(system_id),
+                        None => ledger.accounts().collect(),
+                    };
+                    accounts.retain(|account| type_filter.as_ref().is_none_or(|account_type| &account.account_type == account_type));
+                    accounts.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)));
+
+                    let balances = ledger.all_balances();
+                    let rows: Vec<AccountRow> = accounts.into_iter().map(|account| {
+                        let balance = normalized_balance(&account.account_type, balances.get(&account.id).copied().unwrap_or(0.0));
+                        AccountRow {
+                            id: account.id,
+                            name: account.name.clone(),
+                            account_type: account.account_type.label(),
+                            system_id: account.system_id.clone(),
+                            created_at: account.created_at,
+                            balance,
+                            shown_balance: format_amount(&ledger, &account.system_id, balance),
+                        }
+                    }).collect();
+
+                    print_rows(rows, output_mode);
+                    continue;
                 } else if let Some(rest) = input.strip_prefix("show ") {
                     match rest.trim() {
                         "systems" => {
-                            println!("{}", "Function is in development".cyan());
+                            let rows: Vec<SystemRow> = ledger.systems().map(|system| SystemRow {
+                                id: system.id.clone(),
+                                description: system.description.clone(),
+                                symbol: system.symbol.clone(),
+                                decimal_places: system.decimal_places,
+                            }).collect();
+
+                            print_rows(rows, output_mode);
+                            continue;
+                        }
+                        other if other == "transactions" || other.starts_with("transactions where ") => {
+                            let transactions: Vec<&Transaction> = match other.strip_prefix("transactions where ") {
+                                Some(filter) => match filter.split_once('=') {
+                                    Some((key, value)) => {
+                                        // JSON values match as typed, e.g. `count=3`, anything else as a string
+                                        let value = serde_json::from_str(value.trim())
+                                            .unwrap_or_else(|_| serde_json::Value::String(value.trim().to_string()));
+                                        ledger.find_transactions_by_metadata(key.trim(), &value)
+                                    }
+                                    None => {
+                                        println!("Invalid filter. Use `show transactions where <key>=<value>`");
+                                        continue;
+                                    }
+                                },
+                                None => {
+                                    let mut transactions: Vec<&Transaction> = ledger.transactions.values().collect();
+                                    transactions.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+                                    transactions
+                                }
+                            };
+
+                            let rows = transaction_rows(&ledger, transactions);
+
+                            print_rows(rows, output_mode);
+                            continue;
+                        }
+                        other if other.starts_with("account ") && other.ends_with(" transactions") => {
+                            let account_id: Uuid = match other["account ".len()..other.len() - " transactions".len()].trim().parse() {
+                                Ok(id) => id,
+                                Err(_) => {
+                                    println!("Invalid `account id` format. Use a UUID");
+                                    continue;
+                                }
+                            };
+
+                            if ledger.find_account(account_id).is_none() {
+                                println!("Account not found: {}", account_id);
+                                continue;
+                            }
+
+                            let rows = transaction_rows(&ledger, ledger.transactions_for_account(account_id));
+                            print_rows(rows, output_mode);
                             continue;
                         }
                         "conversions" => {
-                            let conversion_graphs = ledger.conversion_graphs.clone();
-                            let rows: Vec<ConversionGraphRow> = conversion_graphs.iter().map(|(_, conversion_graph)| ConversionGraphRow {
+                            let rows: Vec<ConversionGraphRow> = ledger.active_conversion_graphs().map(|conversion_graph| ConversionGraphRow {
+                                graph: conversion_graph.graph.clone(),
+                                rate: conversion_graph.rate,
+                                rate_since: conversion_graph.rate_since,
+                            }).collect();
+
+                            print_rows(rows, output_mode);
+                            continue;
+                        }
+                        "conversions --all" => {
+                            let rows: Vec<ConversionGraphHistoryRow> = ledger.conversion_graphs().map(|conversion_graph| {
+                                match conversion_graph.historical_window() {
+                                    Some((start, relation, end)) => ConversionGraphHistoryRow {
+                                        graph: relation.to_string(),
+                                        status: classify_graph_key(conversion_graph).as_str(),
+                                        rate: conversion_graph.rate,
+                                        start,
+                                        end: Some(end),
+                                    },
+                                    None => ConversionGraphHistoryRow {
+                                        graph: conversion_graph.graph.clone(),
+                                        status: classify_graph_key(conversion_graph).as_str(),
+                                        rate: conversion_graph.rate,
+                                        start: conversion_graph.rate_since,
+                                        end: None,
+                                    },
+                                }
+                            }).collect();
+
+                            print_rows(rows, output_mode);
+                            continue;
+                        }
+                        other if other.starts_with("conversions page ") => {
+                            let mut args = other["conversions page ".len()..].split_whitespace();
+                            let page = match args.next().map(str::parse::<usize>) {
+                                Some(Ok(page)) if page > 0 => page,
+                                _ => {
+                                    println!("Invalid `page`. Use a page number starting at 1");
+                                    continue;
+                                }
+                            };
+                            let size = match (args.next(), args.next(), args.next()) {
+                                (None, _, _) => DEFAULT_PAGE_SIZE,
+                                (Some("size"), Some(size_str), None) => match size_str.parse::<usize>() {
+                                    Ok(size) if size > 0 => size,
+                                    _ => {
+                                        println!("Invalid `size`. Use a positive number of rows");
+                                        continue;
+                                    }
+                                },
+                                _ => {
+                                    println!("Invalid command format. Type 'help' for list of commands");
+                                    continue;
+                                }
+                            };
+
+                            let rows: Vec<ConversionGraphRow> = ledger.active_conversion_graphs().map(|conversion_graph| ConversionGraphRow {
                                 graph: conversion_graph.graph.clone(),
                                 rate: conversion_graph.rate,
                                 rate_since: conversion_graph.rate_since,
                             }).collect();
+                            let (rows, page_count) = paginate(rows, page, size);
+
+                            if page > page_count {
+                                println!("Page {} is past the last page, page {}", page, page_count);
+                                continue;
+                            }
 
-                            let table = Table::new(rows);
-                            println!("{}", table);
+                            print_rows(rows, output_mode);
+                            if output_mode == OutputMode::Table {
+                                println!("page {} of {}", page, page_count);
+                            }
+                            continue;
+                        }
+                        other if other.starts_with("conversions ") => {
+                            let system_id = other["conversions ".len()..].trim();
+                            let rows: Vec<ConversionGraphRow> = ledger.conversions_for_system(system_id).into_iter().map(|conversion_graph| ConversionGraphRow {
+                                graph: conversion_graph.graph.clone(),
+                                rate: conversion_graph.rate,
+                                rate_since: conversion_graph.rate_since,
+                            }).collect();
+
+                            print_rows(rows, output_mode);
                             continue;
                         }
                         _ => {
@@ -193,6 +962,50 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    ledger.flush()?;
     ledger.persist_indexes()?;
     Ok(())
 }
+
+/// Prints `rows` as a table or as JSON, depending on `mode`
+fn print_rows<T: Tabled + Serialize>(rows: Vec<T>, mode: OutputMode) {
+    match render_rows(rows, mode) {
+        Ok(rendered) => println!("{}", rendered),
+        Err(e) => {
+            println!("Error rendering rows");
+            println!("  {}", e);
+        }
+    }
+}
+
+/// `amount` the way the system `system_id` formats its amounts, plain when the system doesn't say
+/// or is unknown
+fn format_amount(ledger: &Ledger, system_id: &str, amount: f64) -> String {
+    ledger.find_system(system_id).map_or_else(|| amount.to_string(), |system| system.format_amount(amount))
+}
+
+/// One row per transaction with its entries listed by account name
+fn transaction_rows(ledger: &Ledger, transactions: Vec<&Transaction>) -> Vec<TransactionRow> {
+    let mut entries_by_transaction: HashMap<Uuid, Vec<&Entry>> = HashMap::new();
+    for entry in ledger.entries.iter() {
+        entries_by_transaction.entry(entry.transaction_id).or_default().push(entry);
+    }
+
+    transactions.into_iter().map(|transaction| {
+        let entries = entries_by_transaction.get(&transaction.id).map(Vec::as_slice).unwrap_or_default();
+
+        let lines: Vec<String> = entries.iter().map(|entry| match ledger.find_account(entry.account_id) {
+            Some(account) => format!("{}: {}", account.name, format_amount(ledger, &account.system_id, entry.amount)),
+            None => format!("{}: {}", entry.account_id, entry.amount),
+        }).collect();
+
+        TransactionRow {
+            id: transaction.id,
+            timestamp: transaction.timestamp,
+            description: transaction.description.clone(),
+            entries: lines.join("\n"),
+            net: entries.iter().fold(0.0, |net, entry| net + entry.amount),
+            metadata: transaction.metadata.as_ref().map(|metadata| metadata.to_string()).unwrap_or_default(),
+        }
+    }).collect()
+}
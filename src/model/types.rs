@@ -1,12 +1,49 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
 use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use uuid::Uuid;
 
+use crate::error::ZentryError;
+use crate::util::uuid::generate_deterministic_uuid;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct System {
     pub id: String,
     pub description: String,
+    /// Currency symbol amounts are shown with, e.g. `$`
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// Digits of the minor unit, e.g. 2 for USD and 0 for JPY
+    #[serde(default)]
+    pub decimal_places: Option<u8>,
+}
+
+impl System {
+    /// Whether `id` can name a system: non-empty, without whitespace, `<` or `>`, so it can sit
+    /// on either side of a graph key's arrow, e.g. `US-D -> BTC.e`.
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty() && !id.contains(|c: char| c.is_whitespace() || c == '<' || c == '>')
+    }
+
+    /// `amount` as shown to users: rounded to `decimal_places` when the system has them, and
+    /// prefixed with its `symbol`, e.g. `¥1235` for 1234.56 in a zero-decimal currency. The sign
+    /// goes before the symbol, `-¥1235`.
+    pub fn format_amount(&self, amount: f64) -> String {
+        let symbol = self.symbol.as_deref().unwrap_or("");
+        let digits = match self.decimal_places {
+            Some(decimal_places) => format!("{:.*}", decimal_places as usize, amount.abs()),
+            None => amount.abs().to_string(),
+        };
+
+        // no sign for an amount that rounds to zero, e.g. -0.001 at two places
+        let is_zero = digits.chars().all(|c| c == '0' || c == '.');
+        let sign = if amount.is_sign_negative() && !is_zero { "-" } else { "" };
+        format!("{}{}{}", sign, symbol, digits)
+    }
 }
 
 impl PartialEq for System {
@@ -20,7 +57,8 @@ impl Eq for System {}
 /// Represents a system conversion relationship between two systems.
 /// 
 /// # Format
-/// The graph is represented as a string in the format "A -> B", where A and B are system ids.
+/// The graph is represented as a string in the format "A -> B", where A and B are system ids,
+/// see [`GraphKey`] for parsing it.
 /// 
 /// # Examples
 /// Users can define the graph in several ways:
@@ -39,6 +77,10 @@ pub struct ConversionGraph {
     pub graph: String,
     pub rate: f64,
     pub rate_since: DateTime<Utc>,
+    /// `rate` as an exact fraction, when it was given as a decimal. Takes precedence over `rate`,
+    /// which is then only its `f64` approximation, see [`ExactRate`].
+    #[serde(default)]
+    pub exact_rate: Option<ExactRate>,
 }
 
 impl PartialEq for ConversionGraph {
@@ -49,13 +91,315 @@ impl PartialEq for ConversionGraph {
 
 impl Eq for ConversionGraph {}
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+impl ConversionGraph {
+    /// Splits an archived graph key of the form `start[A -> B]end` into its validity window
+    /// and the embedded relation. Returns `None` for active graphs or malformed keys.
+    /// A `#n` after `end` only tells apart archives sharing a window and is ignored here.
+    pub fn historical_window(&self) -> Option<(DateTime<Utc>, &str, DateTime<Utc>)> {
+        let open = self.graph.find('[')?;
+        let close = self.graph.rfind(']')?;
+        if close < open {
+            return None;
+        }
+
+        let end = &self.graph[close + 1..];
+        let end = end.split_once('#').map_or(end, |(end, _)| end);
+
+        let since = DateTime::parse_from_rfc3339(&self.graph[..open]).ok()?;
+        let until = DateTime::parse_from_rfc3339(end).ok()?;
+
+        Some((since.with_timezone(&Utc), &self.graph[open + 1..close], until.with_timezone(&Utc)))
+    }
+
+    pub fn is_historical(&self) -> bool {
+        self.historical_window().is_some()
+    }
+}
+
+/// A conversion rate kept as a reduced fraction instead of an `f64`, so the `1 / rate` of a `<->`
+/// and rates chained over several hops come out exact rather than drifting with every product.
+/// A decimal with an explicit scale, e.g. `1.0834` = `10834 / 10^4`, is one such fraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExactRate {
+    numerator: u64,
+    denominator: u64,
+}
+
+impl ExactRate {
+    pub const ONE: ExactRate = ExactRate { numerator: 1, denominator: 1 };
+
+    /// `None` for a zero numerator or denominator, a rate has to be positive and invertible
+    pub fn new(numerator: u64, denominator: u64) -> Option<Self> {
+        if numerator == 0 || denominator == 0 {
+            return None;
+        }
+
+        let divisor = gcd(numerator as u128, denominator as u128) as u64;
+        Some(Self {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        })
+    }
+
+    /// `mantissa / 10^scale`, `None` for a zero mantissa or a scale past what a `u64` holds
+    pub fn from_decimal(mantissa: u64, scale: u32) -> Option<Self> {
+        Self::new(mantissa, 10u64.checked_pow(scale)?)
+    }
+
+    /// Reads a plain decimal like `1.0834` or `15000`. Signs, exponents and values that
+    /// don't fit are `None`.
+    pub fn parse(decimal: &str) -> Option<Self> {
+        let (whole, fraction) = decimal.split_once('.').unwrap_or((decimal, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let digits = format!("{}{}", whole, fraction);
+        let mantissa = digits.parse::<u64>().ok()?;
+        Self::from_decimal(mantissa, fraction.len() as u32)
+    }
+
+    pub fn numerator(&self) -> u64 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> u64 {
+        self.denominator
+    }
+
+    pub fn reciprocal(&self) -> Self {
+        Self {
+            numerator: self.denominator,
+            denominator: self.numerator,
+        }
+    }
+
+    /// The rate of converting at `self` and then at `other`. `None` when the reduced result
+    /// no longer fits in a `u64` fraction.
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let numerator = self.numerator as u128 * other.numerator as u128;
+        let denominator = self.denominator as u128 * other.denominator as u128;
+        let divisor = gcd(numerator, denominator);
+
+        Some(Self {
+            numerator: u64::try_from(numerator / divisor).ok()?,
+            denominator: u64::try_from(denominator / divisor).ok()?,
+        })
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// Converts `amount` at `scale` decimal places. The amount is taken as a whole number of
+    /// `10^-scale` units, multiplied by the numerator and divided by the denominator once, rounding
+    /// half away from zero, so a rate of exactly one gives `amount` back at that scale. `None` for
+    /// a non-finite amount or when the product overflows an `i128`.
+    pub fn apply(&self, amount: f64, scale: u32) -> Option<f64> {
+        let factor = 10i128.checked_pow(scale)?;
+        let units = (amount * factor as f64).round();
+        if !units.is_finite() || units.abs() >= i128::MAX as f64 {
+            return None;
+        }
+
+        let product = (units as i128).checked_mul(self.numerator as i128)?;
+        let denominator = self.denominator as u128;
+        let (quotient, remainder) = (product.unsigned_abs() / denominator, product.unsigned_abs() % denominator);
+        let rounded = quotient + u128::from(remainder * 2 >= denominator);
+        let converted = if product < 0 { -(rounded as f64) } else { rounded as f64 };
+
+        Some(converted / factor as f64)
+    }
+}
+
+impl std::fmt::Display for ExactRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Arrow of a [`GraphKey`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphDirection {
+    /// `A -> B`
+    Forward,
+    /// `A <- B`, the same relation as `B -> A`
+    Backward,
+    /// `A <-> B`, stored as `A -> B` and `B -> A`
+    Both,
+}
+
+impl GraphDirection {
+    pub fn arrow(&self) -> &'static str {
+        match self {
+            GraphDirection::Forward => "->",
+            GraphDirection::Backward => "<-",
+            GraphDirection::Both => "<->",
+        }
+    }
+}
+
+/// A relation between two systems as users write it, e.g. `"USD <-> SGD"`, see [`ConversionGraph`].
+/// System ids are as [`System::is_valid_id`] allows; whitespace around the arrow is optional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphKey {
+    pub from: String,
+    pub direction: GraphDirection,
+    pub to: String,
+}
+
+impl GraphKey {
+    pub fn parse(key: &str) -> Result<Self, ZentryError> {
+        let invalid = || ZentryError::InvalidData(format!(
+            "invalid graph format: {}. must be `A -> B`, `A <- B` or `A <-> B`", key
+        ));
+
+        // `<->` contains both one-way arrows, so it is looked for first
+        let (from, direction, to) = [GraphDirection::Both, GraphDirection::Forward, GraphDirection::Backward]
+            .into_iter()
+            .find_map(|direction| {
+                key.split_once(direction.arrow()).map(|(from, to)| (from.trim(), direction, to.trim()))
+            })
+            .ok_or_else(invalid)?;
+
+        if !System::is_valid_id(from) || !System::is_valid_id(to) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            from: from.to_string(),
+            direction,
+            to: to.to_string(),
+        })
+    }
+
+    /// The canonical `"A -> B"` key active graphs are stored under. `A <- B` becomes `B -> A`;
+    /// `A <-> B` gives its `A -> B` half, see [`GraphKey::reversed`] for the other.
+    pub fn to_storage_key(&self) -> String {
+        match self.direction {
+            GraphDirection::Backward => format!("{} -> {}", self.to, self.from),
+            GraphDirection::Forward | GraphDirection::Both => format!("{} -> {}", self.from, self.to),
+        }
+    }
+
+    /// The same arrow between the swapped systems, `B <-> A` for `A <-> B`
+    pub fn reversed(&self) -> Self {
+        Self {
+            from: self.to.clone(),
+            direction: self.direction,
+            to: self.from.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for GraphKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.from, self.direction.arrow(), self.to)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum AccountType {
     Asset,
     Liability,
     Equity,
     Revenue,
     Expense,
+    /// A type added with [`register_account_type`], by its byte in the binary layout.
+    /// A byte nothing registered decodes to this as well, so accounts of a type the running program
+    /// doesn't know still load. They are debit-normal and labelled `Custom(<byte>)`.
+    Custom(u8),
+}
+
+/// Lowest byte a custom account type may use, the built-in types take 0 to 4
+pub const FIRST_CUSTOM_ACCOUNT_TYPE: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CustomAccountType {
+    label: String,
+    debit_normal: bool,
+}
+
+/// Custom account types by byte, shared by every ledger in the process
+static CUSTOM_ACCOUNT_TYPES: Lazy<RwLock<BTreeMap<u8, CustomAccountType>>> = Lazy::new(|| RwLock::new(BTreeMap::new()));
+
+/// Adds an account type beyond the built-in five, e.g. `ContraAsset`, and returns it. Programs
+/// register their types at startup, before loading a ledger; only the byte is stored with each
+/// account, so a type must keep its byte across runs. Registering the same byte, label and side
+/// again is a no-op. The label must not contain whitespace, and no two types may share a label,
+/// compared case-insensitively like [`crate::storage::account_type_from_label`] does.
+pub fn register_account_type(byte: u8, label: &str, debit_normal: bool) -> Result<AccountType, ZentryError> {
+    if byte < FIRST_CUSTOM_ACCOUNT_TYPE {
+        return Err(ZentryError::InvalidData(format!(
+            "account type byte {} is reserved, custom types start at {}", byte, FIRST_CUSTOM_ACCOUNT_TYPE
+        )));
+    }
+    if label.is_empty() || label.contains(char::is_whitespace) {
+        return Err(ZentryError::InvalidData(format!("account type label must be non-empty without whitespace: {:?}", label)));
+    }
+
+    let custom = CustomAccountType { label: label.to_string(), debit_normal };
+    let mut registry = CUSTOM_ACCOUNT_TYPES.write().unwrap_or_else(|e| e.into_inner());
+
+    match registry.get(&byte) {
+        Some(existing) if *existing == custom => return Ok(AccountType::Custom(byte)),
+        Some(existing) => {
+            return Err(ZentryError::AlreadyExists(format!("account type byte {} is already registered as {}", byte, existing.label)));
+        }
+        None => {}
+    }
+
+    let builtin = ["Asset", "Liability", "Equity", "Revenue", "Expense"];
+    if builtin.iter().any(|name| name.eq_ignore_ascii_case(label))
+        || registry.values().any(|other| other.label.eq_ignore_ascii_case(label))
+    {
+        return Err(ZentryError::AlreadyExists(format!("account type label already in use: {}", label)));
+    }
+
+    registry.insert(byte, custom);
+    Ok(AccountType::Custom(byte))
+}
+
+/// Every registered custom account type, by byte
+pub fn custom_account_types() -> Vec<AccountType> {
+    let registry = CUSTOM_ACCOUNT_TYPES.read().unwrap_or_else(|e| e.into_inner());
+    registry.keys().map(|byte| AccountType::Custom(*byte)).collect()
+}
+
+impl AccountType {
+    /// Whether the account normally carries a debit (positive) balance. Assets and expenses grow
+    /// with debits; liabilities, equity and revenue grow with credits. Custom types say so when
+    /// they are registered.
+    pub fn is_debit_normal(&self) -> bool {
+        match self {
+            AccountType::Custom(byte) => {
+                let registry = CUSTOM_ACCOUNT_TYPES.read().unwrap_or_else(|e| e.into_inner());
+                registry.get(byte).is_none_or(|custom| custom.debit_normal)
+            }
+            _ => matches!(self, AccountType::Asset | AccountType::Expense),
+        }
+    }
+
+    /// Name shown to users, e.g. `Asset`, or the registered label of a custom type
+    pub fn label(&self) -> String {
+        match self {
+            AccountType::Custom(byte) => {
+                let registry = CUSTOM_ACCOUNT_TYPES.read().unwrap_or_else(|e| e.into_inner());
+                registry.get(byte).map(|custom| custom.label.clone()).unwrap_or_else(|| format!("Custom({})", byte))
+            }
+            _ => format!("{:?}", self),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +409,28 @@ pub struct Account {
     pub account_type: AccountType,
     pub created_at: DateTime<Utc>,
     pub system_id: String,
+    /// Raw `Account::id` of the account this one is grouped under, `None` for top-level accounts
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+}
+
+impl Account {
+    /// A top-level account whose `id` is derived from `system_id` and `name`, so building the
+    /// same account twice, e.g. re-importing a chart of accounts, gives the same id and
+    /// `create_account` turns the second one away instead of storing a duplicate.
+    pub fn deterministic(system_id: &str, name: &str, account_type: AccountType, created_at: DateTime<Utc>) -> Self {
+        // the length prefix keeps e.g. ("A", "B:C") and ("A:B", "C") from sharing a key
+        let key = format!("{}:{}:{}", system_id.len(), system_id, name);
+
+        Account {
+            id: generate_deterministic_uuid(&key),
+            name: name.to_string(),
+            account_type,
+            created_at,
+            system_id: system_id.to_string(),
+            parent_id: None,
+        }
+    }
 }
 
 impl PartialEq for Account {
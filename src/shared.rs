@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::db::{Ledger, MetadataValidator};
+use crate::error::ZentryError;
+use crate::model::{Account, ConversionGraph, Entry, System, Transaction};
+
+/// A [`Ledger`] that can be cloned into several threads, e.g. the handlers of a small server.
+///
+/// Every call holds one lock for its whole duration, so:
+/// - the storage's readers, writers and record cache, which sit in `RefCell`s, are only touched
+///   by one thread at a time. That is also why this is a `Mutex` and not an `RwLock`: the
+///   `RefCell`s make `Ledger` `Send` but not `Sync`, so even reads need exclusive access.
+/// - a record is appended, indexed and put in the in-memory maps before another call can run,
+///   no thread sees a record on disk that the indexes don't know about yet.
+/// - the checks a write makes (unique ids, known systems, monotonic `rate_since`) and the write
+///   itself happen under the same lock, two threads can't both pass the check for the same id.
+///
+/// A panic while the lock is held poisons it and every later call panics too, rather than
+/// carrying on with a ledger that may have been left halfway through a write.
+#[derive(Debug, Clone)]
+pub struct SharedLedger {
+    inner: Arc<Mutex<Ledger>>,
+}
+
+impl SharedLedger {
+    pub fn new(ledger: Ledger) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ledger)),
+        }
+    }
+
+    pub fn load_from_dir(dir: &Path) -> Result<Self, ZentryError> {
+        Ledger::load_from_dir(dir).map(Self::new)
+    }
+
+    pub fn load_read_only(dir: &Path) -> Result<Self, ZentryError> {
+        Ledger::load_read_only(dir).map(Self::new)
+    }
+
+    /// Locks the ledger for several calls in a row, e.g. a read that must see a consistent state.
+    /// Other threads block until the guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, Ledger> {
+        self.inner.lock().expect("ledger lock poisoned by a panic in another thread")
+    }
+
+    /// Runs `f` with the ledger locked, for reads that borrow from it like [`Ledger::accounts`].
+    pub fn with<R>(&self, f: impl FnOnce(&Ledger) -> R) -> R {
+        f(&self.lock())
+    }
+
+    /// Gives back the ledger once no other clone is left
+    pub fn into_inner(self) -> Option<Ledger> {
+        Arc::into_inner(self.inner).map(|mutex| mutex.into_inner().expect("ledger lock poisoned by a panic in another thread"))
+    }
+
+    pub fn create_system(&self, system: System) -> Result<(), ZentryError> {
+        self.lock().create_system(system)
+    }
+
+    pub fn create_account(&self, account: Account) -> Result<(), ZentryError> {
+        self.lock().create_account(account)
+    }
+
+    pub fn update_account(&self, id: Uuid, new: Account) -> Result<(), ZentryError> {
+        self.lock().update_account(id, new)
+    }
+
+    pub fn delete_account(&self, id: Uuid) -> Result<(), ZentryError> {
+        self.lock().delete_account(id)
+    }
+
+    pub fn create_conversion_graph(&self, graph: ConversionGraph) -> Result<(), ZentryError> {
+        self.lock().create_conversion_graph(graph)
+    }
+
+    pub fn delete_conversion_graph(&self, graph: &str) -> Result<(), ZentryError> {
+        self.lock().delete_conversion_graph(graph)
+    }
+
+    pub fn new_entry(&self, account_id: Uuid, amount: f64) -> Result<Entry, ZentryError> {
+        self.lock().new_entry(account_id, amount)
+    }
+
+    pub fn set_metadata_validator(&self, validator: Option<MetadataValidator>) {
+        self.lock().set_metadata_validator(validator)
+    }
+
+    pub fn record_transaction(&self, tx: Transaction, entries: Vec<Entry>) -> Result<(), ZentryError> {
+        self.lock().record_transaction(tx, entries)
+    }
+
+    pub fn record_transaction_idempotent(&self, key: &str, tx: Transaction, entries: Vec<Entry>) -> Result<Transaction, ZentryError> {
+        self.lock().record_transaction_idempotent(key, tx, entries)
+    }
+
+    pub fn account_balance(&self, account_id: Uuid) -> f64 {
+        self.lock().account_balance(account_id)
+    }
+
+    pub fn rate_at(&self, from: &str, to: &str, at: DateTime<Utc>) -> Option<f64> {
+        self.lock().rate_at(from, to, at)
+    }
+
+    pub fn flush(&self) -> Result<(), ZentryError> {
+        self.lock().flush()
+    }
+
+    pub fn persist_indexes(&self) -> Result<(), ZentryError> {
+        self.lock().persist_indexes()
+    }
+
+    pub fn compact(&self) -> Result<(), ZentryError> {
+        self.lock().compact()
+    }
+
+    pub fn snapshot(&self, dir: &Path) -> Result<PathBuf, ZentryError> {
+        self.lock().snapshot(dir)
+    }
+
+    pub fn restore(&self, snapshot: &Path) -> Result<(), ZentryError> {
+        self.lock().restore(snapshot)
+    }
+}
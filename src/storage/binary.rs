@@ -1,15 +1,21 @@
 use std::borrow::Borrow;
-use std::cell::RefCell;
-use std::fs::File;
-use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::collections::HashMap;
+use std::path::Path;
 
 use regex::Regex;
 use uuid::Uuid;
-use chrono::{TimeZone, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 
-use crate::model::{Account, AccountType, Entry, Transaction, System, ConversionGraph};
-use crate::storage::layout::{BinaryLayout, BinaryField, LengthType};
+use crate::error::ZentryError;
+use crate::model::{custom_account_types, Account, AccountType, Entry, Transaction, System, ConversionGraph, ExactRate, GraphKey, FIRST_CUSTOM_ACCOUNT_TYPE};
+use crate::storage::cache::RecordCache;
+use crate::storage::layout::{
+    file_header, BinaryLayout, BinaryField, LengthType, ACCOUNT_PARENT_LAYOUT_VERSION, CHECKSUM_LAYOUT_VERSION,
+    FILE_HEADER_LEN, FILE_MAGIC, LEGACY_LAYOUT_VERSION,
+};
 
 use bimap::BiMap;
 use once_cell::sync::Lazy;
@@ -26,74 +32,164 @@ static ACCOUNT_TYPE_BIMAP: Lazy<BiMap<u8, AccountType>> = Lazy::new(|| {
     map
 });
 
-fn account_type_from_u8(byte: u8) -> Option<AccountType> {
-    ACCOUNT_TYPE_BIMAP.get_by_left(&byte).cloned()
-}
+static ACTIVE_GRAPH_RECORD_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^C\[(.*)\]$").unwrap());
+static HISTORICAL_GRAPH_RECORD_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^H\[(\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?[+-]\d{2}:\d{2}\[.*\]\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?[+-]\d{2}:\d{2}(?:#\d+)?)\]$"
+    ).unwrap()
+});
 
-fn account_type_to_u8(account_type: &AccountType) -> Option<u8> {
-    ACCOUNT_TYPE_BIMAP.get_by_right(account_type).cloned()
+/// Bytes past the built-in types decode to `AccountType::Custom`, registered or not
+fn account_type_from_u8(byte: u8) -> AccountType {
+    ACCOUNT_TYPE_BIMAP.get_by_left(&byte).cloned().unwrap_or(AccountType::Custom(byte))
 }
 
-enum BinaryReadError {
-    DeadRecord,
+/// `None` for a custom type using a built-in type's byte, which would read back as that type
+fn account_type_to_u8(account_type: &AccountType) -> Option<u8> {
+    match account_type {
+        AccountType::Custom(byte) => (*byte >= FIRST_CUSTOM_ACCOUNT_TYPE).then_some(*byte),
+        _ => ACCOUNT_TYPE_BIMAP.get_by_right(account_type).cloned(),
+    }
 }
 
-enum BinaryWriteError {
-    TryingToTombstoneWrongRecord,
+/// Name an account type is stored under by layouts that keep it as a string: the `Serialize` name
+/// of a built-in type, e.g. `Asset`, and `Custom(<byte>)` for the rest, registered or not.
+/// `None` for a custom type using a built-in type's byte, like [`account_type_to_u8`].
+fn account_type_to_name(account_type: &AccountType) -> Option<String> {
+    match account_type {
+        AccountType::Custom(byte) => (*byte >= FIRST_CUSTOM_ACCOUNT_TYPE).then(|| format!("Custom({})", byte)),
+        _ => serde_json::to_value(account_type).ok()?.as_str().map(str::to_string),
+    }
 }
 
-impl From<BinaryReadError> for std::io::Error {
-    fn from(error: BinaryReadError) -> Self {
-        match error {
-            BinaryReadError::DeadRecord => std::io::Error::new(ErrorKind::Other, "dead record"),
-        }
+/// Reverse of [`account_type_to_name`], exact and case-sensitive. Any other name is corruption.
+fn account_type_from_name(name: &str) -> Result<AccountType, ZentryError> {
+    let custom_byte = name.strip_prefix("Custom(")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .and_then(|byte| byte.parse::<u8>().ok())
+        .filter(|byte| *byte >= FIRST_CUSTOM_ACCOUNT_TYPE);
+    if let Some(byte) = custom_byte {
+        return Ok(AccountType::Custom(byte));
     }
+
+    serde_json::from_value::<AccountType>(serde_json::Value::String(name.to_string()))
+        .map_err(|_| ZentryError::InvalidData(format!("unknown account type name: {:?}", name)))
 }
 
-impl From<BinaryWriteError> for std::io::Error {
-    fn from(error: BinaryWriteError) -> Self {
-        match error {
-            BinaryWriteError::TryingToTombstoneWrongRecord => std::io::Error::new(ErrorKind::Other, "trying to tombstone wrong record"),
-        }
-    }
+/// Matches `label` case-insensitively against the built-in account types and the registered
+/// custom ones, e.g. `asset`.
+pub fn account_type_from_label(label: &str) -> Option<AccountType> {
+    ACCOUNT_TYPE_BIMAP.right_values()
+        .cloned()
+        .chain(custom_account_types())
+        .find(|account_type| account_type.label().eq_ignore_ascii_case(label))
 }
 
+/// Status byte every record starts with while it is live
+pub const STATUS_LIVE: u8 = 0x01;
+
+/// Status byte a tombstoned record is overwritten with. Any byte other than this and
+/// [`STATUS_LIVE`] where a record starts means the framing is off, and is reported as
+/// [`ZentryError::CorruptStatus`] instead of being read as either.
+pub const STATUS_DEAD: u8 = 0x00;
+
+/// Longest length-prefixed field a record may hold. A declared length above it can only come from
+/// a corrupted prefix, and is rejected before anything is allocated for it.
+pub const MAX_FIELD_LEN: usize = 16 * 1024 * 1024;
+
 pub trait TombstoneReader {
-    fn is_ignorable_error(&self, e: &std::io::Error) -> bool;
+    fn is_ignorable_error(&self, e: &ZentryError) -> bool;
 
     fn is_tombstone_byte(&self, byte: u8) -> bool {
-        byte == 0x00
+        byte == STATUS_DEAD
     }
 
-    fn read_or_skip<T>(&self) -> std::io::Result<T>
+    fn read_or_skip<T>(&self) -> Result<T, ZentryError>
     where
         T: FromBinary;
 
-    fn read<T>(&self) -> std::io::Result<Vec<T>>
+    fn read<T>(&self) -> Result<Vec<T>, ZentryError>
     where
         T: FromBinary;
 }
 
 pub trait TombstoneWriter {
-    fn tombstone<T>(&self, item: T, offset: u64) -> std::io::Result<()>
+    fn tombstone<T>(&self, item: T, offset: u64) -> Result<(), ZentryError>
     where
         T: FromBinary + PartialEq;
 
-    fn write<T>(&self, item: T) -> std::io::Result<(u64, T)>
+    fn write<T>(&self, item: T) -> Result<(u64, T), ZentryError>
     where
         T: ToBinary;
+
+    fn write_batch<T>(&self, items: Vec<T>) -> Result<Vec<(u64, T)>, ZentryError>
+    where
+        T: ToBinary;
+}
+
+/// A model stored in a record file of its own
+pub trait BinaryRecord {
+    /// Key of the record file in the storage's reader, writer and layout maps
+    const TYPE_KEY: &'static str;
+}
+
+impl BinaryRecord for Account {
+    const TYPE_KEY: &'static str = "accounts";
+}
+
+impl BinaryRecord for Transaction {
+    const TYPE_KEY: &'static str = "transactions";
+}
+
+impl BinaryRecord for Entry {
+    const TYPE_KEY: &'static str = "entries";
+}
+
+impl BinaryRecord for System {
+    const TYPE_KEY: &'static str = "systems";
 }
 
-pub trait ToBinary {
-    fn to_binary(&self, writer: &mut BufWriter<File>, layout: &BinaryLayout) -> std::io::Result<()>;
+impl BinaryRecord for ConversionGraph {
+    const TYPE_KEY: &'static str = "conversion_graphs";
 }
 
-pub trait FromBinary {
-    fn from_binary(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<Self>
+pub trait ToBinary: BinaryRecord {
+    fn to_binary<W: Write>(&self, writer: &mut W, layout: &BinaryLayout) -> Result<(), ZentryError>;
+}
+
+pub trait FromBinary: BinaryRecord {
+    fn from_binary<R: Read>(reader: &mut R, layout: &BinaryLayout) -> Result<Self, ZentryError>
     where
         Self: Sized;
 
-    fn skip_bytes(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<()>;
+    /// Reads past a record body without decoding it. Driven only by the layout's fields, so every
+    /// record type skips the same way and a new field type only needs handling here.
+    fn skip_bytes<R: Read>(reader: &mut R, layout: &BinaryLayout) -> Result<(), ZentryError> {
+        for field in &layout.fields {
+            match field {
+                BinaryField::Uuid(_) => { reader.read_exact(&mut [0u8; 16])?; }
+                BinaryField::OptionalUuid(name) => { read_optional_uuid(reader, name)?; }
+                BinaryField::U8(_) => { reader.read_exact(&mut [0u8; 1])?; }
+                BinaryField::OptionalU8(name) => { read_optional_u8(reader, name)?; }
+                BinaryField::U32(_) => { reader.read_exact(&mut [0u8; 4])?; }
+                BinaryField::I64(_) | BinaryField::U64(_) | BinaryField::F64(_) => { reader.read_exact(&mut [0u8; 8])?; }
+                BinaryField::LengthPrefixed { length_type, name } => {
+                    let len = read_length_prefix(reader, length_type, name)?;
+                    let skipped = std::io::copy(&mut reader.take(len as u64), &mut std::io::sink())?;
+                    if skipped < len as u64 {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::UnexpectedEof,
+                            format!("`{}` declares {} bytes but only {} remain", name, len, skipped),
+                        ).into());
+                    }
+                }
+                BinaryField::OptionalLengthPrefixed { length_type, name } => {
+                    read_optional_length_prefixed_bytes(reader, length_type, name)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -101,36 +197,128 @@ pub struct BinaryStorage {
     readers: RefCell<HashMap<String, BufReader<File>>>,
     writers: RefCell<HashMap<String, BufWriter<File>>>,
     layouts: HashMap<String, BinaryLayout>,
+    /// Offset of the first record in each file, past the header if there is one
+    data_offsets: HashMap<String, u64>,
+    /// Sync every record to disk as it is written instead of leaving it to [`BinaryStorage::flush`]
+    pub durable: bool,
+    /// Opened without writers, see [`BinaryStorage::new_read_only`]
+    read_only: bool,
+    cache: RefCell<RecordCache>,
+    /// Records `read_single` had to decode from disk, cache hits are not counted
+    disk_reads: Cell<u64>,
 }
 
 impl BinaryStorage {
-    pub fn new(readers: HashMap<String, BufReader<File>>, writers: HashMap<String, BufWriter<File>>, layouts: HashMap<String, BinaryLayout>) -> Self {
+    /// Takes the reader, writer and layout of each record file. The header of every file is
+    /// validated and the layout adjusted to the version it declares, see `open_versioned`.
+    /// `cache_capacity` bounds how many records `read_single` keeps decoded, 0 disables the cache.
+    pub fn new(mut readers: HashMap<String, BufReader<File>>, mut writers: HashMap<String, BufWriter<File>>, mut layouts: HashMap<String, BinaryLayout>, cache_capacity: usize) -> Result<Self, ZentryError> {
+        let mut data_offsets = HashMap::new();
+
+        for (type_key, layout) in layouts.iter_mut() {
+            let reader = readers.get_mut(type_key)
+                .ok_or(ZentryError::MissingHandle("reader"))?;
+            let writer = writers.get_mut(type_key)
+                .ok_or(ZentryError::MissingHandle("writer"))?;
+
+            let data_offset = open_versioned(reader, Some(writer), layout)?;
+            data_offsets.insert(type_key.clone(), data_offset);
+        }
+
+        Ok(Self::with_handles(readers, writers, layouts, data_offsets, false, cache_capacity))
+    }
+
+    /// Like [`BinaryStorage::new`] without any writers: nothing, not even the header of an empty
+    /// file, is written, and `write`, `write_batch`, `tombstone` and `compact` fail with
+    /// [`ZentryError::ReadOnly`].
+    pub fn new_read_only(mut readers: HashMap<String, BufReader<File>>, mut layouts: HashMap<String, BinaryLayout>, cache_capacity: usize) -> Result<Self, ZentryError> {
+        let mut data_offsets = HashMap::new();
+
+        for (type_key, layout) in layouts.iter_mut() {
+            let reader = readers.get_mut(type_key)
+                .ok_or(ZentryError::MissingHandle("reader"))?;
+
+            let data_offset = open_versioned(reader, None, layout)?;
+            data_offsets.insert(type_key.clone(), data_offset);
+        }
+
+        Ok(Self::with_handles(readers, HashMap::new(), layouts, data_offsets, true, cache_capacity))
+    }
+
+    fn with_handles(
+        readers: HashMap<String, BufReader<File>>,
+        writers: HashMap<String, BufWriter<File>>,
+        layouts: HashMap<String, BinaryLayout>,
+        data_offsets: HashMap<String, u64>,
+        read_only: bool,
+        cache_capacity: usize,
+    ) -> Self {
         Self {
             readers: RefCell::new(readers),
             writers: RefCell::new(writers),
             layouts,
+            data_offsets,
+            durable: false,
+            read_only,
+            cache: RefCell::new(RecordCache::new(cache_capacity)),
+            disk_reads: Cell::new(0),
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn check_writable(&self) -> Result<(), ZentryError> {
+        if self.read_only {
+            return Err(ZentryError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    pub fn disk_reads(&self) -> u64 {
+        self.disk_reads.get()
+    }
+
+    /// Flushes every writer and syncs its file, so everything written so far survives a crash.
+    pub fn flush(&self) -> Result<(), ZentryError> {
+        for writer in self.writers.borrow_mut().values_mut() {
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Reads the live record at `offset`, from the cache when it was read recently.
+    /// A hit skips the seek, which throws away the reader's buffer, as well as the decode and
+    /// checksum.
+    pub fn read_single<T>(&self, offset: u64) -> Result<T, ZentryError>
+    where
+        T: FromBinary + Clone + Send + 'static,
+    {
+        let type_key = T::TYPE_KEY;
+
+        if let Some(item) = self.cache.borrow_mut().get::<T>(type_key, offset) {
+            return Ok(item);
         }
+
+        let item = self.read_from_disk::<T>(type_key, offset)?;
+        self.cache.borrow_mut().insert(type_key, offset, item.clone());
+        Ok(item)
     }
 
-    pub fn read_single<T>(&self, offset: u64) -> std::io::Result<T>
+    fn read_from_disk<T>(&self, type_key: &str, offset: u64) -> Result<T, ZentryError>
     where
         T: FromBinary,
     {
-        let type_key = match std::any::type_name::<T>() {
-            t if t.contains("Account") => "accounts",
-            t if t.contains("Transaction") => "transactions",
-            t if t.contains("Entry") => "entries",
-            t if t.contains("System") => "systems",
-            t if t.contains("ConversionGraph") => "conversion_graphs",
-            _ => return Err(std::io::Error::new(ErrorKind::Other, "unsupported type"))
-        };
+        self.disk_reads.set(self.disk_reads.get() + 1);
 
         let mut readers = self.readers.borrow_mut();
         let reader = readers.get_mut(type_key)
-            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "no reader found for type"))?;
+            .ok_or(ZentryError::MissingHandle("reader"))?;
 
         let layout = self.layouts.get(type_key)
-            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "no layout found for type"))?;
+            .ok_or(ZentryError::MissingHandle("layout"))?;
 
         reader.seek(SeekFrom::Start(offset))?;
 
@@ -138,20 +326,160 @@ impl BinaryStorage {
         reader.read_exact(&mut tombstone_buf)?;
 
         if self.is_tombstone_byte(tombstone_buf[0]) {
-            return Err(BinaryReadError::DeadRecord.into())
+            return Err(ZentryError::DeadRecord)
         }
+        check_live_status(tombstone_buf[0], offset)?;
 
-        T::from_binary(reader, layout)
+        read_record(reader, layout)
+    }
+
+    /// Reads every live record from the start of its file along with the offset it was found at,
+    /// the offset an index entry for it points to.
+    pub fn read_with_offsets<T>(&self) -> Result<Vec<(u64, T)>, ZentryError>
+    where
+        T: FromBinary,
+    {
+        let type_key = T::TYPE_KEY;
+
+        let data_offset = self.data_offsets.get(type_key).copied().unwrap_or(0);
+
+        self.readers.borrow_mut().get_mut(type_key)
+            .ok_or(ZentryError::MissingHandle("reader"))?
+            .seek(SeekFrom::Start(data_offset))?;
+
+        let mut items = Vec::new();
+
+        loop {
+            let offset = self.readers.borrow_mut().get_mut(type_key)
+                .ok_or(ZentryError::MissingHandle("reader"))?
+                .stream_position()?;
+
+            match self.read_or_skip::<T>() {
+                Ok(item) => items.push((offset, item)),
+                Err(e) if e.is_eof() => break,
+                Err(e) if self.is_ignorable_error(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Reads up to `count` live records starting at `start_offset`, for paging through a file
+    /// without materializing all of it. Tombstoned and unreadable records are skipped and don't
+    /// count toward the page. Returns the page and the offset to resume from, `None` once the end
+    /// of the file is reached. An offset before the file header, e.g. 0, starts at the first record.
+    pub fn read_range<T>(&self, start_offset: u64, count: usize) -> Result<(Vec<T>, Option<u64>), ZentryError>
+    where
+        T: FromBinary,
+    {
+        let type_key = T::TYPE_KEY;
+
+        let data_offset = self.data_offsets.get(type_key).copied().unwrap_or(0);
+
+        let mut readers = self.readers.borrow_mut();
+        let reader = readers.get_mut(type_key)
+            .ok_or(ZentryError::MissingHandle("reader"))?;
+
+        let layout = self.layouts.get(type_key)
+            .ok_or(ZentryError::MissingHandle("layout"))?;
+
+        reader.seek(SeekFrom::Start(start_offset.max(data_offset)))?;
+
+        let mut items = Vec::with_capacity(count);
+
+        while items.len() < count {
+            match read_or_skip_record::<T, _>(reader, layout, |byte| self.is_tombstone_byte(byte), |e| self.is_ignorable_error(e)) {
+                Ok(item) => items.push(item),
+                Err(e) if e.is_eof() => return Ok((items, None)),
+                Err(e) if self.is_ignorable_error(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let next_offset = reader.stream_position()?;
+        if next_offset >= reader.get_ref().metadata()?.len() {
+            return Ok((items, None));
+        }
+
+        Ok((items, Some(next_offset)))
+    }
+
+    /// Layout of the file holding `T` records and the offset its first record starts at, what
+    /// [`read_file_with_offsets`] needs to scan that file through a handle of its own.
+    pub fn file_layout<T>(&self) -> Result<(&BinaryLayout, u64), ZentryError>
+    where
+        T: BinaryRecord,
+    {
+        let type_key = T::TYPE_KEY;
+
+        let layout = self.layouts.get(type_key)
+            .ok_or(ZentryError::MissingHandle("layout"))?;
+
+        Ok((layout, self.data_offsets.get(type_key).copied().unwrap_or(0)))
+    }
+
+    /// Rewrites the record file at `path` keeping only live records, then swaps it in place of
+    /// the old file and reopens the handles. Returns the surviving records with their new offsets.
+    pub fn compact<T>(&self, path: &Path) -> Result<Vec<(u64, T)>, ZentryError>
+    where
+        T: FromBinary + ToBinary,
+    {
+        self.check_writable()?;
+
+        let type_key = T::TYPE_KEY;
+
+        let data_offset = self.data_offsets.get(type_key).copied().unwrap_or(0);
+
+        self.readers.borrow_mut().get_mut(type_key)
+            .ok_or(ZentryError::MissingHandle("reader"))?
+            .seek(SeekFrom::Start(data_offset))?;
+
+        let items = self.read::<T>()?;
+
+        let layout = self.layouts.get(type_key)
+            .ok_or(ZentryError::MissingHandle("layout"))?;
+
+        let compacted_path = path.with_extension("compact");
+        let mut compacted = BufWriter::new(File::create(&compacted_path)?);
+        let mut survivors = Vec::with_capacity(items.len());
+
+        // the compacted file keeps the version of the one it replaces
+        if data_offset > 0 {
+            compacted.write_all(&file_header(layout.version))?;
+        }
+
+        for item in items {
+            let offset = compacted.stream_position()?;
+            item.to_binary(&mut compacted, layout)?;
+            survivors.push((offset, item));
+        }
+
+        compacted.flush()?;
+        compacted.get_ref().sync_all()?;
+        drop(compacted);
+
+        std::fs::rename(&compacted_path, path)?;
+        self.cache.borrow_mut().invalidate_file(type_key);
+
+        self.readers.borrow_mut().insert(type_key.to_string(), BufReader::new(File::open(path)?));
+        self.writers.borrow_mut().insert(
+            type_key.to_string(),
+            BufWriter::new(OpenOptions::new().write(true).create(true).truncate(false).open(path)?),
+        );
+
+        Ok(survivors)
     }
 }
 
 impl FromBinary for Account {
-    fn from_binary(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<Self> {
+    fn from_binary<R: Read>(reader: &mut R, layout: &BinaryLayout) -> Result<Self, ZentryError> {
         let mut id = Uuid::nil();
         let mut name = String::new();
         let mut account_type = AccountType::Asset;
         let mut created_at = Utc.timestamp_opt(0, 0).unwrap();
         let mut system_id = String::new();
+        let mut parent_id = None;
 
         for field in &layout.fields {
             match field {
@@ -161,13 +489,15 @@ impl FromBinary for Account {
                     id = Uuid::from_bytes(buf);
                 }
                 BinaryField::LengthPrefixed { length_type, name: "name" } => {
-                    name = read_length_prefixed_string(reader, length_type)?;
+                    name = read_length_prefixed_string(reader, length_type, "name")?;
                 }
                 BinaryField::U8("account_type") => {
                     let mut buf = [0u8; 1];
                     reader.read_exact(&mut buf)?;
-                    account_type = account_type_from_u8(buf[0])
-                        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "unknown account type"))?;
+                    account_type = account_type_from_u8(buf[0]);
+                }
+                BinaryField::LengthPrefixed { length_type, name: "account_type" } => {
+                    account_type = account_type_from_name(&read_length_prefixed_string(reader, length_type, "account_type")?)?;
                 }
                 BinaryField::I64("created_at") => {
                     let mut buf = [0u8; 8];
@@ -175,31 +505,26 @@ impl FromBinary for Account {
                     let ts = i64::from_le_bytes(buf);
                     created_at = Utc.timestamp_opt(ts, 0).unwrap();
                 }
+                BinaryField::I64("created_at_nanos") => {
+                    let mut buf = [0u8; 8];
+                    reader.read_exact(&mut buf)?;
+                    created_at = Utc.timestamp_nanos(i64::from_le_bytes(buf));
+                }
                 BinaryField::LengthPrefixed { length_type, name: "system_id" } => {
-                    system_id = read_length_prefixed_string(reader, length_type)?;
+                    system_id = read_length_prefixed_string(reader, length_type, "system_id")?;
+                }
+                BinaryField::OptionalUuid("parent_id") => {
+                    parent_id = read_optional_uuid(reader, "parent_id")?;
                 }
                 _ => {}
             }
         }
-        Ok(Account { id, name, account_type, created_at, system_id })
-    }
-
-    fn skip_bytes(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        for field in &layout.fields {
-            match field {
-                BinaryField::Uuid(_) => { let mut buf = [0u8; 16]; reader.read_exact(&mut buf)?; }
-                BinaryField::U8(_) => { let mut buf = [0u8; 1]; reader.read_exact(&mut buf)?; }
-                BinaryField::I64(_) => { let mut buf = [0u8; 8]; reader.read_exact(&mut buf)?; }
-                BinaryField::LengthPrefixed { length_type, .. } => { let _ = read_length_prefixed_string(reader, length_type)?; }
-                _ => {}
-            }
-        }
-        Ok(())
+        Ok(Account { id, name, account_type, created_at, system_id, parent_id })
     }
 }
 
 impl FromBinary for Transaction {
-    fn from_binary(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<Self> {
+    fn from_binary<R: Read>(reader: &mut R, layout: &BinaryLayout) -> Result<Self, ZentryError> {
         let mut id = Uuid::nil();
         let mut description = String::new();
         let mut metadata: Option<serde_json::Value> = None;
@@ -213,10 +538,10 @@ impl FromBinary for Transaction {
                     id = Uuid::from_bytes(buf);
                 }
                 BinaryField::LengthPrefixed { length_type, name: "description" } => {
-                    description = read_length_prefixed_string(reader, length_type)?;
+                    description = read_length_prefixed_string(reader, length_type, "description")?;
                 }
                 BinaryField::LengthPrefixed { length_type, name: "metadata" } => {
-                    let json_str = read_length_prefixed_string(reader, length_type)?;
+                    let json_str = read_length_prefixed_string(reader, length_type, "metadata")?;
                     metadata = if json_str.is_empty() { None } else { serde_json::from_str(&json_str).ok() };
                 }
                 BinaryField::I64("timestamp") => {
@@ -225,27 +550,20 @@ impl FromBinary for Transaction {
                     let ts = i64::from_le_bytes(buf);
                     timestamp = Utc.timestamp_opt(ts, 0).unwrap();
                 }
+                BinaryField::I64("timestamp_nanos") => {
+                    let mut buf = [0u8; 8];
+                    reader.read_exact(&mut buf)?;
+                    timestamp = Utc.timestamp_nanos(i64::from_le_bytes(buf));
+                }
                 _ => {}
             }
         }
         Ok(Transaction { id, description, timestamp, metadata })
     }
-
-    fn skip_bytes(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        for field in &layout.fields {
-            match field {
-                BinaryField::Uuid(_) => { let mut buf = [0u8; 16]; reader.read_exact(&mut buf)?; }
-                BinaryField::I64(_) => { let mut buf = [0u8; 8]; reader.read_exact(&mut buf)?; }
-                BinaryField::LengthPrefixed { length_type, .. } => { let _ = read_length_prefixed_string(reader, length_type)?; }
-                _ => {}
-            }
-        }
-        Ok(())
-    }
 }
 
 impl FromBinary for Entry {
-    fn from_binary(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<Self> {
+    fn from_binary<R: Read>(reader: &mut R, layout: &BinaryLayout) -> Result<Self, ZentryError> {
         let mut id = Uuid::nil();
         let mut transaction_id = Uuid::nil();
         let mut account_id = Uuid::nil();
@@ -278,102 +596,59 @@ impl FromBinary for Entry {
         }
         Ok(Entry { id, transaction_id, account_id, amount })
     }
-
-    fn skip_bytes(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        for field in &layout.fields {
-            match field {
-                BinaryField::Uuid(_) => { let mut buf = [0u8; 16]; reader.read_exact(&mut buf)?; }
-                BinaryField::F64(_) => { let mut buf = [0u8; 8]; reader.read_exact(&mut buf)?; }
-                _ => {}
-            }
-        }
-        Ok(())
-    }
 }
 
 impl FromBinary for System {
-    fn from_binary(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<Self> {
+    fn from_binary<R: Read>(reader: &mut R, layout: &BinaryLayout) -> Result<Self, ZentryError> {
         let mut id = String::new();
         let mut description = String::new();
+        let mut symbol = None;
+        let mut decimal_places = None;
 
         for field in &layout.fields {
             match field {
                 BinaryField::LengthPrefixed { length_type, name: "system_id" } => {
-                    id = read_length_prefixed_string(reader, length_type)?;
+                    id = read_length_prefixed_string(reader, length_type, "system_id")?;
                 }
                 BinaryField::LengthPrefixed { length_type, name: "description" } => {
-                    description = read_length_prefixed_string(reader, length_type)?;
+                    description = read_length_prefixed_string(reader, length_type, "description")?;
+                }
+                BinaryField::OptionalLengthPrefixed { length_type, name: "symbol" } => {
+                    symbol = read_presence_byte(reader, "symbol")?
+                        .then(|| read_length_prefixed_string(reader, length_type, "symbol"))
+                        .transpose()?;
+                }
+                BinaryField::OptionalU8("decimal_places") => {
+                    decimal_places = read_optional_u8(reader, "decimal_places")?;
                 }
                 _ => {}
             }
         }
-        Ok(System { id, description })
-    }
-
-    fn skip_bytes(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        for field in &layout.fields {
-            match field {
-                BinaryField::LengthPrefixed { length_type, .. } => { let _ = read_length_prefixed_string(reader, length_type)?; }
-                _ => {}
-            }
-        }
-        Ok(())
+        Ok(System { id, description, symbol, decimal_places })
     }
 }
 
 impl FromBinary for ConversionGraph {
-    fn from_binary(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<Self> {
+    fn from_binary<R: Read>(reader: &mut R, layout: &BinaryLayout) -> Result<Self, ZentryError> {
         let mut graph = String::new();
         let mut rate = 0.0;
         let mut rate_since = Utc::now();
+        let mut rate_numerator = 0;
+        let mut rate_denominator = 0;
 
         for field in &layout.fields {
             match field {
                 BinaryField::LengthPrefixed { length_type, name: "graph" } => {
-                    let len = match length_type {
-                        LengthType::U8 => {
-                            let mut len_buf = [0u8; 1];
-                            reader.read_exact(&mut len_buf)?;
-                            len_buf[0] as usize
-                        }
-                        LengthType::U16 => {
-                            let mut len_buf = [0u8; 2];
-                            reader.read_exact(&mut len_buf)?;
-                            u16::from_le_bytes(len_buf) as usize
-                        }
-                        LengthType::U32 => {
-                            let mut len_buf = [0u8; 4];
-                            reader.read_exact(&mut len_buf)?;
-                            u32::from_le_bytes(len_buf) as usize
-                        }
-                    };
-
-                    let mut tag_buf = [0u8; 1];
-                    reader.read_exact(&mut tag_buf)?;
-                    let tag = tag_buf[0];
-
-                    if tag != b'C' {
-                        let skip_bytes = len + 8 + 8 - 1;
-                        let mut skip_buf = vec![0u8; skip_bytes];
-                        reader.read_exact(&mut skip_buf)?;
-
-                        return Err(std::io::Error::new(
-                            ErrorKind::InvalidData,
-                            "not converting historical conversion graph, will come in improvement later",
-                        ));
-                    }
-
-                    // Read the rest of the graph string (already read 1 byte for tag)
-                    let mut graph_buf = vec![0u8; len - 1];
-                    reader.read_exact(&mut graph_buf)?;
-                    let graph_with_key = String::from_utf8([vec![tag], graph_buf].concat()).unwrap_or_default();
-
-                    // Extract the value inside C[...]
-                    let re = Regex::new(r"C\[(.*?)\]").unwrap();
-                    if let Some(caps) = re.captures(&graph_with_key) {
-                        if let Some(g) = caps.get(1) {
-                            graph = g.as_str().to_string();
-                        }
+                    let graph_with_key = read_length_prefixed_string(reader, length_type, "graph")?;
+
+                    // Active graphs are stored as C[A -> B], archived ones as H[start[A -> B]end].
+                    // Historical records keep their full key so they index the same way they were archived.
+                    if let Some(caps) = ACTIVE_GRAPH_RECORD_PATTERN.captures(&graph_with_key) {
+                        graph = caps[1].to_string();
+                    } else if let Some(caps) = HISTORICAL_GRAPH_RECORD_PATTERN.captures(&graph_with_key) {
+                        graph = caps[1].to_string();
+                    } else {
+                        return Err(ZentryError::InvalidData(format!("unknown conversion graph record key: {}", graph_with_key)));
                     }
                 }
                 BinaryField::F64("rate") => {
@@ -387,71 +662,40 @@ impl FromBinary for ConversionGraph {
                     let timestamp = i64::from_le_bytes(buf);
                     rate_since = chrono::Utc.timestamp_opt(timestamp, 0).unwrap();
                 }
-                _ => {
-                    return Err(std::io::Error::new(
-                        ErrorKind::InvalidData,
-                        "invalid field for `ConversionGraph`",
-                    ));
+                BinaryField::I64("rate_since_nanos") => {
+                    let mut buf = [0u8; 8];
+                    reader.read_exact(&mut buf)?;
+                    rate_since = Utc.timestamp_nanos(i64::from_le_bytes(buf));
                 }
-            }
-        }
-
-        Ok(ConversionGraph { graph, rate, rate_since })
-    }
-
-    fn skip_bytes(reader: &mut BufReader<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        for field in &layout.fields {
-            match field {
-                BinaryField::LengthPrefixed { length_type, .. } => {
-                    let len = match length_type {
-                        LengthType::U8 => {
-                            let mut len_buf = [0u8; 1];
-                            reader.read_exact(&mut len_buf)?;
-                            len_buf[0] as usize
-                        }
-                        LengthType::U16 => {
-                            let mut len_buf = [0u8; 2];
-                            reader.read_exact(&mut len_buf)?;
-                            u16::from_le_bytes(len_buf) as usize
-                        }
-                        LengthType::U32 => {
-                            let mut len_buf = [0u8; 4];
-                            reader.read_exact(&mut len_buf)?;
-                            u32::from_le_bytes(len_buf) as usize
-                        }
-                    };
-                    // Skip the actual field data
-                    let mut skip_buf = vec![0u8; len];
-                    reader.read_exact(&mut skip_buf)?;
+                BinaryField::U64("rate_numerator") => {
+                    let mut buf = [0u8; 8];
+                    reader.read_exact(&mut buf)?;
+                    rate_numerator = u64::from_le_bytes(buf);
                 }
-                BinaryField::F64 { .. } => {
-                    let mut skip_buf = [0u8; 8];
-                    reader.read_exact(&mut skip_buf)?;
+                BinaryField::U64("rate_denominator") => {
+                    let mut buf = [0u8; 8];
+                    reader.read_exact(&mut buf)?;
+                    rate_denominator = u64::from_le_bytes(buf);
                 }
-                BinaryField::I64 { .. } => {
-                    let mut skip_buf = [0u8; 8];
-                    reader.read_exact(&mut skip_buf)?;
+                _ => {
+                    return Err(ZentryError::InvalidData("invalid field for `ConversionGraph`".to_string()));
                 }
-                _ => {}
             }
         }
-        Ok(())
+
+        // 0/0 when the graph has no exact rate, or was read from a layout without one
+        let exact_rate = ExactRate::new(rate_numerator, rate_denominator);
+
+        Ok(ConversionGraph { graph, rate, rate_since, exact_rate })
     }
 }
 
 impl TombstoneReader for BinaryStorage {
-    fn is_ignorable_error(&self, e: &std::io::Error) -> bool {
-        let msg = e.to_string();
-
-        matches!(
-            msg.as_str(), 
-            m if m.contains("dead record")
-                || m.contains("not enough data")
-                || m.contains("not converting historical conversion graph")
-        )
+    fn is_ignorable_error(&self, e: &ZentryError) -> bool {
+        matches!(e, ZentryError::DeadRecord | ZentryError::NotEnoughData | ZentryError::ChecksumMismatch)
     }
 
-    fn read<T>(&self) -> std::io::Result<Vec<T>>
+    fn read<T>(&self) -> Result<Vec<T>, ZentryError>
     where
         T: FromBinary,
     {
@@ -460,7 +704,7 @@ impl TombstoneReader for BinaryStorage {
         loop {
             match self.read_or_skip::<T>() {
                 Ok(i) => items.push(i),
-                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) if e.is_eof() => break,
                 Err(e) => {
                     if self.is_ignorable_error(&e) {
                         continue;
@@ -474,114 +718,129 @@ impl TombstoneReader for BinaryStorage {
         Ok(items)
     }
 
-    fn read_or_skip<T>(&self) -> std::io::Result<T>
+    fn read_or_skip<T>(&self) -> Result<T, ZentryError>
     where
         T: FromBinary,
     {
-        let type_key = match std::any::type_name::<T>() {
-            t if t.contains("Account") => "accounts",
-            t if t.contains("Transaction") => "transactions",
-            t if t.contains("Entry") => "entries",
-            t if t.contains("System") => "systems",
-            t if t.contains("ConversionGraph") => "conversion_graphs",
-            _ => return Err(std::io::Error::new(ErrorKind::Other, "unsupported type"))
-        };
+        let type_key = T::TYPE_KEY;
 
         let mut readers = self.readers.borrow_mut();
         let reader = readers.get_mut(type_key)
-            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "no reader found for type"))?;
+            .ok_or(ZentryError::MissingHandle("reader"))?;
 
         let layout = self.layouts.get(type_key)
-            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "no layout found for type"))?;
-
-        let reader_current_offset = reader.stream_position()?;
-
-        let mut tombstone_buf = [0u8; 1];
-        reader.read_exact(&mut tombstone_buf)?;
-
-        if self.is_tombstone_byte(tombstone_buf[0]) {
-            T::skip_bytes(reader, layout)?;
-            return Err(BinaryReadError::DeadRecord.into())
-        }
+            .ok_or(ZentryError::MissingHandle("layout"))?;
 
-        match T::from_binary(reader, layout) {
-            Ok(item) => Ok(item),
-            Err(e) if e.kind() == ErrorKind::InvalidData && self.is_ignorable_error(&e) => {
-                reader.seek(SeekFrom::Start(reader_current_offset))?;
-                T::skip_bytes(reader, layout)?;
-                Err(e)
-            }
-            Err(e) => Err(e)
-        }
+        read_or_skip_record(reader, layout, |byte| self.is_tombstone_byte(byte), |e| self.is_ignorable_error(e))
     }
 }
 
 impl TombstoneWriter for BinaryStorage {
-    fn tombstone<T>(&self, item: T, offset: u64) -> std::io::Result<()>
+    fn tombstone<T>(&self, item: T, offset: u64) -> Result<(), ZentryError>
     where
         T: FromBinary + PartialEq
     {
-        let type_key = match std::any::type_name::<T>() {
-            t if t.contains("Account") => "accounts",
-            t if t.contains("Transaction") => "transactions",
-            t if t.contains("Entry") => "entries",
-            t if t.contains("System") => "systems",
-            t if t.contains("ConversionGraph") => "conversion_graphs",
-            _ => return Err(std::io::Error::new(ErrorKind::Other, "unsupported type"))
-        };
+        self.check_writable()?;
+
+        let type_key = T::TYPE_KEY;
 
-        let item_from_binary = self.read_single::<T>(offset)?;
+        // verify against what is on disk, not a cached copy
+        let item_from_binary = self.read_from_disk::<T>(type_key, offset)?;
 
         if item_from_binary != item {
-            return Err(BinaryWriteError::TryingToTombstoneWrongRecord.into())
+            return Err(ZentryError::TombstoneMismatch)
         }
 
-        let mut tombstone_buf = [0u8; 1];
-        tombstone_buf[0] = 0;
+        let tombstone_buf = [STATUS_DEAD; 1];
 
         let mut writers = self.writers.borrow_mut();
         let writer = writers.get_mut(type_key)
-            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "no writer found for type"))?;
+            .ok_or(ZentryError::MissingHandle("writer"))?;
 
         writer.seek(SeekFrom::Start(offset))?;
-        writer.write(&tombstone_buf)?;
+        writer.write_all(&tombstone_buf)?;
         writer.seek(SeekFrom::End(0))?;
 
+        self.cache.borrow_mut().invalidate(type_key, offset);
+
         Ok(())
     }
 
-    fn write<T>(&self, item: T) -> std::io::Result<(u64, T)>
+    fn write<T>(&self, item: T) -> Result<(u64, T), ZentryError>
     where
         T: ToBinary
     {
-        let type_key = match std::any::type_name::<T>() {
-            t if t.contains("Account") => "accounts",
-            t if t.contains("Transaction") => "transactions",
-            t if t.contains("Entry") => "entries",
-            t if t.contains("System") => "systems",
-            t if t.contains("ConversionGraph") => "conversion_graphs",
-            _ => return Err(std::io::Error::new(ErrorKind::Other, "unsupported type"))
-        };
+        self.check_writable()?;
+
+        let type_key = T::TYPE_KEY;
 
         let layouts = self.layouts.borrow();
         let layout = layouts.get(type_key)
-            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "no layout found for type"))?;
+            .ok_or(ZentryError::MissingHandle("layout"))?;
 
         let mut writers = self.writers.borrow_mut();
         let writer = writers.get_mut(type_key)
-            .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "no writer found for type"))?;
+            .ok_or(ZentryError::MissingHandle("writer"))?;
 
         let offset = writer.seek(SeekFrom::End(0))?;
+        self.cache.borrow_mut().invalidate(type_key, offset);
 
         item.to_binary(writer, layout)?;
+
+        // make the record visible to the readers, which hold their own file handles
+        writer.flush()?;
+
+        if self.durable {
+            writer.get_ref().sync_data()?;
+        }
+
         Ok((offset, item))
     }
+
+    /// Appends `items` back to back with a single seek and a single write, returning each with
+    /// the offset it was written at. All items are encoded before anything reaches the file, so
+    /// one that fails to encode leaves the file untouched.
+    fn write_batch<T>(&self, items: Vec<T>) -> Result<Vec<(u64, T)>, ZentryError>
+    where
+        T: ToBinary
+    {
+        self.check_writable()?;
+
+        let type_key = T::TYPE_KEY;
+
+        let layout = self.layouts.get(type_key)
+            .ok_or(ZentryError::MissingHandle("layout"))?;
+
+        let mut writers = self.writers.borrow_mut();
+        let writer = writers.get_mut(type_key)
+            .ok_or(ZentryError::MissingHandle("writer"))?;
+
+        let mut buf = Vec::new();
+        let mut relative_offsets = Vec::with_capacity(items.len());
+        for item in items.iter() {
+            relative_offsets.push(buf.len() as u64);
+            item.to_binary(&mut buf, layout)?;
+        }
+
+        let start = writer.seek(SeekFrom::End(0))?;
+        writer.write_all(&buf)?;
+        writer.flush()?;
+
+        if self.durable {
+            writer.get_ref().sync_data()?;
+        }
+
+        let mut cache = self.cache.borrow_mut();
+        Ok(relative_offsets.into_iter().zip(items).map(|(relative_offset, item)| {
+            cache.invalidate(type_key, start + relative_offset);
+            (start + relative_offset, item)
+        }).collect())
+    }
 }
 
 impl ToBinary for System {
-    fn to_binary(&self, writer: &mut BufWriter<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        // write living record
-        writer.write_all(&[1u8; 1])?;
+    fn to_binary<W: Write>(&self, writer: &mut W, layout: &BinaryLayout) -> Result<(), ZentryError> {
+        let mut body = Vec::new();
 
         for field in &layout.fields {
             match field {
@@ -590,190 +849,225 @@ impl ToBinary for System {
                         "system_id" => self.id.as_bytes(),
                         "description" => self.description.as_bytes(),
                         _ => {
-                            return Err(std::io::Error::new(
-                                ErrorKind::InvalidInput,
-                                format!("unknown field in layout: {}", name),
-                            ));
+                            return Err(ZentryError::InvalidData(format!("unknown field in layout: {}", name)));
                         }
                     };
 
-                    write_length_prefixed_field(writer, bytes, name, length_type)?;
+                    write_length_prefixed_field(&mut body, bytes, name, length_type)?;
+                }
+                BinaryField::OptionalLengthPrefixed { name, length_type } if *name == "symbol" => {
+                    match &self.symbol {
+                        Some(symbol) => {
+                            body.write_all(&[1u8])?;
+                            write_length_prefixed_field(&mut body, symbol.as_bytes(), name, length_type)?;
+                        }
+                        None => body.write_all(&[0u8])?,
+                    }
+                }
+                BinaryField::OptionalU8("decimal_places") => {
+                    match self.decimal_places {
+                        Some(decimal_places) => body.write_all(&[1u8, decimal_places])?,
+                        None => body.write_all(&[0u8])?,
+                    }
                 }
                 other => {
-                    return Err(std::io::Error::new(
-                        ErrorKind::InvalidData,
-                        format!("unexpected binary field in `System` layout: {:?}", other),
-                    ));
+                    return Err(ZentryError::InvalidData(format!("unexpected binary field in `System` layout: {:?}", other)));
                 }
             }
         }
-        Ok(())
+        write_record(writer, &body, layout)
     }
 }
 
 impl ToBinary for ConversionGraph {
-    fn to_binary(&self, writer: &mut BufWriter<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        // write living record
-        writer.write_all(&[1u8; 1])?;
+    fn to_binary<W: Write>(&self, writer: &mut W, layout: &BinaryLayout) -> Result<(), ZentryError> {
+        let mut body = Vec::new();
 
         for field in &layout.fields {
             match field {
                 BinaryField::LengthPrefixed { name, length_type } => {
                     let bytes = match *name {
                         "graph" => {
-                            let key_class = classify_graph_key(self);
-
-                            match key_class {
-                                "active" => {
+                            match classify_graph_key(self) {
+                                GraphKeyClass::Active => {
                                     format!("C[{}]", self.graph).into_bytes()
                                 }
-                                "historical" => {
+                                GraphKeyClass::Historical => {
                                     format!("H[{}]", self.graph).into_bytes()
                                 }
-                                _ => {
-                                    return Err(std::io::Error::new(
-                                        ErrorKind::InvalidInput,
-                                        format!("unknown graph key class: {}", key_class),
-                                    ));
+                                GraphKeyClass::Unknown => {
+                                    return Err(ZentryError::InvalidData(format!("unknown graph key class: {}", self.graph)));
                                 }
                             }
                         }
                         _ => {
-                            return Err(std::io::Error::new(
-                                ErrorKind::InvalidInput,
-                                format!("unknown field in layout: {}", name),
-                            ));
+                            return Err(ZentryError::InvalidData(format!("unknown field in layout: {}", name)));
                         }
                     };
 
-                    write_length_prefixed_field(writer, &bytes, name, &length_type)?;
+                    write_length_prefixed_field(&mut body, &bytes, name, length_type)?;
                 }
                 BinaryField::F64("rate") => {
-                    writer.write_all(&self.rate.to_le_bytes())?;
+                    body.write_all(&self.rate.to_le_bytes())?;
                 }
                 BinaryField::I64("rate_since") => {
                     let timestamp = self.rate_since.timestamp();
-                    writer.write_all(&timestamp.to_le_bytes())?;
+                    body.write_all(&timestamp.to_le_bytes())?;
+                }
+                BinaryField::I64("rate_since_nanos") => {
+                    body.write_all(&timestamp_to_nanos(&self.rate_since, "rate_since")?.to_le_bytes())?;
+                }
+                BinaryField::U64("rate_numerator") => {
+                    body.write_all(&self.exact_rate.map_or(0, |rate| rate.numerator()).to_le_bytes())?;
+                }
+                BinaryField::U64("rate_denominator") => {
+                    body.write_all(&self.exact_rate.map_or(0, |rate| rate.denominator()).to_le_bytes())?;
                 }
                 other => {
-                    return Err(std::io::Error::new(
-                        ErrorKind::InvalidData,
-                        format!("unexpected binary field in `ConversionGraph` layout: {:?}", other),
-                    ));
+                    return Err(ZentryError::InvalidData(format!("unexpected binary field in `ConversionGraph` layout: {:?}", other)));
                 }
             }
         }
-        Ok(())
+        write_record(writer, &body, layout)
     }
 }
 
 impl ToBinary for Entry {
-    fn to_binary(&self, writer: &mut BufWriter<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        // write living record
-        writer.write_all(&[1u8; 1])?;
+    fn to_binary<W: Write>(&self, writer: &mut W, layout: &BinaryLayout) -> Result<(), ZentryError> {
+        let mut body = Vec::new();
 
         for field in &layout.fields {
             match field {
                 BinaryField::Uuid("id") => {
-                    writer.write_all(self.id.as_bytes())?;
+                    body.write_all(self.id.as_bytes())?;
                 }
                 BinaryField::Uuid("transaction_id") => {
-                    writer.write_all(self.transaction_id.as_bytes())?;
+                    body.write_all(self.transaction_id.as_bytes())?;
                 }
                 BinaryField::Uuid("account_id") => {
-                    writer.write_all(self.account_id.as_bytes())?;
+                    body.write_all(self.account_id.as_bytes())?;
                 }
-                BinaryField::I64("amount") => {
-                    writer.write_all(&self.amount.to_le_bytes())?;
+                BinaryField::F64("amount") => {
+                    body.write_all(&self.amount.to_le_bytes())?;
                 }
                 other => {
-                    return Err(std::io::Error::new(
-                        ErrorKind::InvalidData,
-                        format!("unexpected binary field in `Entry` layout: {:?}", other),
-                    ));
+                    return Err(ZentryError::InvalidData(format!("unexpected binary field in `Entry` layout: {:?}", other)));
                 }
             }
         }
-        Ok(())
+        write_record(writer, &body, layout)
     }
 }
 
 impl ToBinary for Transaction {
-    fn to_binary(&self, writer: &mut BufWriter<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        // write living record
-        writer.write_all(&[1u8; 1])?;
+    fn to_binary<W: Write>(&self, writer: &mut W, layout: &BinaryLayout) -> Result<(), ZentryError> {
+        let mut body = Vec::new();
 
         for field in &layout.fields {
             match field {
                 BinaryField::Uuid("id") => {
-                    writer.write_all(self.id.as_bytes())?;
+                    body.write_all(self.id.as_bytes())?;
                 }
                 BinaryField::LengthPrefixed { name, length_type } if *name == "description" => {
                     let bytes = self.description.as_bytes();
-                    write_length_prefixed_field(writer, bytes, name, length_type)?;
+                    write_length_prefixed_field(&mut body, bytes, name, length_type)?;
                 }
                 BinaryField::LengthPrefixed { name, length_type } if *name == "metadata" => {
                     let bytes = match &self.metadata {
                         Some(val) => serde_json::to_vec(val)?,
                         None => Vec::new(),
                     };
-                    write_length_prefixed_field(writer, &bytes, name, length_type)?;
+                    write_length_prefixed_field(&mut body, &bytes, name, length_type)?;
                 }
                 BinaryField::I64("timestamp") => {
                     let timestamp = self.timestamp.timestamp();
-                    writer.write_all(&timestamp.to_le_bytes())?;
+                    body.write_all(&timestamp.to_le_bytes())?;
+                }
+                BinaryField::I64("timestamp_nanos") => {
+                    body.write_all(&timestamp_to_nanos(&self.timestamp, "timestamp")?.to_le_bytes())?;
                 }
                 other => {
-                    return Err(std::io::Error::new(
-                        ErrorKind::InvalidData,
-                        format!("unexpected binary field in `Transaction` layout: {:?}", other),
-                    ));
+                    return Err(ZentryError::InvalidData(format!("unexpected binary field in `Transaction` layout: {:?}", other)));
                 }
             }
         }
 
-        Ok(())
+        write_record(writer, &body, layout)
     }
 }
 
 impl ToBinary for Account {
-    fn to_binary(&self, writer: &mut BufWriter<File>, layout: &BinaryLayout) -> std::io::Result<()> {
-        // write living record
-        writer.write_all(&[1u8; 1])?;
+    fn to_binary<W: Write>(&self, writer: &mut W, layout: &BinaryLayout) -> Result<(), ZentryError> {
+        let mut body = Vec::new();
+
+        // files written before accounts had parents can't hold one, don't drop it silently
+        if self.parent_id.is_some() && layout.version < ACCOUNT_PARENT_LAYOUT_VERSION {
+            return Err(ZentryError::InvalidData(format!(
+                "`parent_id` needs account layout version {}, the accounts file is at version {}",
+                ACCOUNT_PARENT_LAYOUT_VERSION, layout.version
+            )));
+        }
 
         for field in &layout.fields {
             match field {
                 BinaryField::Uuid("id") => {
-                    writer.write_all(self.id.as_bytes())?;
+                    body.write_all(self.id.as_bytes())?;
                 }
                 BinaryField::U8("account_type") => {
-                    writer.write_all(&[account_type_to_u8(&self.account_type).unwrap()])?;
+                    let byte = account_type_to_u8(&self.account_type).ok_or_else(|| {
+                        ZentryError::InvalidData(format!("{:?} uses a byte reserved for the built-in account types", self.account_type))
+                    })?;
+                    body.write_all(&[byte])?;
+                }
+                BinaryField::LengthPrefixed { name, length_type } if *name == "account_type" => {
+                    let type_name = account_type_to_name(&self.account_type).ok_or_else(|| {
+                        ZentryError::InvalidData(format!("{:?} uses a byte reserved for the built-in account types", self.account_type))
+                    })?;
+                    write_length_prefixed_field(&mut body, type_name.as_bytes(), name, length_type)?;
                 }
                 BinaryField::I64("created_at") => {
                     let ts = self.created_at.timestamp();
-                    writer.write_all(&ts.to_le_bytes())?;
+                    body.write_all(&ts.to_le_bytes())?;
+                }
+                BinaryField::I64("created_at_nanos") => {
+                    body.write_all(&timestamp_to_nanos(&self.created_at, "created_at")?.to_le_bytes())?;
                 }
                 BinaryField::LengthPrefixed { name, length_type } if *name == "name" => {
                     let bytes = self.name.as_bytes();
-                    write_length_prefixed_field(writer, bytes, name, length_type)?;
+                    write_length_prefixed_field(&mut body, bytes, name, length_type)?;
                 }
                 BinaryField::LengthPrefixed { name, length_type } if *name == "system_id" => {
                     let bytes = self.system_id.as_bytes();
-                    write_length_prefixed_field(writer, bytes, name, length_type)?;
+                    write_length_prefixed_field(&mut body, bytes, name, length_type)?;
+                }
+                BinaryField::OptionalUuid("parent_id") => {
+                    match self.parent_id {
+                        Some(parent_id) => {
+                            body.write_all(&[1u8])?;
+                            body.write_all(parent_id.as_bytes())?;
+                        }
+                        None => body.write_all(&[0u8])?,
+                    }
                 }
                 other => {
-                    return Err(std::io::Error::new(
-                        ErrorKind::InvalidData,
-                        format!("unexpected binary field in `Account` layout: {:?}", other),
-                    ));
+                    return Err(ZentryError::InvalidData(format!("unexpected binary field in `Account` layout: {:?}", other)));
                 }
             }
         }
-        Ok(())
+        write_record(writer, &body, layout)
     }
 }
 
-pub fn compute_object_size(layout: &BinaryLayout, data: &[u8], offset: usize) -> std::io::Result<usize> {
+/// Nanoseconds since the epoch, which an `i64` only holds for roughly the years 1677 to 2262
+fn timestamp_to_nanos(timestamp: &DateTime<Utc>, name: &str) -> Result<i64, ZentryError> {
+    timestamp.timestamp_nanos_opt().ok_or_else(|| {
+        ZentryError::InvalidData(format!("`{}` {} is out of range for a nanosecond timestamp", name, timestamp.to_rfc3339()))
+    })
+}
+
+/// Byte length of the record body starting at `offset` of an in-memory buffer, status byte and
+/// checksum not included. See [`record_size`] for records still on disk.
+pub fn compute_object_size(layout: &BinaryLayout, data: &[u8], offset: usize) -> Result<usize, ZentryError> {
     let mut cursor = offset;
     let mut total_size = 0;
 
@@ -783,26 +1077,54 @@ pub fn compute_object_size(layout: &BinaryLayout, data: &[u8], offset: usize) ->
                 total_size += 16;
                 cursor += 16;
             }
+            BinaryField::OptionalUuid(_) => {
+                if cursor >= data.len() {
+                    return Err(ZentryError::NotEnoughData);
+                }
+
+                let len = if data[cursor] == 1 { 17 } else { 1 };
+                total_size += len;
+                cursor += len;
+            }
             BinaryField::U8(_) => {
                 total_size += 1;
                 cursor += 1;
             }
+            BinaryField::OptionalU8(_) => {
+                if cursor >= data.len() {
+                    return Err(ZentryError::NotEnoughData);
+                }
+
+                let len = if data[cursor] == 1 { 2 } else { 1 };
+                total_size += len;
+                cursor += len;
+            }
             BinaryField::U32(_) => {
                 total_size += 4;
                 cursor += 4;
             }
-            BinaryField::I64(_) | BinaryField::F64(_) => {
+            BinaryField::I64(_) | BinaryField::U64(_) | BinaryField::F64(_) => {
                 total_size += 8;
                 cursor += 8;
             }
-            BinaryField::LengthPrefixed { name: _, length_type } => {
+            BinaryField::LengthPrefixed { name: _, length_type } | BinaryField::OptionalLengthPrefixed { name: _, length_type } => {
+                if matches!(field, BinaryField::OptionalLengthPrefixed { .. }) {
+                    if cursor >= data.len() {
+                        return Err(ZentryError::NotEnoughData);
+                    }
+
+                    let present = data[cursor] == 1;
+                    total_size += 1;
+                    cursor += 1;
+                    if !present {
+                        continue;
+                    }
+                }
+
                 let len_size = length_type.byte_len();
 
                 if cursor + len_size > data.len() {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::UnexpectedEof,
-                        "not enough data for length prefix",
-                    ));
+                    return Err(ZentryError::NotEnoughData);
                 }
 
                 let length = match length_type {
@@ -828,46 +1150,192 @@ pub fn compute_object_size(layout: &BinaryLayout, data: &[u8], offset: usize) ->
     Ok(total_size)
 }
 
-fn classify_graph_key(graph: &ConversionGraph) -> &'static str {
-    let active_conversion_key_pattern = Regex::new(r"^\s*\w+\s*(->|<-|<->)\s*\w+\s*$").unwrap();
-    let historical_conversion_key_pattern = Regex::new(
-        r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?[+-]\d{2}:\d{2}\[\s*\w+\s*(->|<-|<->)\s*\w+\s*\]\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?[+-]\d{2}:\d{2}$"
-    ).unwrap();
+/// Byte length of the whole record at `offset`, status byte and checksum included, found by reading
+/// only its length prefixes and presence bytes and seeking over everything else. Leaves the reader
+/// at the end of the record, which is where the next one starts. Every seek is relative, which a
+/// `BufReader` serves from its buffer when it can, so skipping records stays sequential I/O.
+/// A record cut short by the end of the file isn't detected here, the next read fails instead.
+pub fn record_size<R: Read + Seek>(layout: &BinaryLayout, reader: &mut R, offset: u64) -> Result<usize, ZentryError> {
+    let position = reader.stream_position()?;
+    if position != offset {
+        reader.seek_relative(offset as i64 - position as i64)?;
+    }
+
+    let header_len = if layout.is_checksummed() { 5 } else { 1 };
+    reader.seek_relative(header_len as i64)?;
+    let mut size = header_len;
 
-    if historical_conversion_key_pattern.is_match(&graph.graph) {
-        return "historical";
-    } else if active_conversion_key_pattern.is_match(&graph.graph) {
-        return "active";
+    for field in &layout.fields {
+        let len = match field {
+            BinaryField::Uuid(_) => 16,
+            BinaryField::OptionalUuid(name) => {
+                size += 1;
+                if read_presence_byte(reader, name)? { 16 } else { 0 }
+            }
+            BinaryField::U8(_) => 1,
+            BinaryField::OptionalU8(name) => {
+                size += 1;
+                if read_presence_byte(reader, name)? { 1 } else { 0 }
+            }
+            BinaryField::U32(_) => 4,
+            BinaryField::I64(_) | BinaryField::U64(_) | BinaryField::F64(_) => 8,
+            BinaryField::LengthPrefixed { length_type, name } => {
+                size += length_type.byte_len();
+                read_length_prefix(reader, length_type, name)?
+            }
+            BinaryField::OptionalLengthPrefixed { length_type, name } => {
+                size += 1;
+                if read_presence_byte(reader, name)? {
+                    size += length_type.byte_len();
+                    read_length_prefix(reader, length_type, name)?
+                } else {
+                    0
+                }
+            }
+        };
+
+        reader.seek_relative(len as i64)?;
+        size += len;
     }
 
-    "unknown"
+    Ok(size)
 }
 
-fn write_length_prefixed_field(writer: &mut BufWriter<File>, bytes: &[u8], name: &str, length_type: &LengthType) -> std::io::Result<()> {
-    let len = bytes.len();
-    
-    match length_type {
-        LengthType::U8 if len <= u8::MAX as usize => {
-            writer.write_all(&[len as u8])?;
-        }
-        LengthType::U16 if len <= u16::MAX as usize => {
-            writer.write_all(&(len as u16).to_le_bytes())?;
-        }
-        LengthType::U32 => {
-            writer.write_all(&(len as u32).to_le_bytes())?;
+/// What a conversion graph's key says about it, see [`classify_graph_key`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphKeyClass {
+    /// In effect now, keyed by its relation
+    Active,
+    /// Archived with its validity window, see [`ConversionGraph::historical_window`]
+    Historical,
+    /// A key that parses as neither, which no view counts as active
+    Unknown,
+}
+
+impl GraphKeyClass {
+    /// `active`, `historical` or `unknown`, as listings show it
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GraphKeyClass::Active => "active",
+            GraphKeyClass::Historical => "historical",
+            GraphKeyClass::Unknown => "unknown",
         }
-        _ => {
-            return Err(std::io::Error::new(
-                ErrorKind::InvalidData,
-                format!("`{}` is too long to encode", name),
-            ));
+    }
+}
+
+pub fn classify_graph_key(graph: &ConversionGraph) -> GraphKeyClass {
+    if let Some((_, relation, _)) = graph.historical_window() {
+        if GraphKey::parse(relation).is_ok() {
+            return GraphKeyClass::Historical;
         }
+    } else if GraphKey::parse(&graph.graph).is_ok() {
+        return GraphKeyClass::Active;
+    }
+
+    GraphKeyClass::Unknown
+}
+
+/// Record bodies are encoded into a buffer before anything reaches the file, so a field that
+/// is too long for its prefix fails the write without leaving a partial record behind.
+fn write_length_prefixed_field<W: Write>(writer: &mut W, bytes: &[u8], name: &str, length_type: &LengthType) -> Result<(), ZentryError> {
+    let len = bytes.len();
+
+    if len > length_type.max_len() {
+        return Err(ZentryError::InvalidData(format!(
+            "`{}` is {} bytes, longer than the {} bytes its {:?} length prefix can hold",
+            name, len, length_type.max_len(), length_type
+        )));
+    }
+    if len > MAX_FIELD_LEN {
+        return Err(ZentryError::InvalidData(format!(
+            "`{}` is {} bytes, longer than the {} bytes a record field can hold",
+            name, len, MAX_FIELD_LEN
+        )));
+    }
+
+    match length_type {
+        LengthType::U8 => writer.write_all(&[len as u8])?,
+        LengthType::U16 => writer.write_all(&(len as u16).to_le_bytes())?,
+        LengthType::U32 => writer.write_all(&(len as u32).to_le_bytes())?,
+    }
+
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+/// Reads the presence byte of an optional field, rejecting anything other than 0 or 1.
+fn read_presence_byte<R: std::io::Read>(reader: &mut R, name: &str) -> Result<bool, ZentryError> {
+    let mut flag = [0u8; 1];
+    reader.read_exact(&mut flag)?;
+
+    match flag[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        other => Err(ZentryError::InvalidData(format!("`{}` has presence byte {}, expected 0 or 1", name, other))),
+    }
+}
+
+/// Reads an `OptionalUuid` field
+fn read_optional_uuid<R: std::io::Read>(reader: &mut R, name: &str) -> Result<Option<Uuid>, ZentryError> {
+    if !read_presence_byte(reader, name)? {
+        return Ok(None);
     }
 
-    writer.write_all(bytes)
+    let mut buf = [0u8; 16];
+    reader.read_exact(&mut buf)?;
+    Ok(Some(Uuid::from_bytes(buf)))
 }
 
-fn read_length_prefixed_string<R: std::io::Read>(reader: &mut R, length_type: &LengthType) -> std::io::Result<String> {
+/// Reads an `OptionalU8` field
+fn read_optional_u8<R: std::io::Read>(reader: &mut R, name: &str) -> Result<Option<u8>, ZentryError> {
+    if !read_presence_byte(reader, name)? {
+        return Ok(None);
+    }
+
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(Some(buf[0]))
+}
+
+/// Reads an `OptionalLengthPrefixed` field
+fn read_optional_length_prefixed_bytes<R: std::io::Read>(reader: &mut R, length_type: &LengthType, name: &str) -> Result<Option<Vec<u8>>, ZentryError> {
+    if !read_presence_byte(reader, name)? {
+        return Ok(None);
+    }
+
+    read_length_prefixed_bytes(reader, length_type, name).map(Some)
+}
+
+/// Reads a length-prefixed field that holds text. Bytes that are not valid UTF-8 mean the record
+/// is corrupted, so they fail the read instead of decoding to something else.
+fn read_length_prefixed_string<R: std::io::Read>(reader: &mut R, length_type: &LengthType, name: &str) -> Result<String, ZentryError> {
+    String::from_utf8(read_length_prefixed_bytes(reader, length_type, name)?).map_err(|e| {
+        ZentryError::InvalidData(format!(
+            "`{}` is not valid UTF-8: invalid byte at offset {} of the field",
+            name, e.utf8_error().valid_up_to()
+        ))
+    })
+}
+
+/// Reads a length-prefixed field. The buffer grows with the bytes actually read rather than being
+/// sized from the prefix up front, so a prefix pointing past the end of a truncated file fails
+/// with an end-of-file error instead of allocating the declared length.
+fn read_length_prefixed_bytes<R: std::io::Read>(reader: &mut R, length_type: &LengthType, name: &str) -> Result<Vec<u8>, ZentryError> {
+    let len = read_length_prefix(reader, length_type, name)?;
+
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("`{}` declares {} bytes but only {} remain", name, len, buf.len()),
+        ).into());
+    }
+    Ok(buf)
+}
+
+/// Reads the length prefix of a field, rejecting lengths no record field can have.
+fn read_length_prefix<R: std::io::Read>(reader: &mut R, length_type: &LengthType, name: &str) -> Result<usize, ZentryError> {
     let len = match length_type {
         LengthType::U8 => {
             let mut buf = [0u8; 1];
@@ -885,7 +1353,272 @@ fn read_length_prefixed_string<R: std::io::Read>(reader: &mut R, length_type: &L
             u32::from_le_bytes(buf) as usize
         }
     };
-    let mut str_buf = vec![0u8; len];
-    reader.read_exact(&mut str_buf)?;
-    Ok(String::from_utf8(str_buf).unwrap_or_default())
+
+    if len > MAX_FIELD_LEN {
+        return Err(ZentryError::InvalidData(format!(
+            "`{}` declares a length of {} bytes, more than the {} bytes a record field can hold",
+            name, len, MAX_FIELD_LEN
+        )));
+    }
+
+    Ok(len)
+}
+
+/// Validates the header at the start of a record file against the layout and returns the offset
+/// of its first record, positioning the reader there. Empty files are stamped with a header for the
+/// layout's version; files without a header are read as legacy records. Without a writer an
+/// empty file is left as it is, there are no records to read from it either way.
+fn open_versioned(reader: &mut BufReader<File>, writer: Option<&mut BufWriter<File>>, layout: &mut BinaryLayout) -> Result<u64, ZentryError> {
+    if reader.get_ref().metadata()?.len() == 0 {
+        if let Some(writer) = writer {
+            writer.seek(SeekFrom::Start(0))?;
+            writer.write_all(&file_header(layout.version))?;
+            writer.flush()?;
+        }
+
+        reader.seek(SeekFrom::Start(FILE_HEADER_LEN))?;
+        return Ok(FILE_HEADER_LEN);
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    let mut first_byte = [0u8; 1];
+    reader.read_exact(&mut first_byte)?;
+
+    // legacy files have no header, they start with the status byte of their first record
+    if matches!(first_byte[0], 0x00 | 0x01) {
+        layout.set_version(LEGACY_LAYOUT_VERSION);
+        reader.seek(SeekFrom::Start(0))?;
+        return Ok(0);
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; FILE_HEADER_LEN as usize];
+    reader.read_exact(&mut header).map_err(|_| {
+        ZentryError::InvalidData(format!("`{}` record file has a truncated header", layout.name))
+    })?;
+
+    if &header[..FILE_MAGIC.len()] != FILE_MAGIC {
+        return Err(ZentryError::InvalidData(format!(
+            "`{}` record file does not start with a zentry header",
+            layout.name
+        )));
+    }
+
+    let version = header[FILE_MAGIC.len()];
+    if version < CHECKSUM_LAYOUT_VERSION || version > layout.version {
+        return Err(ZentryError::InvalidData(format!(
+            "`{}` records are stored with layout version {}, this build reads versions {} to {}",
+            layout.name, version, CHECKSUM_LAYOUT_VERSION, layout.version
+        )));
+    }
+
+    layout.set_version(version);
+    Ok(FILE_HEADER_LEN)
+}
+
+/// Reads every live record of the file at `path` with its offset, like
+/// [`BinaryStorage::read_with_offsets`] but through a fresh handle instead of the storage's shared
+/// reader, so several record files can be scanned on separate threads.
+/// Pass what [`BinaryStorage::file_layout`] returns for the file.
+pub fn read_file_with_offsets<T>(path: &Path, layout: &BinaryLayout, data_offset: u64) -> Result<Vec<(u64, T)>, ZentryError>
+where
+    T: FromBinary,
+{
+    RecordCursor::open(path, layout, data_offset)?.collect()
+}
+
+/// Steps through the live records of a record file one at a time, through a handle of its own,
+/// so a caller can go over a file far larger than memory. Yields each record with its offset.
+/// Tombstoned, truncated and corrupt records are skipped the same way a load skips them; any
+/// other error is yielded once and ends the iteration.
+pub struct RecordCursor<'a, T> {
+    reader: BufReader<File>,
+    layout: &'a BinaryLayout,
+    data_offset: u64,
+    done: bool,
+    _record: std::marker::PhantomData<T>,
+}
+
+impl<'a, T: FromBinary> RecordCursor<'a, T> {
+    pub fn open(path: &Path, layout: &'a BinaryLayout, data_offset: u64) -> Result<Self, ZentryError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        reader.seek(SeekFrom::Start(data_offset))?;
+
+        Ok(Self {
+            reader,
+            layout,
+            data_offset,
+            done: false,
+            _record: std::marker::PhantomData,
+        })
+    }
+
+    /// Goes back to the first record, e.g. to make a second pass
+    pub fn reset(&mut self) -> Result<(), ZentryError> {
+        self.reader.seek(SeekFrom::Start(self.data_offset))?;
+        self.done = false;
+        Ok(())
+    }
+}
+
+impl<T: FromBinary> Iterator for RecordCursor<'_, T> {
+    type Item = Result<(u64, T), ZentryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_ignorable = |e: &ZentryError| matches!(e, ZentryError::DeadRecord | ZentryError::NotEnoughData | ZentryError::ChecksumMismatch);
+
+        while !self.done {
+            let offset = match self.reader.stream_position() {
+                Ok(offset) => offset,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            };
+
+            match read_or_skip_record::<T, _>(&mut self.reader, self.layout, |byte| byte == STATUS_DEAD, is_ignorable) {
+                Ok(item) => return Some(Ok((offset, item))),
+                Err(e) if e.is_eof() => self.done = true,
+                Err(e) if is_ignorable(&e) => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Counts the tombstoned records of the file at `path` from their status bytes alone, every body
+/// is stepped over with [`record_size`] instead of being decoded. A record cut short at the end
+/// of the file ends the count.
+pub fn count_tombstones(path: &Path, layout: &BinaryLayout, data_offset: u64) -> Result<usize, ZentryError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(data_offset))?;
+
+    let mut tombstones = 0;
+    loop {
+        let offset = reader.stream_position()?;
+
+        let mut status = [0u8; 1];
+        match reader.read_exact(&mut status) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        if status[0] == STATUS_DEAD {
+            tombstones += 1;
+        } else {
+            check_live_status(status[0], offset)?;
+        }
+
+        match record_size(layout, &mut reader, offset) {
+            Ok(_) => {}
+            Err(e) if e.is_eof() => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(tombstones)
+}
+
+/// Reads the record at the reader's position, status byte included. Dead and unreadable records
+/// are skipped over so the reader ends up at the next record either way, and reported as errors.
+fn read_or_skip_record<T, R>(
+    reader: &mut R,
+    layout: &BinaryLayout,
+    is_tombstone_byte: impl Fn(u8) -> bool,
+    is_ignorable_error: impl Fn(&ZentryError) -> bool,
+) -> Result<T, ZentryError>
+where
+    T: FromBinary,
+    R: Read + Seek,
+{
+    let record_offset = reader.stream_position()?;
+
+    let mut tombstone_buf = [0u8; 1];
+    reader.read_exact(&mut tombstone_buf)?;
+
+    if is_tombstone_byte(tombstone_buf[0]) {
+        record_size(layout, reader, record_offset)?;
+        return Err(ZentryError::DeadRecord)
+    }
+    // not skipped like a dead record: past a byte that is neither status the record boundaries
+    // can't be trusted, so the read stops here
+    check_live_status(tombstone_buf[0], record_offset)?;
+
+    match read_record(reader, layout) {
+        Ok(item) => Ok(item),
+        // the checksum is only verified once the whole record has been consumed
+        Err(ZentryError::ChecksumMismatch) => Err(ZentryError::ChecksumMismatch),
+        Err(e) if is_ignorable_error(&e) => {
+            record_size(layout, reader, record_offset)?;
+            Err(e)
+        }
+        Err(e) => Err(e)
+    }
+}
+
+/// Rejects a status byte that is not [`STATUS_LIVE`], for callers that have already handled
+/// [`STATUS_DEAD`].
+fn check_live_status(status: u8, offset: u64) -> Result<(), ZentryError> {
+    if status != STATUS_LIVE {
+        return Err(ZentryError::CorruptStatus { offset, status });
+    }
+    Ok(())
+}
+
+/// Frames an encoded record body as `[status][body]`, or `[status][crc32][body]` for
+/// checksummed layouts.
+fn write_record<W: Write>(writer: &mut W, body: &[u8], layout: &BinaryLayout) -> Result<(), ZentryError> {
+    writer.write_all(&[STATUS_LIVE; 1])?;
+
+    if layout.is_checksummed() {
+        writer.write_all(&crc32fast::hash(body).to_le_bytes())?;
+    }
+
+    writer.write_all(body)?;
+    Ok(())
+}
+
+/// Decodes the record following a status byte. For checksummed layouts the body is captured
+/// while walking its fields and verified before decoding, so a corrupted record is fully
+/// consumed and reported as `ChecksumMismatch` rather than decoded into garbage.
+fn read_record<T, R>(reader: &mut R, layout: &BinaryLayout) -> Result<T, ZentryError>
+where
+    T: FromBinary,
+    R: Read,
+{
+    if !layout.is_checksummed() {
+        return T::from_binary(reader, layout);
+    }
+
+    let mut checksum_buf = [0u8; 4];
+    reader.read_exact(&mut checksum_buf)?;
+
+    let mut capturing = CapturingReader { inner: reader, captured: Vec::new() };
+    T::skip_bytes(&mut capturing, layout)?;
+    let body = capturing.captured;
+
+    if crc32fast::hash(&body) != u32::from_le_bytes(checksum_buf) {
+        return Err(ZentryError::ChecksumMismatch);
+    }
+
+    T::from_binary(&mut body.as_slice(), layout)
+}
+
+/// Keeps a copy of every byte read through it.
+struct CapturingReader<'a, R: Read> {
+    inner: &'a mut R,
+    captured: Vec<u8>,
+}
+
+impl<R: Read> Read for CapturingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.captured.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
 }
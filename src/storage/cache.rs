@@ -0,0 +1,93 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Record file type key and offset of a record
+type RecordKey = (&'static str, u64);
+
+/// Bounded least-recently-used cache of decoded records, keyed by record file and offset.
+/// Records of any type share the cache; a lookup with the wrong type is a miss.
+/// Records must be `Send` so the storage holding the cache can move to another thread.
+pub struct RecordCache {
+    capacity: usize,
+    tick: u64,
+    records: HashMap<RecordKey, (u64, Box<dyn Any + Send>)>,
+    /// Last use tick to key, the first entry is the one evicted next
+    recency: BTreeMap<u64, RecordKey>,
+}
+
+impl RecordCache {
+    /// A capacity of 0 disables caching
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            records: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    pub fn get<T: Clone + 'static>(&mut self, type_key: &'static str, offset: u64) -> Option<T> {
+        let key = (type_key, offset);
+        let record = self.records.get(&key)?.1.downcast_ref::<T>()?.clone();
+        self.touch(key);
+        Some(record)
+    }
+
+    pub fn insert<T: Send + 'static>(&mut self, type_key: &'static str, offset: u64, record: T) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = (type_key, offset);
+        if !self.records.contains_key(&key) && self.records.len() >= self.capacity {
+            if let Some((_, evicted)) = self.recency.pop_first() {
+                self.records.remove(&evicted);
+            }
+        }
+
+        self.tick += 1;
+        if let Some((last_used, _)) = self.records.insert(key, (self.tick, Box::new(record))) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(self.tick, key);
+    }
+
+    pub fn invalidate(&mut self, type_key: &'static str, offset: u64) {
+        if let Some((last_used, _)) = self.records.remove(&(type_key, offset)) {
+            self.recency.remove(&last_used);
+        }
+    }
+
+    /// Drops every record of one file, e.g. after compaction moved them all
+    pub fn invalidate_file(&mut self, type_key: &'static str) {
+        self.records.retain(|(key, _), _| *key != type_key);
+        self.recency.retain(|_, (key, _)| *key != type_key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn touch(&mut self, key: RecordKey) {
+        self.tick += 1;
+        if let Some((last_used, _)) = self.records.get_mut(&key) {
+            self.recency.remove(last_used);
+            *last_used = self.tick;
+            self.recency.insert(self.tick, key);
+        }
+    }
+}
+
+impl fmt::Debug for RecordCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.records.len())
+            .finish()
+    }
+}
@@ -1,14 +1,24 @@
 #[derive(Debug)]
 pub enum BinaryField {
     Uuid(&'static str),
+    /// A presence byte, 1 when a 16-byte UUID follows and 0 when nothing does
+    OptionalUuid(&'static str),
     U8(&'static str),
+    /// A presence byte, 1 when a byte follows and 0 when nothing does
+    OptionalU8(&'static str),
     U32(&'static str),
     I64(&'static str),
+    U64(&'static str),
     F64(&'static str),
     LengthPrefixed {
         length_type: LengthType,
         name: &'static str,
     },
+    /// A presence byte, 1 when a `LengthPrefixed` field follows and 0 when nothing does
+    OptionalLengthPrefixed {
+        length_type: LengthType,
+        name: &'static str,
+    },
 }
 
 
@@ -27,47 +37,157 @@ impl LengthType {
             LengthType::U32 => 4,
         }
     }
+
+    /// Longest field, in bytes, the prefix can describe
+    pub fn max_len(&self) -> usize {
+        match self {
+            LengthType::U8 => u8::MAX as usize,
+            LengthType::U16 => u16::MAX as usize,
+            LengthType::U32 => u32::MAX as usize,
+        }
+    }
+}
+
+/// Every versioned `.bin` file starts with `FILE_MAGIC` followed by a single layout version byte
+pub const FILE_MAGIC: &[u8; 4] = b"ZNTR";
+pub const FILE_HEADER_LEN: u64 = FILE_MAGIC.len() as u64 + 1;
+
+/// Records written before files carried a header: `[status][body]`
+pub const LEGACY_LAYOUT_VERSION: u8 = 1;
+/// Records carry a CRC32 of their body: `[status][crc32][body]`. Oldest version found behind a header
+pub const CHECKSUM_LAYOUT_VERSION: u8 = 2;
+/// Account names carry a `U16` length prefix instead of a `U8` one
+pub const WIDE_ACCOUNT_NAME_LAYOUT_VERSION: u8 = 3;
+/// Transaction descriptions carry a `U16` length prefix instead of a `U8` one
+pub const WIDE_TRANSACTION_DESCRIPTION_LAYOUT_VERSION: u8 = 3;
+/// Accounts carry an optional `parent_id`
+pub const ACCOUNT_PARENT_LAYOUT_VERSION: u8 = 4;
+/// `created_at` is stored in nanoseconds instead of whole seconds
+pub const ACCOUNT_NANOS_LAYOUT_VERSION: u8 = 5;
+/// The account type is stored by name, e.g. `Asset`, in a `U8`-prefixed string instead of as a
+/// byte, so reading it back doesn't depend on the byte mapping
+pub const ACCOUNT_TYPE_NAME_LAYOUT_VERSION: u8 = 6;
+/// `timestamp` is stored in nanoseconds instead of whole seconds
+pub const TRANSACTION_NANOS_LAYOUT_VERSION: u8 = 4;
+/// `rate_since` is stored in nanoseconds instead of whole seconds
+pub const CONVERSION_GRAPH_NANOS_LAYOUT_VERSION: u8 = 3;
+/// Conversion graphs carry their exact rate as a `U64` numerator and denominator, 0/0 for none
+pub const CONVERSION_GRAPH_EXACT_RATE_LAYOUT_VERSION: u8 = 4;
+/// Systems carry an optional currency `symbol` and `decimal_places`
+pub const SYSTEM_CURRENCY_LAYOUT_VERSION: u8 = 3;
+
+pub const ACCOUNT_LAYOUT_VERSION: u8 = 6;
+pub const TRANSACTION_LAYOUT_VERSION: u8 = 4;
+pub const ENTRY_LAYOUT_VERSION: u8 = 2;
+pub const SYSTEM_LAYOUT_VERSION: u8 = 3;
+pub const CONVERSION_GRAPH_LAYOUT_VERSION: u8 = 4;
+
+pub fn file_header(version: u8) -> [u8; 5] {
+    let mut header = [0u8; 5];
+    header[..4].copy_from_slice(FILE_MAGIC);
+    header[4] = version;
+    header
 }
 
 #[derive(Debug)]
 pub struct BinaryLayout {
     pub name: &'static str,
+    pub version: u8,
     pub fields: Vec<BinaryField>,
 }
 
+impl BinaryLayout {
+    pub fn is_checksummed(&self) -> bool {
+        self.version >= CHECKSUM_LAYOUT_VERSION
+    }
+
+    /// Switches to the layout that records of `version` were written with
+    pub fn set_version(&mut self, version: u8) {
+        match self.name {
+            "Account" => *self = account_layout_for(version),
+            "Transaction" => *self = transaction_layout_for(version),
+            "ConversionGraph" => *self = conversion_graph_layout_for(version),
+            "System" => *self = system_layout_for(version),
+            _ => self.version = version,
+        }
+    }
+}
+
 pub fn account_layout() -> BinaryLayout {
-    BinaryLayout {
-        name: "Account",
-        fields: vec![
-            BinaryField::Uuid("id"),
-            BinaryField::LengthPrefixed {
-                length_type: LengthType::U8,
-                name: "name",
-            },
-            BinaryField::U8("account_type"),
-            BinaryField::I64("created_at"),
+    account_layout_for(ACCOUNT_LAYOUT_VERSION)
+}
+
+pub fn account_layout_for(version: u8) -> BinaryLayout {
+    let name_length_type = if version >= WIDE_ACCOUNT_NAME_LAYOUT_VERSION {
+        LengthType::U16
+    } else {
+        LengthType::U8
+    };
+
+    let mut fields = vec![
+        BinaryField::Uuid("id"),
+        BinaryField::LengthPrefixed {
+            length_type: name_length_type,
+            name: "name",
+        },
+        if version >= ACCOUNT_TYPE_NAME_LAYOUT_VERSION {
             BinaryField::LengthPrefixed {
                 length_type: LengthType::U8,
-                name: "system_id",
-            },
-        ],
+                name: "account_type",
+            }
+        } else {
+            BinaryField::U8("account_type")
+        },
+        if version >= ACCOUNT_NANOS_LAYOUT_VERSION {
+            BinaryField::I64("created_at_nanos")
+        } else {
+            BinaryField::I64("created_at")
+        },
+        BinaryField::LengthPrefixed {
+            length_type: LengthType::U8,
+            name: "system_id",
+        },
+    ];
+    if version >= ACCOUNT_PARENT_LAYOUT_VERSION {
+        fields.push(BinaryField::OptionalUuid("parent_id"));
+    }
+
+    BinaryLayout {
+        name: "Account",
+        version,
+        fields,
     }
 }
 
 pub fn transaction_layout() -> BinaryLayout {
+    transaction_layout_for(TRANSACTION_LAYOUT_VERSION)
+}
+
+pub fn transaction_layout_for(version: u8) -> BinaryLayout {
+    let description_length_type = if version >= WIDE_TRANSACTION_DESCRIPTION_LAYOUT_VERSION {
+        LengthType::U16
+    } else {
+        LengthType::U8
+    };
+
     BinaryLayout {
         name: "Transaction",
+        version,
         fields: vec![
             BinaryField::Uuid("id"),
             BinaryField::LengthPrefixed {
-                length_type: LengthType::U8,
+                length_type: description_length_type,
                 name: "description",
             },
             BinaryField::LengthPrefixed {
                 length_type: LengthType::U32,
                 name: "metadata",
             },
-            BinaryField::I64("timestamp"),
+            if version >= TRANSACTION_NANOS_LAYOUT_VERSION {
+                BinaryField::I64("timestamp_nanos")
+            } else {
+                BinaryField::I64("timestamp")
+            },
         ],
     }
 }
@@ -75,6 +195,7 @@ pub fn transaction_layout() -> BinaryLayout {
 pub fn entry_layout() -> BinaryLayout {
     BinaryLayout {
         name: "Entry",
+        version: ENTRY_LAYOUT_VERSION,
         fields: vec![
             BinaryField::Uuid("id"),
             BinaryField::Uuid("transaction_id"),
@@ -85,32 +206,61 @@ pub fn entry_layout() -> BinaryLayout {
 }
 
 pub fn system_layout() -> BinaryLayout {
+    system_layout_for(SYSTEM_LAYOUT_VERSION)
+}
+
+pub fn system_layout_for(version: u8) -> BinaryLayout {
+    let mut fields = vec![
+        BinaryField::LengthPrefixed {
+            length_type: LengthType::U8,
+            name: "system_id",
+        },
+        BinaryField::LengthPrefixed {
+            length_type: LengthType::U8,
+            name: "description",
+        },
+    ];
+    if version >= SYSTEM_CURRENCY_LAYOUT_VERSION {
+        fields.push(BinaryField::OptionalLengthPrefixed {
+            length_type: LengthType::U8,
+            name: "symbol",
+        });
+        fields.push(BinaryField::OptionalU8("decimal_places"));
+    }
+
     BinaryLayout {
         name: "System",
-        fields: vec![
-            BinaryField::LengthPrefixed {
-                length_type: LengthType::U8,
-                name: "system_id",
-            },
-            BinaryField::LengthPrefixed {
-                length_type: LengthType::U8,
-                name: "description",
-            },
-        ],
+        version,
+        fields,
     }
 }
 
 pub fn conversion_graph_layout() -> BinaryLayout {
+    conversion_graph_layout_for(CONVERSION_GRAPH_LAYOUT_VERSION)
+}
+
+pub fn conversion_graph_layout_for(version: u8) -> BinaryLayout {
+    let mut fields = vec![
+        BinaryField::LengthPrefixed {
+            length_type: LengthType::U8,
+            name: "graph",
+        },
+        BinaryField::F64("rate"), // rate
+        if version >= CONVERSION_GRAPH_NANOS_LAYOUT_VERSION {
+            BinaryField::I64("rate_since_nanos")
+        } else {
+            BinaryField::I64("rate_since")
+        },
+    ];
+    if version >= CONVERSION_GRAPH_EXACT_RATE_LAYOUT_VERSION {
+        fields.push(BinaryField::U64("rate_numerator"));
+        fields.push(BinaryField::U64("rate_denominator"));
+    }
+
     BinaryLayout {
         name: "ConversionGraph",
-        fields: vec![
-            BinaryField::LengthPrefixed {
-                length_type: LengthType::U8,
-                name: "graph",
-            },
-            BinaryField::F64("rate"), // rate
-            BinaryField::I64("rate_since"), // rate_since
-        ],
+        version,
+        fields,
     }
 }
 
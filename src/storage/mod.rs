@@ -1,5 +1,9 @@
 pub mod binary;
+pub mod cache;
 pub mod layout;
+pub mod wal;
 
 pub use binary::*;
+pub use cache::*;
 pub use layout::*;
+pub use wal::*;
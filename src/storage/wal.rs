@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::ZentryError;
+
+/// One line of the write-ahead log.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WalRecord {
+    /// Logged and synced before any record of the transaction is written
+    Begin { transaction_id: Uuid, entry_ids: Vec<Uuid> },
+    /// Logged once the entries and the transaction have all been written
+    Commit { transaction_id: Uuid },
+}
+
+/// Write-ahead log for operations spanning several record files, see `Ledger::record_transaction`.
+///
+/// Every operation logs its intent (the ids it is about to write) before touching the `.bin`
+/// files and a commit marker afterwards. A `Begin` without a `Commit` means the process stopped
+/// partway through, and `Ledger::load_from_dir` rolls the operation back by tombstoning whatever
+/// records of it made it to disk. The log is emptied whenever no operation is in flight.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    /// `None` for a read-only log whose file doesn't exist, which reads as empty
+    file: Option<File>,
+    read_only: bool,
+    in_flight: HashSet<Uuid>,
+}
+
+impl WriteAheadLog {
+    pub fn open(path: &Path) -> Result<Self, ZentryError> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+
+        Ok(Self {
+            file: Some(file),
+            read_only: false,
+            in_flight: HashSet::new(),
+        })
+    }
+
+    /// Opens the log only to read what is pending, without creating it. Logging and clearing
+    /// fail with [`ZentryError::ReadOnly`].
+    pub fn open_read_only(path: &Path) -> Result<Self, ZentryError> {
+        let file = match File::open(path) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            file,
+            read_only: true,
+            in_flight: HashSet::new(),
+        })
+    }
+
+    pub fn begin(&mut self, transaction_id: Uuid, entry_ids: Vec<Uuid>) -> Result<(), ZentryError> {
+        self.append(&WalRecord::Begin { transaction_id, entry_ids })?;
+        self.in_flight.insert(transaction_id);
+        Ok(())
+    }
+
+    pub fn commit(&mut self, transaction_id: Uuid) -> Result<(), ZentryError> {
+        self.append(&WalRecord::Commit { transaction_id })?;
+        self.in_flight.remove(&transaction_id);
+
+        if self.in_flight.is_empty() {
+            self.clear()?;
+        }
+        Ok(())
+    }
+
+    /// `Begin` records left without a matching `Commit`, in the order they were logged.
+    /// A torn last line, from a crash while it was being written, is ignored.
+    pub fn pending(&mut self) -> Result<Vec<WalRecord>, ZentryError> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(Vec::new());
+        };
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut begun = Vec::new();
+        let mut committed = HashSet::new();
+
+        for line in BufReader::new(&*file).lines() {
+            let line = line?;
+            match serde_json::from_str::<WalRecord>(&line) {
+                Ok(WalRecord::Commit { transaction_id }) => {
+                    committed.insert(transaction_id);
+                }
+                Ok(record) => begun.push(record),
+                Err(_) => break,
+            }
+        }
+
+        Ok(begun.into_iter()
+            .filter(|record| !matches!(record, WalRecord::Begin { transaction_id, .. } if committed.contains(transaction_id)))
+            .collect())
+    }
+
+    pub fn clear(&mut self) -> Result<(), ZentryError> {
+        let file = self.writable_file()?;
+        file.set_len(0)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    fn append(&mut self, record: &WalRecord) -> Result<(), ZentryError> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let file = self.writable_file()?;
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&line)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    fn writable_file(&mut self) -> Result<&mut File, ZentryError> {
+        match self.file.as_mut() {
+            Some(file) if !self.read_only => Ok(file),
+            _ => Err(ZentryError::ReadOnly),
+        }
+    }
+}
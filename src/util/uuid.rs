@@ -1,22 +1,78 @@
-use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
 use uuid::Uuid;
 
-/// Generate a deterministic UUID from a hashable value.
+/// Context string BLAKE3 derives keys under. Bump the version suffix only together with a
+/// format migration: it changes every derived UUID, and with it every persisted `.idx` key.
+pub const KEY_DERIVATION_CONTEXT: &str = "ZentryDB deterministic uuid v1";
+
+/// The canonical bytes a value is keyed by. Unlike `std::hash::Hash`, whose output is not
+/// guaranteed across Rust versions or platforms, these bytes are part of the on-disk format.
+pub trait KeyBytes {
+    fn key_bytes(&self) -> &[u8];
+}
+
+impl KeyBytes for str {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl KeyBytes for String {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl KeyBytes for Uuid {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<T: KeyBytes + ?Sized> KeyBytes for &T {
+    fn key_bytes(&self) -> &[u8] {
+        (**self).key_bytes()
+    }
+}
+
+/// Generate a deterministic UUID from a value's key bytes.
 /// The same input will always generate the same UUID.
 /// 
+/// # Stability
+/// The output only depends on [`KeyBytes::key_bytes`] and [`KEY_DERIVATION_CONTEXT`], both pinned,
+/// so it is the same on every platform, Rust version and build. The UUIDs are persisted as
+/// `.idx` keys, so this is a format guarantee: `"USD"` always maps to
+/// `9484a1e8-0fe8-5721-59a7-8a6368a80461`.
+/// 
 /// # Implementation
-/// Uses a 64-bit hash of the input to generate a 128-bit UUID:
-/// - First 8 bytes: Direct from hash
-/// - Last 8 bytes: Bit-rotated hash for better distribution
-pub fn generate_deterministic_uuid<T: Hash>(value: &T) -> Uuid {
-    let mut hasher = DefaultHasher::new();
-    value.hash(&mut hasher);
-    let hash = hasher.finish();
+/// The bytes are fed through BLAKE3 in key derivation mode and the first 16 bytes of the digest
+/// become the UUID, so all 128 bits are independent instead of being stretched from a 64-bit hash.
+pub fn generate_deterministic_uuid<T: KeyBytes + ?Sized>(value: &T) -> Uuid {
+    let mut hasher = blake3::Hasher::new_derive_key(KEY_DERIVATION_CONTEXT);
+    hasher.update(value.key_bytes());
+    let digest = hasher.finalize();
     
     let mut uuid_bytes = [0u8; 16];
-    uuid_bytes[0..8].copy_from_slice(&hash.to_le_bytes());
-    uuid_bytes[8..16].copy_from_slice(&(hash.rotate_right(32)).to_le_bytes());
+    uuid_bytes.copy_from_slice(&digest.as_bytes()[0..16]);
     
     Uuid::from_bytes(uuid_bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn distinct_inputs_do_not_collide() {
+        let mut seen = HashSet::new();
+        for i in 0..100_000 {
+            assert!(seen.insert(generate_deterministic_uuid(&format!("account-{}", i))), "collision at account-{}", i);
+        }
+    }
+
+    #[test]
+    fn usd_uuid_is_pinned() {
+        let expected = Uuid::parse_str("9484a1e8-0fe8-5721-59a7-8a6368a80461").unwrap();
+        assert_eq!(generate_deterministic_uuid("USD"), expected);
+    }
+}
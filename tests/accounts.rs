@@ -0,0 +1,308 @@
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+use uuid::Uuid;
+use zentry_db::db::Ledger;
+use zentry_db::error::ZentryError;
+use zentry_db::index::SecondaryIndex;
+use zentry_db::model::{register_account_type, Account, AccountType};
+use zentry_db::storage::account_type_from_label;
+use zentry_db::util::uuid::generate_deterministic_uuid;
+
+use common::{account, create_account, ledger, post, reload, system, TempDir};
+
+#[test]
+fn deleted_account_stays_deleted_after_reload() {
+    let dir = TempDir::new("delete_account");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let kept = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let deleted = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    ledger.delete_account(deleted).unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert!(ledger.find_account(deleted).is_none());
+    assert!(ledger.find_account(kept).is_some());
+    assert_eq!(ledger.accounts().count(), 1);
+}
+
+#[test]
+fn account_with_entries_cannot_be_deleted() {
+    let dir = TempDir::new("delete_with_entries");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let equity = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 100.0), (equity, -100.0)]);
+
+    assert!(matches!(ledger.delete_account(cash), Err(ZentryError::InvalidData(_))));
+    assert!(ledger.find_account(cash).is_some());
+}
+
+#[test]
+fn account_with_children_cannot_be_deleted() {
+    let dir = TempDir::new("delete_with_children");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let parent = create_account(&mut ledger, "Assets", AccountType::Asset, "USD");
+    let mut child = account("Cash", AccountType::Asset, "USD");
+    child.parent_id = Some(parent);
+    let child_id = child.id;
+    ledger.create_account(child).unwrap();
+
+    assert!(matches!(ledger.delete_account(parent), Err(ZentryError::InvalidData(_))));
+    assert!(ledger.verify_integrity().is_ok());
+
+    ledger.delete_account(child_id).unwrap();
+    ledger.delete_account(parent).unwrap();
+    assert_eq!(ledger.accounts().count(), 0);
+}
+
+#[test]
+fn accounts_are_grouped_by_system() {
+    let dir = TempDir::new("accounts_by_system");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let bank = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let konto = create_account(&mut ledger, "Konto", AccountType::Asset, "EUR");
+    ledger.flush().unwrap();
+    ledger.persist_indexes().unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let mut usd: Vec<_> = ledger.accounts_in_system("USD").iter().map(|account| account.id).collect();
+    usd.sort();
+    let mut expected = vec![cash, bank];
+    expected.sort();
+    assert_eq!(usd, expected);
+
+    let eur: Vec<_> = ledger.accounts_in_system("EUR").iter().map(|account| account.id).collect();
+    assert_eq!(eur, vec![konto]);
+    assert!(ledger.accounts_in_system("GBP").is_empty());
+
+    let persisted = SecondaryIndex::load(&dir.join("accounts_by_system.idx")).unwrap();
+    assert_eq!(persisted, ledger.account_system_index);
+}
+
+#[test]
+fn accounts_are_found_by_name() {
+    let dir = TempDir::new("account_names");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    assert_eq!(ledger.find_account_by_name("Cash").unwrap().id, cash);
+    assert!(ledger.find_account_by_name("Bank").is_none());
+
+    let duplicate = ledger.create_account(account("Cash", AccountType::Asset, "EUR"));
+    assert!(matches!(duplicate, Err(ZentryError::InvalidData(_))));
+    assert_eq!(ledger.accounts().count(), 1);
+
+    drop(ledger);
+    let ledger = reload(&dir);
+    assert_eq!(ledger.find_account_by_name("Cash").unwrap().id, cash);
+}
+
+#[test]
+fn renamed_account_survives_reload() {
+    let dir = TempDir::new("rename_account");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let old_offset = ledger.account_index.get(&generate_deterministic_uuid(&cash)).unwrap();
+    ledger.update_account(cash, account("Petty cash", AccountType::Asset, "USD")).unwrap();
+    ledger.flush().unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let renamed = ledger.find_account(cash).unwrap();
+    assert_eq!(renamed.id, cash);
+    assert_eq!(renamed.name, "Petty cash");
+    assert!(ledger.find_account_by_name("Cash").is_none());
+    assert_ne!(ledger.account_index.get(&generate_deterministic_uuid(&cash)), Some(old_offset));
+    assert!(matches!(ledger.storage.read_single::<Account>(old_offset), Err(ZentryError::DeadRecord)));
+}
+
+#[test]
+fn long_strings_fit_their_length_prefix_or_are_rejected_by_name() {
+    let dir = TempDir::new("long_names");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    // `name` has a u16 length prefix
+    let long_name = "n".repeat(300);
+    let id = create_account(&mut ledger, &long_name, AccountType::Asset, "USD");
+    drop(ledger);
+    let mut ledger = reload(&dir);
+    assert_eq!(ledger.find_account(id).unwrap().name, long_name);
+
+    // `system_id` still has a u8 one
+    let long_system = "s".repeat(300);
+    match ledger.create_account(account("Wide", AccountType::Asset, &long_system)) {
+        Err(ZentryError::InvalidData(message)) => assert!(message.contains("`system_id` is 300 bytes"), "unexpected message: {}", message),
+        other => panic!("expected InvalidData, got {:?}", other),
+    }
+    assert!(ledger.find_account_by_name("Wide").is_none());
+}
+
+fn create_child(ledger: &mut Ledger, name: &str, parent: Uuid) -> Uuid {
+    let mut child = account(name, AccountType::Asset, "USD");
+    child.parent_id = Some(parent);
+    let id = child.id;
+    ledger.create_account(child).unwrap();
+    id
+}
+
+#[test]
+fn account_subtree_spans_two_levels() {
+    let dir = TempDir::new("subtree");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let assets = create_account(&mut ledger, "Assets", AccountType::Asset, "USD");
+    let cash = create_child(&mut ledger, "Cash", assets);
+    let bank = create_child(&mut ledger, "Bank", assets);
+    let checking = create_child(&mut ledger, "Checking", bank);
+    let savings = create_child(&mut ledger, "Savings", bank);
+    create_account(&mut ledger, "Loan", AccountType::Liability, "USD");
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert_eq!(ledger.find_account(checking).unwrap().parent_id, Some(bank));
+
+    let subtree: Vec<_> = ledger.account_subtree(assets).iter().map(|account| account.id).collect();
+    assert_eq!(subtree, [bank, cash, checking, savings]);
+    let subtree: Vec<_> = ledger.account_subtree(bank).iter().map(|account| account.id).collect();
+    assert_eq!(subtree, [checking, savings]);
+    assert!(ledger.account_subtree(cash).is_empty());
+}
+
+#[test]
+fn rolled_up_balance_sums_the_subtree() {
+    let dir = TempDir::new("rolled_up_balance");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let assets = create_account(&mut ledger, "Assets", AccountType::Asset, "USD");
+    let cash = create_child(&mut ledger, "Cash", assets);
+    let bank = create_child(&mut ledger, "Bank", assets);
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    post(&mut ledger, Utc::now(), &[(assets, 5.0), (cash, 20.0), (bank, 75.0), (capital, -100.0)]);
+    post(&mut ledger, Utc::now(), &[(cash, -10.0), (bank, 10.0)]);
+
+    assert_eq!(ledger.rolled_up_balance(assets), 100.0);
+    assert_eq!(ledger.rolled_up_balance(bank), 85.0);
+    assert_eq!(ledger.account_balance(assets), 5.0);
+}
+
+#[test]
+fn balance_as_of_leaves_out_later_transactions() {
+    let dir = TempDir::new("balance_as_of");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+
+    let boundary = Utc.with_ymd_and_hms(2026, 6, 30, 23, 59, 59).unwrap();
+    post(&mut ledger, boundary - Duration::days(10), &[(cash, 100.0), (capital, -100.0)]);
+    post(&mut ledger, boundary, &[(cash, 20.0), (capital, -20.0)]);
+    post(&mut ledger, boundary + Duration::seconds(1), &[(cash, 300.0), (capital, -300.0)]);
+
+    assert_eq!(ledger.account_balance_as_of(cash, boundary - Duration::days(11)), 0.0);
+    assert_eq!(ledger.account_balance_as_of(cash, boundary), 120.0);
+    assert_eq!(ledger.account_balance_as_of(capital, boundary), -120.0);
+    assert_eq!(ledger.account_balance(cash), 420.0);
+}
+
+#[test]
+fn custom_account_type_survives_reload() {
+    let dir = TempDir::new("custom_account_type");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let contra = register_account_type(40, "ContraAsset", false).unwrap();
+    assert_eq!(register_account_type(40, "ContraAsset", false).unwrap(), contra);
+    assert!(matches!(register_account_type(40, "GainLoss", true), Err(ZentryError::AlreadyExists(_))));
+    assert!(matches!(register_account_type(41, "asset", true), Err(ZentryError::AlreadyExists(_))));
+    assert!(matches!(register_account_type(2, "Reserve", true), Err(ZentryError::InvalidData(_))));
+
+    let depreciation = create_account(&mut ledger, "Accumulated depreciation", contra.clone(), "USD");
+    let unknown = create_account(&mut ledger, "Unknown", AccountType::Custom(42), "USD");
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let account_type = &ledger.find_account(depreciation).unwrap().account_type;
+    assert_eq!(*account_type, AccountType::Custom(40));
+    assert_eq!(account_type.label(), "ContraAsset");
+    assert!(!account_type.is_debit_normal());
+    assert_eq!(account_type_from_label("contraasset"), Some(contra));
+
+    // a byte nothing registered still loads, as a debit-normal fallback
+    let account_type = &ledger.find_account(unknown).unwrap().account_type;
+    assert_eq!(*account_type, AccountType::Custom(42));
+    assert_eq!(account_type.label(), "Custom(42)");
+    assert!(account_type.is_debit_normal());
+}
+
+#[test]
+fn find_account_takes_the_raw_id() {
+    let dir = TempDir::new("find_account");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cash = account("Cash", AccountType::Asset, "USD");
+    let id = cash.id;
+    ledger.create_account(cash).unwrap();
+
+    assert_eq!(ledger.find_account(id).unwrap().name, "Cash");
+    // the derived key is what `accounts` is keyed by, not an account id
+    let key = generate_deterministic_uuid(&id);
+    assert!(ledger.accounts.contains_key(&key));
+    assert!(ledger.find_account(key).is_none());
+
+    // entries carry the raw id as well
+    let entry = ledger.new_entry(id, 1.0).unwrap();
+    assert_eq!(entry.account_id, id);
+    drop(ledger);
+
+    assert_eq!(reload(&dir).find_account(id).unwrap().id, id);
+}
+
+#[test]
+fn deterministic_account_is_stored_once() {
+    let dir = TempDir::new("deterministic_account");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let first = Account::deterministic("USD", "Cash", AccountType::Asset, Utc::now() - Duration::days(1));
+    let again = Account::deterministic("USD", "Cash", AccountType::Asset, Utc::now());
+    assert_eq!(first.id, again.id);
+    assert_ne!(
+        Account::deterministic("A", "B:C", AccountType::Asset, Utc::now()).id,
+        Account::deterministic("A:B", "C", AccountType::Asset, Utc::now()).id
+    );
+    assert_ne!(first.id, Account::deterministic("EUR", "Cash", AccountType::Asset, Utc::now()).id);
+
+    ledger.create_account(first.clone()).unwrap();
+    assert!(matches!(ledger.create_account(again), Err(ZentryError::InvalidData(_))));
+
+    // re-running an import turns the repeated rows away
+    let csv = dir.join("accounts.csv");
+    std::fs::write(&csv, "name,account_type,system_id\nBank,Asset,USD\n").unwrap();
+    assert_eq!(ledger.import_accounts_csv(&csv).unwrap(), 1);
+    assert!(matches!(ledger.import_accounts_csv(&csv), Err(ZentryError::InvalidData(_))));
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert_eq!(ledger.iter_accounts().unwrap().count(), 2);
+    assert_eq!(ledger.find_account(first.id).unwrap().created_at, first.created_at);
+}
@@ -0,0 +1,81 @@
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+use zentry_db::interface::cli::{normalized_balance, paginate, render_rows, ConversionGraphRow, OutputMode, TrialBalanceRow};
+use zentry_db::model::{register_account_type, AccountType};
+
+#[test]
+fn conversions_render_as_a_json_array_of_rows() {
+    let rate_since = Utc.with_ymd_and_hms(2026, 4, 1, 9, 0, 0).unwrap();
+    let rows = vec![
+        ConversionGraphRow { graph: "EUR -> USD".to_string(), rate: 1.1, rate_since },
+        ConversionGraphRow { graph: "USD -> EUR".to_string(), rate: 0.9, rate_since },
+    ];
+
+    let json: Value = serde_json::from_str(&render_rows(rows, OutputMode::Json).unwrap()).unwrap();
+    assert_eq!(json, json!([
+        {"graph": "EUR -> USD", "rate": 1.1, "rate_since": "2026-04-01T09:00:00Z"},
+        {"graph": "USD -> EUR", "rate": 0.9, "rate_since": "2026-04-01T09:00:00Z"},
+    ]));
+}
+
+#[test]
+fn paginate_slices_250_items() {
+    let items: Vec<usize> = (0..250).collect();
+
+    let (first, pages) = paginate(items.clone(), 1, 100);
+    assert_eq!(pages, 3);
+    assert_eq!(first, (0..100).collect::<Vec<_>>());
+
+    let (last, _) = paginate(items.clone(), 3, 100);
+    assert_eq!(last, (200..250).collect::<Vec<_>>());
+
+    let (past_the_end, pages) = paginate(items.clone(), 4, 100);
+    assert!(past_the_end.is_empty());
+    assert_eq!(pages, 3);
+
+    assert_eq!(paginate(items.clone(), 1, 50).1, 5);
+    assert_eq!(paginate(items, 1, 251).1, 1);
+    assert_eq!(paginate(Vec::<usize>::new(), 1, 50), (Vec::new(), 1));
+}
+
+#[test]
+fn balances_read_positive_on_their_normal_side() {
+    let deferred = register_account_type(70, "DeferredRevenue", false).unwrap();
+    let cases = [
+        (AccountType::Asset, 50.0, 50.0),
+        (AccountType::Expense, 50.0, 50.0),
+        (AccountType::Liability, -50.0, 50.0),
+        (AccountType::Equity, -50.0, 50.0),
+        (AccountType::Revenue, -50.0, 50.0),
+        (deferred, -50.0, 50.0),
+        // unregistered custom types fall back to debit-normal
+        (AccountType::Custom(71), 50.0, 50.0),
+        // against the normal side the balance reads negative
+        (AccountType::Asset, -20.0, -20.0),
+        (AccountType::Liability, 20.0, -20.0),
+    ];
+    for (account_type, raw, shown) in cases {
+        assert_eq!(normalized_balance(&account_type, raw), shown, "{:?} {}", account_type, raw);
+    }
+
+    for account_type in [AccountType::Liability, AccountType::Equity, AccountType::Revenue] {
+        assert!(normalized_balance(&account_type, 0.0).is_sign_positive(), "{:?} shows -0", account_type);
+    }
+}
+
+#[test]
+fn balances_show_formatted_but_serialize_as_numbers() {
+    let rows = || vec![TrialBalanceRow {
+        system_id: "JPY".to_string(),
+        account_type: "Asset".to_string(),
+        balance: 1234.56,
+        shown_balance: "¥1235".to_string(),
+    }];
+
+    let table = render_rows(rows(), OutputMode::Table).unwrap();
+    assert!(table.contains("¥1235") && !table.contains("1234.56"), "{}", table);
+    assert!(!table.contains("shown_balance"), "{}", table);
+
+    let json: Value = serde_json::from_str(&render_rows(rows(), OutputMode::Json).unwrap()).unwrap();
+    assert_eq!(json, json!([{"system_id": "JPY", "account_type": "Asset", "balance": 1234.56}]));
+}
@@ -0,0 +1,113 @@
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use zentry_db::db::Ledger;
+use zentry_db::install_at;
+use zentry_db::model::{Account, AccountType, ConversionGraph, System, Transaction};
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A data directory of its own under the system temp dir, removed when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "zentry_test_{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, file: &str) -> PathBuf {
+        self.path.join(file)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Installs a fresh database in `dir` and loads it
+pub fn ledger(dir: &TempDir) -> Ledger {
+    install_at(dir.path()).unwrap();
+    Ledger::load_from_dir(dir.path()).unwrap()
+}
+
+/// Loads the ledger in `dir` again, the way the next run of the program would
+pub fn reload(dir: &TempDir) -> Ledger {
+    Ledger::load_from_dir(dir.path()).unwrap()
+}
+
+pub fn system(id: &str) -> System {
+    System {
+        id: id.to_string(),
+        description: format!("{} system", id),
+        symbol: None,
+        decimal_places: None,
+    }
+}
+
+pub fn account(name: &str, account_type: AccountType, system_id: &str) -> Account {
+    Account {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        account_type,
+        created_at: Utc::now(),
+        system_id: system_id.to_string(),
+        parent_id: None,
+    }
+}
+
+/// Creates the account and returns its raw `Account::id`
+pub fn create_account(ledger: &mut Ledger, name: &str, account_type: AccountType, system_id: &str) -> Uuid {
+    let account = account(name, account_type, system_id);
+    let id = account.id;
+    ledger.create_account(account).unwrap();
+    id
+}
+
+pub fn transaction(description: &str, timestamp: DateTime<Utc>) -> Transaction {
+    Transaction {
+        id: Uuid::new_v4(),
+        description: description.to_string(),
+        timestamp,
+        metadata: None,
+    }
+}
+
+/// Records one transaction timestamped `timestamp` with a leg per `(account id, amount)` and
+/// returns its id
+pub fn post(ledger: &mut Ledger, timestamp: DateTime<Utc>, legs: &[(Uuid, f64)]) -> Uuid {
+    let tx = transaction("posting", timestamp);
+    let id = tx.id;
+    let entries = legs.iter().map(|(account_id, amount)| ledger.new_entry(*account_id, *amount).unwrap()).collect();
+    ledger.record_transaction(tx, entries).unwrap();
+    id
+}
+
+pub fn graph(graph: &str, rate: f64, rate_since: DateTime<Utc>) -> ConversionGraph {
+    ConversionGraph {
+        graph: graph.to_string(),
+        rate,
+        rate_since,
+        exact_rate: None,
+    }
+}
@@ -0,0 +1,520 @@
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+use zentry_db::db::Ledger;
+use zentry_db::error::ZentryError;
+use zentry_db::model::{AccountType, ExactRate, GraphDirection, GraphKey};
+use zentry_db::storage::{classify_graph_key, GraphKeyClass};
+use zentry_db::util::uuid::generate_deterministic_uuid;
+use zentry_db::TombstoneWriter;
+
+use common::{account, graph, ledger, reload, system, TempDir};
+
+#[test]
+fn archived_graph_survives_reload() {
+    let dir = TempDir::new("archived_graph");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let first_since = Utc::now() - Duration::hours(2);
+    let second_since = Utc::now() - Duration::hours(1);
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, first_since)).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, second_since)).unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let archived: Vec<_> = ledger.conversion_graphs().filter(|graph| graph.is_historical()).collect();
+    assert_eq!(archived.len(), 1);
+
+    let (since, relation, until) = archived[0].historical_window().unwrap();
+    assert_eq!(relation, "USD -> EUR");
+    assert_eq!(since, first_since);
+    assert_eq!(until, second_since);
+    assert_eq!(archived[0].rate, 0.9);
+    assert_eq!(ledger.active_conversion_graphs().count(), 1);
+}
+
+#[test]
+fn zero_rate_bidirectional_conversion_is_rejected() {
+    let dir = TempDir::new("zero_rate");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let result = ledger.create_conversion_graph(graph("USD <-> EUR", 0.0, Utc::now()));
+    assert!(matches!(result, Err(ZentryError::InvalidData(_))));
+    assert_eq!(ledger.conversion_graphs().count(), 0);
+
+    for rate in [f64::NAN, f64::INFINITY] {
+        for relation in ["USD -> EUR", "USD <- EUR"] {
+            let result = ledger.create_conversion_graph(graph(relation, rate, Utc::now()));
+            assert!(matches!(result, Err(ZentryError::InvalidData(_))), "{} at {} was accepted", relation, rate);
+        }
+    }
+}
+
+#[test]
+fn rate_at_follows_the_archived_window() {
+    let dir = TempDir::new("rate_at");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let first_since = Utc::now() - Duration::hours(2);
+    let second_since = Utc::now() - Duration::hours(1);
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, first_since)).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, second_since)).unwrap();
+
+    assert_eq!(ledger.rate_at("USD", "EUR", first_since - Duration::minutes(1)), None);
+    assert_eq!(ledger.rate_at("USD", "EUR", first_since), Some(0.9));
+    assert_eq!(ledger.rate_at("USD", "EUR", second_since - Duration::seconds(1)), Some(0.9));
+    assert_eq!(ledger.rate_at("USD", "EUR", second_since), Some(0.8));
+    assert_eq!(ledger.rate_at("USD", "EUR", Utc::now()), Some(0.8));
+}
+
+#[test]
+fn deleted_conversion_no_longer_converts() {
+    let dir = TempDir::new("delete_conversion");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let since = Utc::now() - Duration::hours(1);
+    ledger.create_conversion_graph(graph("USD <-> EUR", 0.5, since)).unwrap();
+    assert_eq!(ledger.convert(10.0, "USD", "EUR"), Some(5.0));
+    assert_eq!(ledger.convert(10.0, "EUR", "USD"), Some(20.0));
+
+    ledger.delete_conversion_graph("USD <-> EUR").unwrap();
+    assert_eq!(ledger.convert(10.0, "USD", "EUR"), None);
+    assert_eq!(ledger.convert(10.0, "EUR", "USD"), None);
+    assert_eq!(ledger.active_conversion_graphs().count(), 0);
+
+    drop(ledger);
+    let ledger = reload(&dir);
+    assert_eq!(ledger.convert(10.0, "USD", "EUR"), None);
+    assert_eq!(ledger.rate_at("USD", "EUR", since + Duration::minutes(30)), Some(0.5));
+}
+
+#[test]
+fn conversions_for_system_lists_active_graphs_on_either_side() {
+    let dir = TempDir::new("conversions_for_system");
+    let mut ledger = ledger(&dir);
+    for id in ["USD", "EUR", "JPY", "GBP"] {
+        ledger.create_system(system(id)).unwrap();
+    }
+
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, Utc::now() - Duration::hours(2))).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, Utc::now() - Duration::hours(1))).unwrap();
+    ledger.create_conversion_graph(graph("JPY -> USD", 0.007, Utc::now())).unwrap();
+    ledger.create_conversion_graph(graph("EUR -> GBP", 0.85, Utc::now())).unwrap();
+
+    let usd: Vec<_> = ledger.conversions_for_system("USD").iter().map(|graph| (graph.graph.as_str(), graph.rate)).collect();
+    assert_eq!(usd, [("JPY -> USD", 0.007), ("USD -> EUR", 0.8)]);
+
+    let gbp: Vec<_> = ledger.conversions_for_system("GBP").iter().map(|graph| graph.graph.as_str()).collect();
+    assert_eq!(gbp, ["EUR -> GBP"]);
+    assert!(ledger.conversions_for_system("US").is_empty());
+}
+
+#[test]
+fn rate_inconsistencies_are_detected() {
+    let dir = TempDir::new("rate_inconsistencies");
+    let mut ledger = ledger(&dir);
+    for id in ["USD", "EUR", "GBP"] {
+        ledger.create_system(system(id)).unwrap();
+    }
+
+    let since = Utc::now() - Duration::hours(1);
+    ledger.create_conversion_graph(graph("USD <-> EUR", 0.5, since)).unwrap();
+    ledger.create_conversion_graph(graph("EUR -> GBP", 0.8, since)).unwrap();
+    ledger.create_conversion_graph(graph("USD -> GBP", 0.4, since)).unwrap();
+    assert!(ledger.detect_rate_inconsistencies(0.01).is_empty());
+
+    // the replaced rates break a round trip and both triangles
+    ledger.create_conversion_graph(graph("EUR -> USD", 2.5, Utc::now())).unwrap();
+    ledger.create_conversion_graph(graph("USD -> GBP", 0.5, Utc::now())).unwrap();
+    let problems = ledger.detect_rate_inconsistencies(0.01);
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(problems.iter().any(|problem| problem.starts_with("EUR -> USD -> EUR round trip gives 1.25")), "{:?}", problems);
+    assert!(problems.iter().any(|problem| problem.starts_with("USD -> GBP is 0.5 directly but 0.4 through EUR")), "{:?}", problems);
+    assert!(problems.iter().any(|problem| problem.starts_with("EUR -> GBP is 0.8 directly but 1.25 through USD")), "{:?}", problems);
+}
+
+#[test]
+fn timestamps_keep_sub_second_precision() {
+    let dir = TempDir::new("sub_second");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let since = Utc.with_ymd_and_hms(2026, 5, 1, 8, 30, 0).unwrap() + Duration::milliseconds(123);
+    let replaced = since + Duration::milliseconds(1);
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, since)).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, replaced)).unwrap();
+
+    let mut cash = account("Cash", AccountType::Asset, "USD");
+    cash.created_at = since;
+    let cash_id = cash.id;
+    ledger.create_account(cash).unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert_eq!(ledger.find_account(cash_id).unwrap().created_at, since);
+    assert_eq!(ledger.active_conversion_graphs().next().unwrap().rate_since, replaced);
+    assert_eq!(ledger.rate_at("USD", "EUR", since), Some(0.9));
+    assert_eq!(ledger.rate_at("USD", "EUR", replaced), Some(0.8));
+}
+
+#[test]
+fn rapid_replacements_keep_every_archived_window() {
+    let dir = TempDir::new("rapid_replacements");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let base = Utc::now() - Duration::seconds(1);
+    let since: Vec<_> = (0..4).map(|i| base + Duration::milliseconds(i)).collect();
+    for (i, rate_since) in since.iter().enumerate() {
+        ledger.create_conversion_graph(graph("USD -> EUR", 1.0 + i as f64 / 10.0, *rate_since)).unwrap();
+    }
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let mut windows: Vec<_> = ledger.conversion_graphs()
+        .filter_map(|graph| graph.historical_window().map(|(start, _, end)| (start, end, graph.rate)))
+        .collect();
+    windows.sort_by_key(|(start, _, _)| *start);
+    assert_eq!(windows, [(since[0], since[1], 1.0), (since[1], since[2], 1.1), (since[2], since[3], 1.2)]);
+
+    for (i, rate_since) in since.iter().enumerate() {
+        assert_eq!(ledger.rate_at("USD", "EUR", *rate_since), Some(1.0 + i as f64 / 10.0));
+    }
+}
+
+#[test]
+fn rate_at_prefers_the_latest_of_overlapping_windows() {
+    let dir = TempDir::new("overlapping_windows");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let archived_since = Utc::now() - Duration::hours(2);
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, archived_since)).unwrap();
+    ledger.delete_conversion_graph("USD -> EUR").unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, archived_since - Duration::hours(1))).unwrap();
+
+    let check = |ledger: &Ledger| {
+        assert_eq!(ledger.rate_at("USD", "EUR", archived_since - Duration::minutes(30)), Some(0.8));
+        assert_eq!(ledger.rate_at("USD", "EUR", archived_since + Duration::minutes(30)), Some(0.9));
+        assert_eq!(ledger.rate_at("USD", "EUR", Utc::now()), Some(0.8));
+    };
+    check(&ledger);
+    drop(ledger);
+    check(&reload(&dir));
+}
+
+#[test]
+fn plan_for_bidirectional_rate_archives_the_forward_rate() {
+    let dir = TempDir::new("plan_conversion");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let first_since = Utc::now() - Duration::hours(1);
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, first_since)).unwrap();
+
+    let replacement = graph("USD <-> EUR", 0.5, Utc::now());
+    let plan = ledger.plan_conversion_graph(&replacement).unwrap();
+
+    assert_eq!(plan.archived.len(), 1);
+    let archive = &plan.archived[0];
+    assert_eq!((archive.graph.graph.as_str(), archive.graph.rate), ("USD -> EUR", 0.8));
+    assert!(archive.historical_key.contains("[USD -> EUR]"), "{}", archive.historical_key);
+    assert_eq!(archive.historical_uuid, generate_deterministic_uuid(&archive.historical_key));
+
+    let created: Vec<_> = plan.created.iter().map(|conversion| (conversion.graph.graph.as_str(), conversion.graph.rate)).collect();
+    assert_eq!(created, [("USD -> EUR", 0.5), ("EUR -> USD", 2.0)]);
+    for conversion in &plan.created {
+        assert_eq!(conversion.uuid, generate_deterministic_uuid(&conversion.graph.graph));
+    }
+
+    // planning touched nothing
+    assert_eq!(ledger.conversion_graphs().count(), 1);
+    assert_eq!(ledger.convert(10.0, "USD", "EUR"), Some(8.0));
+
+    ledger.create_conversion_graph(replacement).unwrap();
+    assert!(ledger.conversion_graphs.contains_key(&archive.historical_uuid));
+    assert_eq!(ledger.convert(10.0, "EUR", "USD"), Some(20.0));
+}
+
+#[test]
+fn graph_keys_parse_every_arrow() {
+    let parsed = |key: &str| {
+        let key = GraphKey::parse(key).unwrap();
+        (key.from.clone(), key.direction, key.to.clone(), key.to_storage_key())
+    };
+    let expected = |from: &str, direction, to: &str, storage: &str| (from.to_string(), direction, to.to_string(), storage.to_string());
+
+    assert_eq!(parsed("USD -> EUR"), expected("USD", GraphDirection::Forward, "EUR", "USD -> EUR"));
+    assert_eq!(parsed("USD <- EUR"), expected("USD", GraphDirection::Backward, "EUR", "EUR -> USD"));
+    assert_eq!(parsed("USD <-> EUR"), expected("USD", GraphDirection::Both, "EUR", "USD -> EUR"));
+    assert_eq!(GraphKey::parse("USD <-> EUR").unwrap().reversed().to_storage_key(), "EUR -> USD");
+
+    for key in ["USD->EUR", "  USD   ->\tEUR ", "USD-> EUR"] {
+        assert_eq!(parsed(key), expected("USD", GraphDirection::Forward, "EUR", "USD -> EUR"), "{:?}", key);
+    }
+    assert_eq!(parsed("gold_oz<->USD").3, "gold_oz -> USD");
+    assert_eq!(parsed("US-D -> BTC.e"), expected("US-D", GraphDirection::Forward, "BTC.e", "US-D -> BTC.e"));
+    assert_eq!(parsed("A-->-B"), expected("A-", GraphDirection::Forward, "-B", "A- -> -B"));
+
+    for key in ["", "USD", "USD EUR", "USD => EUR", "-> EUR", "USD <->", "US D -> EUR", "USD -> EUR -> JPY", "USD -> E>UR", "U<SD -> EUR"] {
+        assert!(matches!(GraphKey::parse(key), Err(ZentryError::InvalidData(_))), "{:?} parsed", key);
+    }
+}
+
+#[test]
+fn exact_round_trip_gives_back_the_original_amount() {
+    let dir = TempDir::new("exact_round_trip");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let mut usd_eur = graph("USD <-> EUR", 0.0, Utc::now());
+    usd_eur.exact_rate = ExactRate::parse("1.0834");
+    ledger.create_conversion_graph(usd_eur).unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let forward = ledger.exact_rate_at("USD", "EUR", Utc::now()).unwrap();
+    let back = ledger.exact_rate_at("EUR", "USD", Utc::now()).unwrap();
+    assert_eq!(forward, ExactRate::new(5417, 5000).unwrap());
+    assert_eq!(back, forward.reciprocal());
+
+    let round_trip = forward.checked_mul(&back).unwrap();
+    assert_eq!(round_trip, ExactRate::new(1, 1).unwrap());
+    for amount in [123.45, 0.1, 1e9 + 0.07] {
+        assert_eq!(round_trip.apply(amount, 2), Some(amount));
+    }
+    assert_eq!(ledger.convert_exact(100.0, "USD", "EUR"), Some(108.34));
+    assert_eq!(ledger.convert_exact(108.34, "EUR", "USD"), Some(100.0));
+}
+
+#[test]
+fn self_conversion_is_rejected_in_every_direction() {
+    let dir = TempDir::new("self_conversion");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    for relation in ["USD -> USD", "USD <- USD", "USD <-> USD"] {
+        match ledger.create_conversion_graph(graph(relation, 1.0, Utc::now())) {
+            Err(ZentryError::InvalidData(message)) => assert!(message.contains("to itself"), "{}: {}", relation, message),
+            other => panic!("{} was not rejected: {:?}", relation, other),
+        }
+    }
+    assert_eq!(ledger.conversion_graphs().count(), 0);
+}
+
+#[test]
+fn two_hop_conversion_explains_both_legs_in_order() {
+    let dir = TempDir::new("explained_path");
+    let mut ledger = ledger(&dir);
+    for id in ["USD", "EUR", "GBP"] {
+        ledger.create_system(system(id)).unwrap();
+    }
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.5, Utc::now())).unwrap();
+    ledger.create_conversion_graph(graph("GBP <- EUR", 0.25, Utc::now())).unwrap();
+
+    let path = ledger.convert_explained(100.0, "USD", "GBP", false).unwrap();
+    let hops: Vec<_> = path.hops.iter().map(|(from, to, rate)| (from.as_str(), to.as_str(), *rate)).collect();
+    assert_eq!(hops, [("USD", "EUR", 0.5), ("EUR", "GBP", 0.25)]);
+    assert!(path.derived.is_empty());
+    assert_eq!(path.result, 12.5);
+    assert_eq!(ledger.convert(100.0, "USD", "GBP"), Some(path.result));
+
+    assert!(ledger.convert_explained(100.0, "USD", "USD", false).unwrap().hops.is_empty());
+    assert!(ledger.convert_explained(100.0, "GBP", "USD", false).is_none());
+}
+
+#[test]
+fn active_conversion_graphs_leave_archived_ones_out() {
+    let dir = TempDir::new("active_only");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let archived_since = Utc::now() - Duration::hours(1);
+    let active_since = Utc::now();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, archived_since)).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, active_since)).unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let active: Vec<_> = ledger.active_conversion_graphs().map(|graph| (graph.graph.as_str(), graph.rate)).collect();
+    assert_eq!(active, [("USD -> EUR", 0.8)]);
+
+    let classes: Vec<_> = ledger.conversion_graphs().map(|graph| (classify_graph_key(graph), graph.rate)).collect();
+    assert_eq!(classes.len(), 2);
+    assert!(classes.contains(&(GraphKeyClass::Active, 0.8)) && classes.contains(&(GraphKeyClass::Historical, 0.9)), "{:?}", classes);
+
+    let archived = ledger.conversion_graphs().find(|graph| graph.is_historical()).unwrap();
+    assert_eq!(archived.historical_window(), Some((archived_since, "USD -> EUR", active_since)));
+}
+
+#[test]
+fn reverse_rate_is_derived_only_when_asked() {
+    let dir = TempDir::new("reciprocal_rate");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.5, Utc::now())).unwrap();
+
+    let path = ledger.convert_explained(10.0, "EUR", "USD", true).unwrap();
+    let hops: Vec<_> = path.hops.iter().map(|(from, to, rate)| (from.as_str(), to.as_str(), *rate)).collect();
+    assert_eq!(hops, [("EUR", "USD", 2.0)]);
+    assert_eq!(path.derived, [0]);
+    assert_eq!(path.result, 20.0);
+
+    assert!(ledger.convert_explained(10.0, "EUR", "USD", false).is_none());
+    assert_eq!(ledger.convert(10.0, "EUR", "USD"), None);
+    assert_eq!(ledger.convert_exact(10.0, "EUR", "USD"), None);
+
+    // the stored direction is never reported as derived
+    assert!(ledger.convert_explained(10.0, "USD", "EUR", true).unwrap().derived.is_empty());
+}
+
+#[test]
+fn rate_since_has_to_move_forward() {
+    let dir = TempDir::new("rate_since_order");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+    let earlier = Utc::now() - Duration::hours(2);
+    let later = Utc::now() - Duration::hours(1);
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, earlier)).unwrap();
+    ledger.create_conversion_graph(graph("EUR -> USD", 1.2, later)).unwrap();
+    let before: Vec<_> = ledger.conversion_graphs().map(|graph| (graph.graph.clone(), graph.rate)).collect();
+
+    let invalid = |result| match result {
+        Err(ZentryError::InvalidData(message)) => message,
+        other => panic!("expected InvalidData, got {:?}", other),
+    };
+
+    let future = Utc::now() + Duration::hours(1);
+    let message = invalid(ledger.create_conversion_graph(graph("USD -> EUR", 0.8, future)));
+    assert_eq!(message, format!("rate_since {} is in the future", future.to_rfc3339()));
+
+    let message = invalid(ledger.create_conversion_graph(graph("USD -> EUR", 0.8, earlier)));
+    assert_eq!(message, format!(
+        "rate_since {} must be after the active rate for USD -> EUR (since {})", earlier.to_rfc3339(), earlier.to_rfc3339()
+    ));
+    let message = invalid(ledger.create_conversion_graph(graph("USD -> EUR", 0.8, earlier - Duration::minutes(1))));
+    assert!(message.ends_with(&format!("for USD -> EUR (since {})", earlier.to_rfc3339())), "{}", message);
+
+    // after the forward rate but not the reverse one, so neither side is replaced
+    let between = earlier + Duration::minutes(30);
+    let message = invalid(ledger.create_conversion_graph(graph("USD <-> EUR", 0.8, between)));
+    assert_eq!(message, format!(
+        "rate_since {} must be after the active rate for EUR -> USD (since {})", between.to_rfc3339(), later.to_rfc3339()
+    ));
+
+    let after: Vec<_> = ledger.conversion_graphs().map(|graph| (graph.graph.clone(), graph.rate)).collect();
+    assert_eq!(after, before);
+    assert_eq!(ledger.conversion_graphs().filter(|graph| graph.is_historical()).count(), 0);
+}
+
+#[test]
+fn hyphenated_system_ids_survive_compaction() {
+    let dir = TempDir::new("hyphenated_ids");
+    let ledger = ledger(&dir);
+
+    // written straight to storage, the way a data dir from before system ids were checked holds them
+    for id in ["US-D", "BTC.e"] {
+        ledger.storage.write(system(id)).unwrap();
+    }
+    let archived = "2024-01-01T00:00:00+00:00[US-D -> BTC.e]2024-06-01T00:00:00+00:00";
+    ledger.storage.write(graph(archived, 0.00002, "2024-01-01T00:00:00Z".parse().unwrap())).unwrap();
+    ledger.storage.write(graph("US-D -> BTC.e", 0.00001, "2024-06-01T00:00:00Z".parse().unwrap())).unwrap();
+    ledger.flush().unwrap();
+    drop(ledger);
+
+    let mut ledger = reload(&dir);
+    let classes: Vec<_> = ledger.conversion_graphs().map(classify_graph_key).collect();
+    assert_eq!(classes, [GraphKeyClass::Historical, GraphKeyClass::Active]);
+    ledger.compact().unwrap();
+    drop(ledger);
+
+    let mut ledger = reload(&dir);
+    assert_eq!(ledger.conversion_graphs().count(), 2);
+    assert_eq!(ledger.convert(100_000.0, "US-D", "BTC.e"), Some(1.0));
+    assert_eq!(ledger.rate_at("US-D", "BTC.e", "2024-03-01T00:00:00Z".parse().unwrap()), Some(0.00002));
+
+    // and the same ids go in through the regular path now
+    ledger.create_conversion_graph(graph("BTC.e -> US-D", 90_000.0, Utc::now())).unwrap();
+    ledger.create_system(system("EUR-x")).unwrap();
+    assert!(matches!(ledger.create_system(system("US D")), Err(ZentryError::InvalidData(_))));
+    assert!(matches!(ledger.create_system(system("A>B")), Err(ZentryError::InvalidData(_))));
+    assert!(ledger.find_system("US D").is_none());
+}
+
+#[test]
+fn exact_conversion_over_three_hops_rounds_once() {
+    let dir = TempDir::new("exact_three_hops");
+    let mut ledger = ledger(&dir);
+    for id in ["USD", "EUR", "GBP", "CHF"] {
+        ledger.create_system(system(id)).unwrap();
+    }
+    for (relation, rate) in [("USD -> EUR", "0.913713"), ("EUR -> GBP", "0.857129"), ("GBP -> CHF", "1.276931")] {
+        let mut conversion = graph(relation, 0.0, Utc::now());
+        conversion.exact_rate = ExactRate::parse(rate);
+        ledger.create_conversion_graph(conversion).unwrap();
+    }
+
+    // none of the fractions reduce, the product's numerator is past 2^53
+    let product = ["USD", "EUR", "GBP", "CHF"].windows(2)
+        .map(|pair| ledger.exact_rate_at(pair[0], pair[1], Utc::now()).unwrap())
+        .try_fold(ExactRate::ONE, |product, rate| product.checked_mul(&rate))
+        .unwrap();
+    assert_eq!(product, ExactRate::new(1_000_053_936_316_840_587, 1_000_000_000_000_000_000).unwrap());
+    assert!(product.numerator() > 1 << 53);
+
+    // worked out with exact fractions; multiplying in f64 gives 1234.6265876193186 and so on
+    let cases = [(1234.56, 1234.63), (100.0, 100.01), (99_999.99, 100_005.38), (-250.05, -250.06)];
+    for (amount, expected) in cases {
+        assert_eq!(ledger.convert_exact(amount, "USD", "CHF"), Some(expected), "{}", amount);
+        assert_eq!(ledger.convert(amount, "USD", "CHF"), Some(expected), "{}", amount);
+    }
+    let path = ledger.convert_explained(1234.56, "USD", "CHF", false).unwrap();
+    assert_eq!(path.hops.len(), 3);
+    assert_eq!(path.result, 1234.63);
+
+    // the scale is the ledger's
+    ledger.set_amount_scale(4);
+    assert_eq!(ledger.convert_exact(100.0, "USD", "CHF"), Some(100.0054));
+}
+
+#[test]
+fn unknown_graph_key_is_left_out_of_every_active_view() {
+    let dir = TempDir::new("unknown_graph_key");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.5, Utc::now())).unwrap();
+
+    // has the arrow storage keys split on, but `US D` can't be a system id
+    let unknown = graph("US D -> EUR", 3.0, Utc::now());
+    assert_eq!(classify_graph_key(&unknown), GraphKeyClass::Unknown);
+    assert_eq!(GraphKeyClass::Unknown.as_str(), "unknown");
+    ledger.conversion_graphs.insert(generate_deterministic_uuid(&unknown.graph), unknown);
+
+    let active: Vec<_> = ledger.active_conversion_graphs().map(|graph| graph.graph.as_str()).collect();
+    assert_eq!(active, ["USD -> EUR"]);
+    let for_eur: Vec<_> = ledger.conversions_for_system("EUR").iter().map(|graph| graph.graph.as_str()).collect();
+    assert_eq!(for_eur, ["USD -> EUR"]);
+    assert!(ledger.conversions_for_system("US D").is_empty());
+    assert_eq!(ledger.convert(10.0, "US D", "EUR"), None);
+    assert!(ledger.detect_rate_inconsistencies(0.01).is_empty());
+
+    let stats = ledger.stats().unwrap();
+    assert_eq!((stats.active_conversion_graphs, stats.historical_conversion_graphs), (1, 0));
+}
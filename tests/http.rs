@@ -0,0 +1,47 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+use serde_json::{json, Value};
+use zentry_db::interface::http::Server;
+use zentry_db::shared::SharedLedger;
+
+use common::{ledger, system, TempDir};
+
+/// Sends a bare `GET target` and returns the status line and the body
+fn get(addr: SocketAddr, target: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, addr).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), body.to_string())
+}
+
+#[test]
+fn systems_are_served_as_json() {
+    let dir = TempDir::new("http_systems");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let server = Server::bind("127.0.0.1:0", SharedLedger::new(ledger)).unwrap();
+    let addr = server.local_addr().unwrap();
+    // serves until the test process exits
+    std::thread::spawn(move || server.run());
+
+    let (status, body) = get(addr, "/systems");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let systems: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(systems, json!([
+        {"id": "EUR", "description": "EUR system", "symbol": null, "decimal_places": null},
+        {"id": "USD", "description": "USD system", "symbol": null, "decimal_places": null},
+    ]));
+
+    let (status, _) = get(addr, "/nowhere");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+}
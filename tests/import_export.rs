@@ -0,0 +1,125 @@
+mod common;
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use zentry_db::db::MigrationCounts;
+use zentry_db::error::ZentryError;
+use zentry_db::model::AccountType;
+
+use common::{create_account, ledger, reload, system, transaction, TempDir};
+
+#[test]
+fn exported_json_parses_back() {
+    let dir = TempDir::new("export_json");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    let mut tx = transaction("opening", Utc::now());
+    tx.metadata = Some(json!({"ref": "INV-1", "lines": [1, 2]}));
+    let entries = vec![ledger.new_entry(cash, 100.0).unwrap(), ledger.new_entry(capital, -100.0).unwrap()];
+    ledger.record_transaction(tx.clone(), entries).unwrap();
+
+    let mut out = Vec::new();
+    ledger.export_json(&mut out).unwrap();
+    let export: Value = serde_json::from_slice(&out).unwrap();
+
+    assert_eq!(export["accounts"].as_array().unwrap().len(), 2);
+    assert_eq!(export["entries"].as_array().unwrap().len(), 2);
+    assert_eq!(export["systems"][0]["id"], "USD");
+    assert_eq!(export["conversion_graphs"].as_array().unwrap().len(), 0);
+
+    let exported = &export["transactions"][0];
+    assert_eq!(exported["metadata"], tx.metadata.unwrap());
+    let timestamp = DateTime::parse_from_rfc3339(exported["timestamp"].as_str().unwrap()).unwrap();
+    assert_eq!(timestamp.with_timezone(&Utc), tx.timestamp);
+}
+
+#[test]
+fn accounts_import_from_the_csv_fixture() {
+    let dir = TempDir::new("import_accounts");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/accounts.csv");
+    assert_eq!(ledger.import_accounts_csv(&fixture).unwrap(), 5);
+    assert_eq!(ledger.find_account_by_name("Sales").unwrap().account_type, AccountType::Revenue);
+    assert_eq!(ledger.find_account_by_name("Accounts Payable").unwrap().system_id, "USD");
+
+    let csv = dir.join("unknown_system.csv");
+    std::fs::write(&csv, "name,account_type,system_id\nWallet,Asset,USD\nKonto,Asset,EUR\n").unwrap();
+    match ledger.import_accounts_csv(&csv) {
+        Err(ZentryError::NotFound(message)) => assert!(message.starts_with("line 3:"), "unexpected message: {}", message),
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+    assert!(ledger.find_account_by_name("Wallet").is_none());
+}
+
+#[test]
+fn systems_import_skips_duplicates_and_rejects_malformed_lines() {
+    let dir = TempDir::new("import_systems");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/systems.csv");
+    let (created, skipped) = ledger.import_systems(&fixture).unwrap();
+    assert_eq!(created, 3);
+    assert_eq!(skipped, ["USD", "EUR"]);
+    assert_eq!(ledger.find_system("JPY").unwrap().description, "Japanese Yen");
+    assert_eq!(ledger.find_system("IDR").unwrap().description, "Indonesian Rupiah, also written Rp");
+    assert_eq!(ledger.find_system("EUR").unwrap().description, "Euro");
+
+    let malformed = dir.join("malformed.csv");
+    std::fs::write(&malformed, "GBP,Pound sterling\nCHF Swiss franc\n").unwrap();
+    match ledger.import_systems(&malformed) {
+        Err(ZentryError::InvalidData(message)) => assert!(message.starts_with("line 2:"), "unexpected message: {}", message),
+        other => panic!("expected InvalidData, got {:?}", other),
+    }
+    assert!(ledger.find_system("GBP").is_none());
+}
+
+#[test]
+fn jsonl_fixture_migrates_into_the_binary_store() {
+    let dir = TempDir::new("migrate_jsonl");
+    let mut ledger = ledger(&dir);
+
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/jsonl");
+    for file in std::fs::read_dir(&fixture).unwrap() {
+        let file = file.unwrap();
+        std::fs::copy(file.path(), dir.path().join(file.file_name())).unwrap();
+    }
+
+    let report = ledger.migrate_from_jsonl().unwrap();
+    let counts = |counts: MigrationCounts| (counts.migrated, counts.duplicates);
+    assert_eq!(counts(report.systems), (2, 1));
+    assert_eq!(counts(report.accounts), (3, 0));
+    assert_eq!(counts(report.conversion_graphs), (2, 0));
+    assert_eq!(counts(report.transactions), (1, 0));
+    assert_eq!(report.skipped.len(), 3, "{:?}", report.skipped);
+    assert!(report.skipped[0].starts_with("accounts.jsonl:4: system not found: GBP"), "{:?}", report.skipped);
+    assert!(report.skipped[1].starts_with("accounts.jsonl:5: "), "{:?}", report.skipped);
+    assert!(report.skipped[2].starts_with("transactions.jsonl:2: "), "{:?}", report.skipped);
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert!(ledger.verify_indexes().is_empty());
+    assert_eq!(ledger.find_system("USD").unwrap().description, "US Dollar");
+    let checking = ledger.find_account_by_name("Checking").unwrap();
+    assert_eq!(checking.parent_id, Some(ledger.find_account_by_name("Cash").unwrap().id));
+    assert!(ledger.find_account_by_name("Orphan").is_none());
+    assert_eq!(ledger.account_system_index.get("USD").count(), 3);
+    assert_eq!(ledger.account_balance(checking.id), 25.0);
+    assert_eq!(ledger.transactions.len(), 1);
+    assert_eq!(ledger.entries.len(), 2);
+    assert_eq!(ledger.rate_at("USD", "EUR", "2024-01-15T00:00:00Z".parse().unwrap()), Some(0.91));
+    assert_eq!(ledger.rate_at("USD", "EUR", Utc::now()), Some(0.92));
+
+    // everything is in the store now, a second run only finds duplicates
+    let mut ledger = ledger;
+    let again = ledger.migrate_from_jsonl().unwrap();
+    assert_eq!((again.systems.migrated, again.accounts.migrated, again.transactions.migrated), (0, 0, 0));
+    assert_eq!(again.accounts.duplicates, 3);
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use zentry_db::index::{BTreeIndex, SecondaryIndex, TimestampIndex};
+use zentry_db::model::{Account, AccountType};
+use zentry_db::util::uuid::generate_deterministic_uuid;
+
+use common::{create_account, ledger, post, reload, system, TempDir};
+
+#[test]
+fn removed_btree_entry_stays_removed_after_persist() {
+    let dir = TempDir::new("btree_remove");
+    let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+
+    let mut index = BTreeIndex::new();
+    for (offset, id) in ids.iter().enumerate() {
+        index.insert(*id, offset as u64 * 100);
+    }
+
+    assert_eq!(index.remove(&ids[1]), Some(100));
+    assert_eq!(index.remove(&ids[1]), None);
+    assert_eq!(index.get(&ids[1]), None);
+    assert_eq!(index.len(), 2);
+
+    let path = dir.join("accounts.idx");
+    index.persist(&path).unwrap();
+    let loaded = BTreeIndex::load(&path).unwrap();
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded.get(&ids[0]), Some(0));
+    assert_eq!(loaded.get(&ids[1]), None);
+    assert_eq!(loaded.get(&ids[2]), Some(200));
+}
+
+#[test]
+fn interrupted_persist_leaves_the_previous_index() {
+    let dir = TempDir::new("interrupted_persist");
+    let id = Uuid::new_v4();
+
+    let mut timestamps = TimestampIndex::new();
+    timestamps.insert(1_000, id, 5);
+    let timestamp_path = dir.join("transactions_by_timestamp.idx");
+    timestamps.persist(&timestamp_path).unwrap();
+
+    let mut systems = SecondaryIndex::new();
+    systems.insert("USD", id);
+    let secondary_path = dir.join("accounts_by_system.idx");
+    systems.persist(&secondary_path).unwrap();
+
+    // a persist that died before its rename only got as far as the temporary file
+    for path in [&timestamp_path, &secondary_path] {
+        assert!(!path.with_extension("idx.tmp").exists());
+        std::fs::write(path.with_extension("idx.tmp"), [0xab; 7]).unwrap();
+    }
+
+    let loaded = TimestampIndex::load(&timestamp_path).unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded.get(1_000, &id), Some(5));
+    assert_eq!(SecondaryIndex::load(&secondary_path).unwrap(), systems);
+}
+
+#[test]
+fn truncated_indexes_are_rejected_and_rebuilt() {
+    let dir = TempDir::new("truncated_indexes");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    let tx = post(&mut ledger, Utc::now(), &[(cash, 10.0), (capital, -10.0)]);
+    ledger.flush().unwrap();
+    ledger.persist_indexes().unwrap();
+    drop(ledger);
+
+    let timestamp_path = dir.join("transactions_by_timestamp.idx");
+    let secondary_path = dir.join("accounts_by_system.idx");
+    for path in [&timestamp_path, &secondary_path] {
+        let len = std::fs::metadata(path).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(path).unwrap().set_len(len - 3).unwrap();
+    }
+    assert_eq!(TimestampIndex::load(&timestamp_path).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(SecondaryIndex::load(&secondary_path).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let ledger = reload(&dir);
+    let between: Vec<_> = ledger.transactions_between(Utc::now() - Duration::hours(1), Utc::now()).iter().map(|tx| tx.id).collect();
+    assert_eq!(between, [tx]);
+    assert_eq!(ledger.accounts_in_system("USD").len(), 2);
+    assert_eq!(TimestampIndex::load(&timestamp_path).unwrap().len(), 1);
+    assert_eq!(SecondaryIndex::load(&secondary_path).unwrap(), ledger.account_system_index);
+}
+
+#[test]
+fn timestamp_index_past_the_end_of_the_file_is_rebuilt() {
+    let dir = TempDir::new("timestamp_out_of_range");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    let timestamp = Utc::now() - Duration::minutes(5);
+    post(&mut ledger, timestamp, &[(cash, 10.0), (capital, -10.0)]);
+    ledger.flush().unwrap();
+    ledger.persist_indexes().unwrap();
+
+    // every entry moved far past the end of entries.bin
+    let path = dir.join("entries_by_timestamp.idx");
+    let mut stale = TimestampIndex::new();
+    for (timestamp, id, _) in ledger.entry_timestamp_index.range(i64::MIN, i64::MAX) {
+        stale.insert(timestamp, id, 1 << 40);
+    }
+    drop(ledger);
+    stale.persist(&path).unwrap();
+
+    let ledger = reload(&dir);
+    let entries = ledger.entries_between(timestamp, Utc::now()).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(TimestampIndex::load(&path).unwrap().range(i64::MIN, i64::MAX).iter().all(|(_, _, offset)| *offset < 1 << 40));
+}
+
+#[test]
+fn rebuild_indexes_restores_a_deleted_index() {
+    let dir = TempDir::new("rebuild_indexes");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 10.0), (capital, -10.0)]);
+    ledger.persist_indexes().unwrap();
+
+    let path = dir.join("accounts.idx");
+    std::fs::remove_file(&path).unwrap();
+    ledger.account_index = BTreeIndex::new();
+    ledger.entry_index = BTreeIndex::new();
+
+    ledger.rebuild_indexes().unwrap();
+    assert!(ledger.verify_indexes().is_empty());
+    for id in [cash, capital] {
+        let offset = ledger.account_index.get(&generate_deterministic_uuid(&id)).unwrap();
+        assert_eq!(ledger.storage.read_single::<Account>(offset).unwrap().id, id);
+    }
+    for entry in ledger.entries.clone() {
+        assert_eq!(ledger.find_entry(entry.id).unwrap().amount, entry.amount);
+    }
+
+    let persisted = BTreeIndex::load(&path).unwrap();
+    assert_eq!(persisted.len(), 2);
+    assert!(persisted.iter().all(|(id, offset)| ledger.account_index.get(&id) == Some(offset)));
+}
+
+#[test]
+fn verify_indexes_reports_a_wrong_offset() {
+    let dir = TempDir::new("verify_indexes");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let bank = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 5.0), (bank, -5.0)]);
+    ledger.flush().unwrap();
+    assert_eq!(ledger.verify_indexes(), Vec::<String>::new());
+
+    let cash_key = generate_deterministic_uuid(&cash);
+    let bank_key = generate_deterministic_uuid(&bank);
+    let cash_offset = ledger.account_index.get(&cash_key).unwrap();
+    let bank_offset = ledger.account_index.get(&bank_key).unwrap();
+    ledger.account_index.insert(cash_key, bank_offset);
+
+    assert_eq!(ledger.verify_indexes(), [
+        format!("accounts.bin: {} is indexed at offset {}, which holds {}", cash_key, bank_offset, bank_key),
+        format!("accounts.bin: live record {} at offset {} is indexed at offset {}", cash_key, cash_offset, bank_offset),
+    ]);
+}
+
+#[test]
+fn stale_secondary_index_is_rebuilt() {
+    let dir = TempDir::new("stale_secondary");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let konto = create_account(&mut ledger, "Konto", AccountType::Asset, "EUR");
+    ledger.persist_indexes().unwrap();
+    let persisted = std::mem::take(&mut ledger.account_system_index);
+    drop(ledger);
+
+    let path = dir.join("accounts_by_system.idx");
+    assert_eq!(SecondaryIndex::load(&path).unwrap(), persisted);
+    assert_eq!(reload(&dir).account_system_index, persisted);
+
+    // every id is still there, one under the wrong system, plus one no account has
+    let mut stale = SecondaryIndex::new();
+    stale.insert("USD", generate_deterministic_uuid(&cash));
+    stale.insert("USD", generate_deterministic_uuid(&konto));
+    stale.insert("EUR", Uuid::new_v4());
+    stale.persist(&path).unwrap();
+
+    let ledger = reload(&dir);
+    assert_eq!(ledger.account_system_index, persisted);
+    assert_eq!(ledger.accounts_in_system("EUR")[0].id, konto);
+    assert_eq!(SecondaryIndex::load(&path).unwrap(), persisted);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use chrono::Utc;
+use uuid::Uuid;
+use zentry_db::db::Ledger;
+use zentry_db::model::{AccountType, Entry};
+use zentry_db::util::uuid::generate_deterministic_uuid;
+
+use common::{account, create_account, ledger, post, system, TempDir};
+
+fn problems(ledger: &Ledger) -> Vec<String> {
+    ledger.verify_integrity().err().unwrap_or_default()
+}
+
+#[test]
+fn balanced_ledger_has_no_problems() {
+    let dir = TempDir::new("integrity_ok");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 100.0), (capital, -100.0)]);
+
+    assert!(ledger.verify_integrity().is_ok());
+}
+
+#[test]
+fn unbalanced_entries_are_reported() {
+    let dir = TempDir::new("integrity_unbalanced");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let stray = ledger.new_entry(cash, 5.0).unwrap();
+    ledger.entries.push(stray);
+
+    let problems = problems(&ledger);
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].starts_with("ledger is unbalanced"), "unexpected problem: {}", problems[0]);
+}
+
+#[test]
+fn entry_on_a_missing_account_is_reported() {
+    let dir = TempDir::new("integrity_missing_account");
+    let mut ledger = ledger(&dir);
+    let missing = Uuid::new_v4();
+    ledger.entries.push(Entry { id: Uuid::new_v4(), transaction_id: Uuid::new_v4(), account_id: missing, amount: 0.0 });
+
+    let problems = problems(&ledger);
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].contains(&format!("references missing account {}", missing)), "unexpected problem: {}", problems[0]);
+}
+
+#[test]
+fn account_in_a_missing_system_is_reported() {
+    let dir = TempDir::new("integrity_missing_system");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    ledger.systems.clear();
+
+    let problems = problems(&ledger);
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].contains("references missing system USD"), "unexpected problem: {}", problems[0]);
+}
+
+#[test]
+fn account_under_a_missing_parent_is_reported() {
+    let dir = TempDir::new("integrity_missing_parent");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let parent = create_account(&mut ledger, "Assets", AccountType::Asset, "USD");
+    let mut child = account("Cash", AccountType::Asset, "USD");
+    child.parent_id = Some(parent);
+    ledger.create_account(child).unwrap();
+    ledger.accounts.remove(&generate_deterministic_uuid(&parent));
+
+    let problems = problems(&ledger);
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].contains(&format!("references missing parent account {}", parent)), "unexpected problem: {}", problems[0]);
+}
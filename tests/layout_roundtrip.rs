@@ -0,0 +1,327 @@
+//! Round-trips generated records of every model through `BinaryStorage` and checks that what is
+//! read back is what was written.
+//!
+//! Each case writes a few records of every type into a fresh data dir, reads each one back from
+//! its offset with the record cache off, tombstones every third one, and checks that a full scan
+//! returns exactly the survivors. Values grow with the case number, so the first failure is
+//! usually a small one; it is then regenerated from the same seed at every smaller size and the
+//! smallest one that still fails is reported. The seed is fixed, so a failure replays as is.
+//!
+//! Values are compared through their JSON form, since the models' `PartialEq` only looks at ids.
+//! Left out of the generators because they don't round-trip by design:
+//! - `NaN` amounts and rates, which never compare equal
+//! - floats inside transaction metadata, which `serde_json` may read back a ulp off
+//! - custom account types, which have to be registered in the reading process first
+//! - timestamps outside what `i64` nanoseconds cover, which the writers reject
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+use zentry_db::{
+    account_layout, conversion_graph_layout, entry_layout, system_layout, transaction_layout,
+    Account, AccountType, BinaryStorage, ConversionGraph, Entry, ExactRate, FromBinary, System,
+    ToBinary, TombstoneWriter, Transaction,
+};
+
+use common::TempDir;
+
+const CASES: usize = 256;
+const SEED: u64 = 0x5EED_2E47;
+/// Largest size a case is generated at, past the 255 bytes a `U8` prefix holds so the `U16`
+/// fields get longer values than the `U8` ones can
+const MAX_SIZE: usize = 320;
+/// Records of each type written per case
+const RECORDS_PER_CASE: usize = 6;
+
+/// Longest value a `U8` length prefix can describe
+const U8_FIELD_LIMIT: usize = u8::MAX as usize;
+
+/// A xorshift generator whose values are bounded by `size`, so the same seed at a smaller size
+/// draws the same choices with shorter strings and smaller collections
+struct Gen {
+    state: u64,
+    size: usize,
+}
+
+impl Gen {
+    fn new(seed: u64, size: usize) -> Self {
+        // xorshift never leaves 0
+        Gen { state: seed.max(1), size }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Uniform in `0..=max`
+    fn upto(&mut self, max: usize) -> usize {
+        (self.next_u64() % (max as u64 + 1)) as usize
+    }
+
+    fn chance(&mut self, one_in: u64) -> bool {
+        self.next_u64().is_multiple_of(one_in)
+    }
+
+    fn uuid(&mut self) -> Uuid {
+        Uuid::from_u64_pair(self.next_u64(), self.next_u64())
+    }
+
+    /// At most `max_bytes` bytes of UTF-8, mixing one to four byte characters so byte and
+    /// character lengths disagree
+    fn string(&mut self, max_bytes: usize) -> String {
+        const POOL: [char; 12] = ['a', 'Z', '0', '_', ' ', '-', '"', 'é', '¥', '€', '日', '🙂'];
+
+        let budget = self.upto(self.size.min(max_bytes));
+        let mut value = String::new();
+        loop {
+            let c = POOL[self.upto(POOL.len() - 1)];
+            if value.len() + c.len_utf8() > budget {
+                return value;
+            }
+            value.push(c);
+        }
+    }
+
+    /// A system id as `System::is_valid_id` allows, so it can sit on either side of a graph key
+    fn system_id(&mut self, max_bytes: usize) -> String {
+        const POOL: [char; 8] = ['A', 'z', '7', '_', '-', '.', 'é', '日'];
+
+        let budget = self.upto(self.size.min(max_bytes)).max(1);
+        let mut value = String::new();
+        loop {
+            let c = POOL[self.upto(POOL.len() - 1)];
+            if value.len() + c.len_utf8() > budget {
+                return if value.is_empty() { "X".to_string() } else { value };
+            }
+            value.push(c);
+        }
+    }
+
+    /// Any finite `f64`, either from raw bits or a plain money-like amount
+    fn finite_f64(&mut self) -> f64 {
+        if self.chance(2) {
+            return (self.next_u64() as i64 % 100_000_000) as f64 / 100.0;
+        }
+        loop {
+            let value = f64::from_bits(self.next_u64());
+            if value.is_finite() {
+                return value;
+            }
+        }
+    }
+
+    /// Anywhere in the range `i64` nanoseconds cover, about 1677 to 2262
+    fn timestamp(&mut self) -> DateTime<Utc> {
+        Utc.timestamp_nanos(self.next_u64() as i64)
+    }
+
+    /// From 1970 on, so the RFC 3339 form has the four digit year archived graph keys expect
+    fn timestamp_since_epoch(&mut self) -> DateTime<Utc> {
+        Utc.timestamp_nanos((self.next_u64() >> 1) as i64)
+    }
+
+    fn metadata(&mut self, depth: usize) -> serde_json::Value {
+        match self.upto(if depth == 0 { 3 } else { 5 }) {
+            0 => serde_json::Value::Null,
+            1 => serde_json::Value::Bool(self.chance(2)),
+            2 => serde_json::Value::from(self.next_u64() as i64),
+            3 => serde_json::Value::String(self.string(64)),
+            4 => (0..self.upto(self.size.min(4))).map(|_| self.metadata(depth - 1)).collect(),
+            _ => (0..self.upto(self.size.min(4)))
+                .map(|_| (self.string(16), self.metadata(depth - 1)))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+        }
+    }
+
+    fn account(&mut self) -> Account {
+        const TYPES: [AccountType; 5] = [AccountType::Asset, AccountType::Liability, AccountType::Equity, AccountType::Revenue, AccountType::Expense];
+
+        Account {
+            id: self.uuid(),
+            name: self.string(u16::MAX as usize),
+            account_type: TYPES[self.upto(TYPES.len() - 1)].clone(),
+            created_at: self.timestamp(),
+            system_id: self.string(U8_FIELD_LIMIT),
+            parent_id: self.chance(2).then(|| self.uuid()),
+        }
+    }
+
+    fn transaction(&mut self) -> Transaction {
+        Transaction {
+            id: self.uuid(),
+            description: self.string(u16::MAX as usize),
+            timestamp: self.timestamp(),
+            metadata: self.chance(3).then(|| self.metadata(2)),
+        }
+    }
+
+    fn entry(&mut self) -> Entry {
+        Entry {
+            id: self.uuid(),
+            transaction_id: self.uuid(),
+            account_id: self.uuid(),
+            amount: self.finite_f64(),
+        }
+    }
+
+    fn system(&mut self) -> System {
+        System {
+            id: self.string(U8_FIELD_LIMIT),
+            description: self.string(U8_FIELD_LIMIT),
+            symbol: self.chance(2).then(|| self.string(U8_FIELD_LIMIT)),
+            decimal_places: self.chance(2).then(|| self.next_u64() as u8),
+        }
+    }
+
+    /// Active graphs are stored as `C[A -> B]` and archived ones as `H[start[A -> B]end#n]`,
+    /// both behind a `U8` prefix, so the system ids are kept short enough for the wrapper to fit
+    fn conversion_graph(&mut self) -> ConversionGraph {
+        let from = self.system_id(40);
+        let to = self.system_id(40);
+        let relation = format!("{} -> {}", from, to);
+
+        let graph = if self.chance(3) {
+            let since = self.timestamp_since_epoch();
+            let until = self.timestamp_since_epoch();
+            let suffix = if self.chance(2) { format!("#{}", self.upto(99)) } else { String::new() };
+            format!("{}[{}]{}{}", since.to_rfc3339(), relation, until.to_rfc3339(), suffix)
+        } else {
+            relation
+        };
+
+        ConversionGraph {
+            graph,
+            rate: self.finite_f64(),
+            rate_since: self.timestamp(),
+            exact_rate: self.chance(2).then(|| ExactRate::new(self.next_u64().max(1), self.next_u64().max(1))).flatten(),
+        }
+    }
+}
+
+/// A storage over freshly installed files in `dir`, wired up the way `Ledger` does it but
+/// without a record cache, so every read decodes from disk
+fn open_storage(dir: &Path) -> Result<BinaryStorage, String> {
+    let _ = std::fs::remove_dir_all(dir);
+    zentry_db::install_at(dir).map_err(|e| e.to_string())?;
+
+    let mut readers = HashMap::new();
+    let mut writers = HashMap::new();
+    let mut layouts = HashMap::new();
+
+    for layout in [account_layout(), transaction_layout(), entry_layout(), system_layout(), conversion_graph_layout()] {
+        let type_key = match layout.name {
+            "Account" => "accounts",
+            "Transaction" => "transactions",
+            "Entry" => "entries",
+            "System" => "systems",
+            _ => "conversion_graphs",
+        };
+        let path = dir.join(format!("{}.bin", type_key));
+
+        readers.insert(type_key.to_string(), BufReader::new(File::open(&path).map_err(|e| e.to_string())?));
+        writers.insert(
+            type_key.to_string(),
+            BufWriter::new(OpenOptions::new().write(true).truncate(false).open(&path).map_err(|e| e.to_string())?),
+        );
+        layouts.insert(type_key.to_string(), layout);
+    }
+
+    BinaryStorage::new(readers, writers, layouts, 0).map_err(|e| e.to_string())
+}
+
+fn json<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).expect("models serialize to JSON")
+}
+
+/// Writes `items`, reads each back from its offset, tombstones every third and checks a full
+/// scan returns the rest. `Err` describes the first mismatch.
+fn round_trip<T>(storage: &BinaryStorage, items: Vec<T>) -> Result<(), String>
+where
+    T: ToBinary + FromBinary + PartialEq + Clone + Send + Serialize + std::fmt::Debug + 'static,
+{
+    let mut written = Vec::with_capacity(items.len());
+    for item in items {
+        let (offset, item) = storage.write(item).map_err(|e| format!("write failed: {}", e))?;
+        written.push((offset, item));
+    }
+    storage.flush().map_err(|e| e.to_string())?;
+
+    for (offset, item) in written.iter() {
+        let read = storage.read_single::<T>(*offset).map_err(|e| format!("read at {} failed: {} for {:?}", offset, e, item))?;
+        if json(&read) != json(item) {
+            return Err(format!("read at {} differs\n  wrote {:?}\n  read  {:?}", offset, item, read));
+        }
+    }
+
+    let mut survivors = Vec::new();
+    for (index, (offset, item)) in written.into_iter().enumerate() {
+        if index % 3 == 0 {
+            storage.tombstone(item.clone(), offset).map_err(|e| format!("tombstone at {} failed: {}", offset, e))?;
+            if storage.read_single::<T>(offset).is_ok() {
+                return Err(format!("tombstoned record at {} still reads back", offset));
+            }
+        } else {
+            survivors.push((offset, item));
+        }
+    }
+    storage.flush().map_err(|e| e.to_string())?;
+
+    let scanned = storage.read_with_offsets::<T>().map_err(|e| format!("scan failed: {}", e))?;
+    let scanned: Vec<(u64, serde_json::Value)> = scanned.iter().map(|(offset, item)| (*offset, json(item))).collect();
+    let expected: Vec<(u64, serde_json::Value)> = survivors.iter().map(|(offset, item)| (*offset, json(item))).collect();
+    if scanned != expected {
+        return Err(format!("scan after tombstoning differs\n  expected {:?}\n  scanned  {:?}", expected, scanned));
+    }
+
+    Ok(())
+}
+
+/// One case: `RECORDS_PER_CASE` records of every type generated from `seed` at `size`
+fn run_case(dir: &Path, seed: u64, size: usize) -> Result<(), String> {
+    let storage = open_storage(dir)?;
+    let mut gen = Gen::new(seed, size);
+
+    let accounts = (0..RECORDS_PER_CASE).map(|_| gen.account()).collect();
+    let transactions = (0..RECORDS_PER_CASE).map(|_| gen.transaction()).collect();
+    let entries = (0..RECORDS_PER_CASE).map(|_| gen.entry()).collect();
+    let systems = (0..RECORDS_PER_CASE).map(|_| gen.system()).collect();
+    let conversion_graphs = (0..RECORDS_PER_CASE).map(|_| gen.conversion_graph()).collect();
+
+    round_trip::<Account>(&storage, accounts).map_err(|e| format!("Account: {}", e))?;
+    round_trip::<Transaction>(&storage, transactions).map_err(|e| format!("Transaction: {}", e))?;
+    round_trip::<Entry>(&storage, entries).map_err(|e| format!("Entry: {}", e))?;
+    round_trip::<System>(&storage, systems).map_err(|e| format!("System: {}", e))?;
+    round_trip::<ConversionGraph>(&storage, conversion_graphs).map_err(|e| format!("ConversionGraph: {}", e))
+}
+
+#[test]
+fn generated_records_round_trip() {
+    let dir = TempDir::new("layout_roundtrip");
+    let dir = dir.join("data");
+    let mut seeds = Gen::new(SEED, 0);
+
+    for case in 0..CASES {
+        let case_seed = seeds.next_u64();
+        let size = (case * MAX_SIZE / CASES).max(1);
+
+        if let Err(failure) = run_case(&dir, case_seed, size) {
+            // the same seed at a smaller size makes the same choices with smaller values
+            let (size, failure) = (0..size)
+                .find_map(|smaller| run_case(&dir, case_seed, smaller).err().map(|failure| (smaller, failure)))
+                .unwrap_or((size, failure));
+
+            panic!("case {} failed (case seed {}, shrunk to size {})\n{}", case, case_seed, size, failure);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use zentry_db::db::Ledger;
+use zentry_db::error::ZentryError;
+use zentry_db::model::{Account, AccountType};
+use zentry_db::util::uuid::generate_deterministic_uuid;
+
+use common::{account, create_account, graph, ledger, post, reload, system, transaction, TempDir};
+
+#[test]
+fn listings_come_back_in_the_same_order() {
+    let dir = TempDir::new("ordering");
+    let mut ledger = ledger(&dir);
+    for id in ["JPY", "USD", "EUR", "GBP", "IDR"] {
+        ledger.create_system(system(id)).unwrap();
+    }
+    for name in ["Rent", "Cash", "Sales", "Bank", "Loan"] {
+        create_account(&mut ledger, name, AccountType::Asset, "USD");
+    }
+    for relation in ["USD -> JPY", "EUR -> USD", "GBP -> IDR"] {
+        ledger.create_conversion_graph(graph(relation, 2.0, Utc::now())).unwrap();
+    }
+
+    let systems: Vec<_> = ledger.systems().map(|system| system.id.clone()).collect();
+    assert_eq!(systems, ["EUR", "GBP", "IDR", "JPY", "USD"]);
+    assert_eq!(ledger.systems().map(|system| system.id.clone()).collect::<Vec<_>>(), systems);
+
+    let accounts: Vec<_> = ledger.accounts().map(|account| account.name.clone()).collect();
+    assert_eq!(accounts, ["Bank", "Cash", "Loan", "Rent", "Sales"]);
+
+    let graphs: Vec<_> = ledger.conversion_graphs().map(|graph| graph.graph.clone()).collect();
+    assert_eq!(graphs, ["EUR -> USD", "GBP -> IDR", "USD -> JPY"]);
+
+    drop(ledger);
+    let ledger = reload(&dir);
+    assert_eq!(ledger.systems().map(|system| system.id.clone()).collect::<Vec<_>>(), systems);
+    assert_eq!(ledger.accounts().map(|account| account.name.clone()).collect::<Vec<_>>(), accounts);
+}
+
+#[test]
+fn duplicate_system_is_rejected() {
+    let dir = TempDir::new("duplicate_system");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let mut again = system("USD");
+    again.description = "US Dollar again".to_string();
+    assert!(matches!(ledger.create_system(again), Err(ZentryError::AlreadyExists(_))));
+
+    drop(ledger);
+    let ledger = reload(&dir);
+    assert_eq!(ledger.systems().count(), 1);
+    assert_eq!(ledger.find_system("USD").unwrap().description, "USD system");
+}
+
+#[test]
+fn missing_data_file_is_reported() {
+    let dir = TempDir::new("missing_data_file");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    drop(ledger);
+
+    std::fs::remove_file(dir.join("entries.bin")).unwrap();
+    match Ledger::load_from_dir(dir.path()) {
+        Err(ZentryError::NotFound(message)) => {
+            assert!(message.contains("entries.bin") && message.ends_with("is missing, run install to create it"), "{}", message);
+        }
+        other => panic!("expected NotFound, got {:?}", other.map(|_| ())),
+    }
+    assert!(!dir.join("entries.bin").exists());
+}
+
+#[test]
+fn stats_count_records_and_tombstones() {
+    let dir = TempDir::new("stats");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, Utc::now() - Duration::hours(1))).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, Utc::now())).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    let unused = create_account(&mut ledger, "Unused", AccountType::Asset, "USD");
+    ledger.delete_account(unused).unwrap();
+    post(&mut ledger, Utc::now(), &[(cash, 10.0), (sales, -10.0)]);
+    post(&mut ledger, Utc::now(), &[(cash, 5.0), (sales, -5.0)]);
+    ledger.flush().unwrap();
+
+    let stats = ledger.stats().unwrap();
+    assert_eq!((stats.accounts, stats.transactions, stats.entries, stats.systems), (2, 2, 4, 2));
+    assert_eq!((stats.active_conversion_graphs, stats.historical_conversion_graphs), (1, 1));
+
+    let files: Vec<_> = stats.files.iter().map(|file| (file.file, file.index_len)).collect();
+    assert_eq!(files, [
+        ("accounts.bin", 2),
+        ("transactions.bin", 2),
+        ("entries.bin", 4),
+        ("systems.bin", 2),
+        ("conversion_graphs.bin", 2),
+    ]);
+
+    let accounts = &stats.files[0];
+    assert_eq!(accounts.tombstones, 1);
+    assert_eq!(accounts.bin_bytes, std::fs::metadata(dir.join("accounts.bin")).unwrap().len());
+    assert!(stats.files[1..4].iter().all(|file| file.tombstones == 0), "{:?}", stats.files);
+}
+
+#[test]
+fn creating_records_writes_no_jsonl() {
+    let dir = TempDir::new("no_jsonl");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+    ledger.create_conversion_graph(graph("USD <-> EUR", 0.5, Utc::now())).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 10.0), (capital, -10.0)]);
+    ledger.persist_indexes().unwrap();
+    assert!(ledger.verify_indexes().is_empty());
+    drop(ledger);
+
+    let jsonl: Vec<_> = std::fs::read_dir(dir.path()).unwrap()
+        .map(|file| file.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "jsonl"))
+        .collect();
+    assert!(jsonl.is_empty(), "{:?}", jsonl);
+
+    let ledger = reload(&dir);
+    assert_eq!((ledger.systems().count(), ledger.accounts().count(), ledger.transactions.len()), (2, 2, 1));
+    assert_eq!(ledger.active_conversion_graphs().count(), 2);
+    assert_eq!(ledger.account_balance(cash), 10.0);
+}
+
+/// Every file in `dir` with its contents
+fn snapshot(dir: &TempDir) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+    let mut files: Vec<_> = std::fs::read_dir(dir.path()).unwrap()
+        .map(|file| file.unwrap().path())
+        .map(|path| (path.clone(), std::fs::read(&path).unwrap()))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn read_only_ledger_reads_but_never_writes() {
+    let dir = TempDir::new("read_only");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    let unused = create_account(&mut ledger, "Unused", AccountType::Asset, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 10.0), (capital, -10.0)]);
+    drop(ledger);
+
+    // a stale index is rebuilt in memory only
+    std::fs::remove_file(dir.join("accounts.idx")).unwrap();
+    let before = snapshot(&dir);
+
+    let mut ledger = Ledger::load_read_only(dir.path()).unwrap();
+    assert!(ledger.storage.is_read_only());
+    assert_eq!(ledger.find_account(cash).unwrap().name, "Cash");
+    assert_eq!(ledger.account_balance(cash), 10.0);
+    assert_eq!(ledger.iter_accounts().unwrap().count(), 3);
+    let offset = ledger.account_index.get(&generate_deterministic_uuid(&cash)).unwrap();
+    assert_eq!(ledger.storage.read_single::<Account>(offset).unwrap().id, cash);
+
+    assert!(matches!(ledger.create_system(system("EUR")), Err(ZentryError::ReadOnly)));
+    let account = account("Bank", AccountType::Asset, "USD");
+    assert!(matches!(ledger.create_account(account), Err(ZentryError::ReadOnly)));
+    assert!(matches!(ledger.delete_account(unused), Err(ZentryError::ReadOnly)));
+    let entries = vec![ledger.new_entry(cash, 1.0).unwrap(), ledger.new_entry(capital, -1.0).unwrap()];
+    let tx = transaction("more", Utc::now());
+    assert!(matches!(ledger.record_transaction(tx, entries), Err(ZentryError::ReadOnly)));
+    assert!(matches!(ledger.persist_indexes(), Err(ZentryError::ReadOnly)));
+
+    assert_eq!((ledger.systems().count(), ledger.accounts().count(), ledger.transactions.len()), (1, 3, 1));
+    assert_eq!(ledger.account_balance(cash), 10.0);
+    drop(ledger);
+    assert_eq!(snapshot(&dir), before);
+}
+
+#[test]
+fn currency_metadata_survives_reload_and_formats_amounts() {
+    let dir = TempDir::new("currency_metadata");
+    let mut ledger = ledger(&dir);
+    let mut jpy = system("JPY");
+    jpy.symbol = Some("¥".to_string());
+    jpy.decimal_places = Some(0);
+    let mut usd = system("USD");
+    usd.symbol = Some("$".to_string());
+    usd.decimal_places = Some(2);
+    ledger.create_system(jpy).unwrap();
+    ledger.create_system(usd).unwrap();
+    ledger.create_system(system("PTS")).unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let jpy = ledger.find_system("JPY").unwrap();
+    assert_eq!((jpy.symbol.as_deref(), jpy.decimal_places), (Some("¥"), Some(0)));
+    assert_eq!(jpy.format_amount(1234.56), "¥1235");
+    assert_eq!(jpy.format_amount(-1234.56), "-¥1235");
+    assert_eq!(jpy.format_amount(-0.4), "¥0");
+
+    let usd = ledger.find_system("USD").unwrap();
+    assert_eq!(usd.format_amount(12.5), "$12.50");
+    assert_eq!(usd.format_amount(-0.126), "-$0.13");
+
+    let points = ledger.find_system("PTS").unwrap();
+    assert_eq!((points.symbol.as_deref(), points.decimal_places), (None, None));
+    assert_eq!(points.format_amount(12.5), "12.5");
+}
+
+#[test]
+fn trial_balance_buckets_entries_by_account_type() {
+    let dir = TempDir::new("trial_balance");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let bank = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    let rent = create_account(&mut ledger, "Rent", AccountType::Expense, "USD");
+    create_account(&mut ledger, "Loan", AccountType::Liability, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 100.0), (capital, -100.0)]);
+    post(&mut ledger, Utc::now(), &[(bank, 40.0), (sales, -40.0)]);
+    post(&mut ledger, Utc::now(), &[(rent, 25.0), (cash, -25.0)]);
+
+    let mut buckets: Vec<_> = ledger.trial_balance().into_iter().collect();
+    buckets.sort_by_key(|(account_type, _)| account_type.label());
+    assert_eq!(buckets, [
+        (AccountType::Asset, 115.0),
+        (AccountType::Equity, -100.0),
+        (AccountType::Expense, 25.0),
+        (AccountType::Revenue, -40.0),
+    ]);
+}
+
+#[test]
+fn is_balanced_until_an_unbalanced_entry_is_written() {
+    let dir = TempDir::new("is_balanced");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    assert!(ledger.is_balanced());
+
+    post(&mut ledger, Utc::now(), &[(cash, 100.0), (capital, -100.0)]);
+    post(&mut ledger, Utc::now(), &[(cash, 0.1), (cash, 0.2), (capital, -0.3)]);
+    assert!(ledger.is_balanced());
+
+    // bypasses `record_transaction`, the way a stray write would
+    let stray = ledger.new_entry(cash, 5.0).unwrap();
+    ledger.entries.push(stray);
+    assert!(!ledger.is_balanced());
+}
+
+#[test]
+fn reports_are_split_by_system() {
+    let dir = TempDir::new("reports_by_system");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("JPY")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    let yen = create_account(&mut ledger, "Yen cash", AccountType::Asset, "JPY");
+    let fees = create_account(&mut ledger, "Fees", AccountType::Expense, "JPY");
+    let start = Utc::now() - Duration::hours(1);
+    post(&mut ledger, start, &[(cash, 10.0), (sales, -10.0)]);
+    post(&mut ledger, start, &[(fees, 1500.0), (yen, -1500.0)]);
+
+    let trial_balances = ledger.trial_balance_by_system();
+    assert_eq!(trial_balances.keys().collect::<Vec<_>>(), ["JPY", "USD"]);
+    assert_eq!(trial_balances["USD"][&AccountType::Revenue], -10.0);
+    assert_eq!(trial_balances["JPY"][&AccountType::Asset], -1500.0);
+    assert!(!trial_balances["USD"].contains_key(&AccountType::Expense));
+
+    let statements = ledger.income_statement_by_system(start, Utc::now());
+    assert_eq!(statements.into_iter().collect::<Vec<_>>(), [
+        ("JPY".to_string(), (0.0, 1500.0, -1500.0)),
+        ("USD".to_string(), (10.0, 0.0, 10.0)),
+    ]);
+}
+
+#[test]
+fn restore_brings_back_the_snapshot() {
+    let dir = TempDir::new("snapshot");
+    let backups = TempDir::new("snapshot_backups");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 10.0), (capital, -10.0)]);
+
+    let snapshot = ledger.snapshot(backups.path()).unwrap();
+    assert_eq!(snapshot.parent(), Some(backups.path()));
+
+    let bank = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    post(&mut ledger, Utc::now(), &[(bank, 5.0), (capital, -5.0)]);
+    ledger.create_system(system("EUR")).unwrap();
+    ledger.set_amount_scale(4);
+
+    ledger.restore(&snapshot).unwrap();
+    assert!(ledger.find_account(bank).is_none());
+    assert!(ledger.find_system("EUR").is_none());
+    assert_eq!(ledger.transactions.len(), 1);
+    assert_eq!(ledger.account_balance(capital), -10.0);
+    assert!(ledger.verify_indexes().is_empty(), "{:?}", ledger.verify_indexes());
+    // settings belong to the running ledger, not the data
+    assert_eq!(ledger.amount_scale, 4);
+
+    // the restored data stays usable and is what the next run finds
+    create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    drop(ledger);
+    let ledger = reload(&dir);
+    assert_eq!(ledger.accounts().count(), 3);
+    assert_eq!(ledger.account_balance(cash), 10.0);
+}
+
+#[test]
+fn reset_empties_a_populated_ledger() {
+    let dir = TempDir::new("reset");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, Utc::now())).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    post(&mut ledger, Utc::now(), &[(cash, 10.0), (capital, -10.0)]);
+    ledger.persist_indexes().unwrap();
+    drop(ledger);
+    std::fs::write(dir.join("accounts.jsonl"), "{}\n").unwrap();
+
+    let mut ledger = Ledger::reset(dir.path()).unwrap();
+    let stats = ledger.stats().unwrap();
+    assert_eq!((stats.accounts, stats.transactions, stats.entries, stats.systems), (0, 0, 0, 0));
+    assert_eq!(stats.active_conversion_graphs, 0);
+    assert!(stats.files.iter().all(|file| file.index_len == 0 && file.tombstones == 0), "{:?}", stats.files);
+    assert!(ledger.find_account(cash).is_none());
+    assert_eq!(ledger.account_balance(cash), 0.0);
+    for file in ["accounts", "transactions", "entries", "systems", "conversion_graphs"] {
+        assert!(dir.join(&format!("{}.bin", file)).is_file(), "{}", file);
+        assert!(dir.join(&format!("{}.idx", file)).is_file(), "{}", file);
+    }
+    assert!(!dir.join("accounts.jsonl").exists());
+
+    // the fresh ledger writes to the reinstalled files
+    ledger.create_system(system("USD")).unwrap();
+    create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    drop(ledger);
+    let ledger = reload(&dir);
+    assert_eq!((ledger.systems().count(), ledger.accounts().count(), ledger.transactions.len()), (1, 1, 0));
+    assert_eq!(ledger.account_balance(cash), 0.0);
+    assert!(ledger.verify_indexes().is_empty(), "{:?}", ledger.verify_indexes());
+}
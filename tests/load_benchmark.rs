@@ -0,0 +1,84 @@
+mod common;
+
+use std::time::Instant;
+
+use chrono::Utc;
+use uuid::Uuid;
+use zentry_db::model::{Account, AccountType, ConversionGraph, Entry, System, Transaction};
+use zentry_db::storage::{
+    account_layout, conversion_graph_layout, entry_layout, read_file_with_offsets, system_layout, transaction_layout,
+    BinaryLayout, FromBinary, TombstoneWriter, FILE_HEADER_LEN,
+};
+
+use common::{account, graph, ledger, reload, system, transaction, TempDir};
+
+const RECORDS: usize = 50_000;
+
+/// Times a full load, which scans the five record files on threads of their own, and compares
+/// scanning the same files one after the other with scanning them on a thread each.
+/// Run with `cargo test --release --test load_benchmark -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn threaded_scans_beat_sequential_scans() {
+    let dir = TempDir::new("load_benchmark");
+    let ledger = ledger(&dir);
+
+    let systems: Vec<System> = (0..RECORDS).map(|i| system(&format!("S{}", i))).collect();
+    let accounts: Vec<Account> = (0..RECORDS).map(|i| account(&format!("account {}", i), AccountType::Asset, "S0")).collect();
+    let transactions: Vec<Transaction> = (0..RECORDS).map(|i| transaction(&format!("transaction {}", i), Utc::now())).collect();
+    let entries: Vec<Entry> = transactions.iter().zip(accounts.iter())
+        .map(|(tx, account)| Entry { id: Uuid::new_v4(), transaction_id: tx.id, account_id: account.id, amount: 0.0 })
+        .collect();
+    let graphs: Vec<ConversionGraph> = (1..RECORDS)
+        .map(|i| graph(&format!("S0 -> S{}", i), 1.0, Utc::now()))
+        .collect();
+
+    ledger.storage.write_batch(systems).unwrap();
+    ledger.storage.write_batch(accounts).unwrap();
+    ledger.storage.write_batch(transactions).unwrap();
+    ledger.storage.write_batch(entries).unwrap();
+    ledger.storage.write_batch(graphs).unwrap();
+    ledger.flush().unwrap();
+    drop(ledger);
+
+    // the first load rebuilds and persists every index, time the one after it
+    drop(reload(&dir));
+    let start = Instant::now();
+    let ledger = reload(&dir);
+    let load = start.elapsed();
+    assert_eq!(ledger.accounts.len(), RECORDS);
+    assert_eq!(ledger.entries.len(), RECORDS);
+
+    let start = Instant::now();
+    scan::<Account>(&dir, "accounts.bin", account_layout());
+    scan::<Transaction>(&dir, "transactions.bin", transaction_layout());
+    scan::<Entry>(&dir, "entries.bin", entry_layout());
+    scan::<System>(&dir, "systems.bin", system_layout());
+    scan::<ConversionGraph>(&dir, "conversion_graphs.bin", conversion_graph_layout());
+    let sequential = start.elapsed();
+
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        scope.spawn(|| scan::<Account>(&dir, "accounts.bin", account_layout()));
+        scope.spawn(|| scan::<Transaction>(&dir, "transactions.bin", transaction_layout()));
+        scope.spawn(|| scan::<Entry>(&dir, "entries.bin", entry_layout()));
+        scope.spawn(|| scan::<System>(&dir, "systems.bin", system_layout()));
+        scope.spawn(|| scan::<ConversionGraph>(&dir, "conversion_graphs.bin", conversion_graph_layout()));
+    });
+    let parallel = start.elapsed();
+
+    println!(
+        "{} records per file: load {:?}, scans one after the other {:?}, scans on a thread each {:?}",
+        RECORDS, load, sequential, parallel
+    );
+    // with a single core there is nothing to gain
+    if std::thread::available_parallelism().map_or(1, |cores| cores.get()) > 1 {
+        assert!(parallel < sequential, "scans on a thread each took {:?}, one after the other {:?}", parallel, sequential);
+    }
+}
+
+/// Every file holds `RECORDS` records but the conversion graphs, which skip `S0 -> S0`
+fn scan<T: FromBinary>(dir: &TempDir, file: &str, layout: BinaryLayout) {
+    let records = read_file_with_offsets::<T>(&dir.join(file), &layout, FILE_HEADER_LEN).unwrap();
+    assert!(records.len() >= RECORDS - 1);
+}
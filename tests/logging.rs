@@ -0,0 +1,86 @@
+//! Installs a process-wide logger, so these tests live in a binary of their own
+
+mod common;
+
+use std::process::Command;
+use std::sync::{Mutex, Once};
+
+use chrono::{Duration, Utc};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use common::{graph, ledger, reload, system, TempDir};
+
+/// Keeps every record logged in this process
+struct CapturingLogger {
+    records: Mutex<Vec<(Level, String)>>,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.records.lock().unwrap().push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger { records: Mutex::new(Vec::new()) };
+static INIT: Once = Once::new();
+
+fn logged() -> &'static Mutex<Vec<(Level, String)>> {
+    INIT.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(LevelFilter::Debug);
+    });
+    &LOGGER.records
+}
+
+/// Loads a ledger and archives a conversion graph, the paths that used to print
+fn load_and_archive(name: &str) {
+    let dir = TempDir::new(name);
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.9, Utc::now() - Duration::hours(1))).unwrap();
+    ledger.create_conversion_graph(graph("USD -> EUR", 0.8, Utc::now())).unwrap();
+    drop(ledger);
+    reload(&dir);
+}
+
+#[test]
+fn loading_logs_at_debug() {
+    let records = logged();
+    load_and_archive("logging");
+
+    let records = records.lock().unwrap();
+    let debug = |prefix: &str| records.iter().any(|(level, message)| *level == Level::Debug && message.starts_with(prefix));
+    assert!(debug("Completed loading ledger data. Took: "), "{:?}", records);
+    assert!(debug("Archiving conversion graph: "), "{:?}", records);
+}
+
+/// Run by `loading_prints_nothing`, in a process of its own so its stdout can be read
+#[test]
+#[ignore]
+fn load_without_a_logger() {
+    load_and_archive("quiet");
+}
+
+#[test]
+fn loading_prints_nothing() {
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["load_without_a_logger", "--exact", "--ignored", "--nocapture", "--test-threads=1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // everything left once the test harness' own lines are dropped came from the ledger
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let noise: Vec<_> = stdout.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with("running ") && !line.starts_with("test "))
+        .collect();
+    assert!(noise.is_empty(), "{:?}", noise);
+    assert!(stdout.contains("test load_without_a_logger ... ok"), "{}", stdout);
+}
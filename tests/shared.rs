@@ -0,0 +1,36 @@
+mod common;
+
+use zentry_db::shared::SharedLedger;
+
+use common::{ledger, reload, system, TempDir};
+
+#[test]
+fn systems_created_from_two_threads_all_persist() {
+    let dir = TempDir::new("shared_ledger");
+    let shared = SharedLedger::new(ledger(&dir));
+
+    std::thread::scope(|scope| {
+        for thread in 0..2 {
+            let shared = shared.clone();
+            scope.spawn(move || {
+                for i in 0..50 {
+                    shared.create_system(system(&format!("T{}S{}", thread, i))).unwrap();
+                }
+            });
+        }
+    });
+
+    assert_eq!(shared.with(|ledger| ledger.systems().count()), 100);
+    let ledger = shared.into_inner().unwrap();
+    ledger.flush().unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert_eq!(ledger.systems().count(), 100);
+    for thread in 0..2 {
+        for i in 0..50 {
+            assert!(ledger.find_system(&format!("T{}S{}", thread, i)).is_some(), "T{}S{} is missing", thread, i);
+        }
+    }
+    assert!(ledger.verify_indexes().is_empty());
+}
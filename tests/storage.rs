@@ -0,0 +1,512 @@
+mod common;
+
+use std::io::Cursor;
+
+use chrono::Utc;
+use uuid::Uuid;
+use zentry_db::db::Ledger;
+use zentry_db::error::ZentryError;
+use zentry_db::install_at;
+use zentry_db::model::{Account, AccountType, ConversionGraph, Entry, ExactRate, System, Transaction};
+use zentry_db::storage::{
+    account_layout, account_layout_for, compute_object_size, conversion_graph_layout, entry_layout, file_header,
+    record_size, system_layout, system_layout_for, transaction_layout, BinaryLayout, BinaryRecord, FromBinary,
+    RecordCursor, ToBinary, TombstoneWriter, ACCOUNT_LAYOUT_VERSION, ACCOUNT_NANOS_LAYOUT_VERSION,
+    CONVERSION_GRAPH_LAYOUT_VERSION, ENTRY_LAYOUT_VERSION, LEGACY_LAYOUT_VERSION, STATUS_DEAD, STATUS_LIVE,
+    SYSTEM_LAYOUT_VERSION, TRANSACTION_LAYOUT_VERSION,
+};
+use zentry_db::util::uuid::generate_deterministic_uuid;
+
+use common::{account, create_account, graph, ledger, reload, system, transaction, TempDir};
+
+#[test]
+fn compact_drops_tombstones_and_rewrites_offsets() {
+    let dir = TempDir::new("compact");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let first = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let middle = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let last = create_account(&mut ledger, "Loan", AccountType::Liability, "USD");
+    ledger.delete_account(middle).unwrap();
+    ledger.flush().unwrap();
+
+    let before = std::fs::metadata(dir.join("accounts.bin")).unwrap().len();
+    let last_offset_before = ledger.account_index.get(&generate_deterministic_uuid(&last)).unwrap();
+    ledger.compact().unwrap();
+    let after = std::fs::metadata(dir.join("accounts.bin")).unwrap().len();
+
+    assert!(after < before, "accounts.bin is {} bytes after compacting, {} before", after, before);
+    assert_eq!(ledger.account_index.len(), 2);
+
+    for (id, name) in [(first, "Cash"), (last, "Loan")] {
+        let offset = ledger.account_index.get(&generate_deterministic_uuid(&id)).unwrap();
+        let account = ledger.storage.read_single::<Account>(offset).unwrap();
+        assert_eq!(account.id, id);
+        assert_eq!(account.name, name);
+    }
+    let moved = ledger.account_index.get(&generate_deterministic_uuid(&last)).unwrap();
+    assert!(moved < last_offset_before, "the last account stayed at offset {}", moved);
+}
+
+#[test]
+fn reading_a_tombstoned_record_is_a_dead_record() {
+    let dir = TempDir::new("dead_record");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let id = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let offset = ledger.account_index.get(&generate_deterministic_uuid(&id)).unwrap();
+    ledger.delete_account(id).unwrap();
+
+    assert!(matches!(ledger.storage.read_single::<Account>(offset), Err(ZentryError::DeadRecord)));
+}
+
+#[test]
+fn corrupted_record_is_skipped_on_load() {
+    let dir = TempDir::new("checksum");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let first = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let corrupted = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let last = create_account(&mut ledger, "Loan", AccountType::Liability, "USD");
+    let offset = ledger.account_index.get(&generate_deterministic_uuid(&corrupted)).unwrap();
+    ledger.flush().unwrap();
+    drop(ledger);
+
+    // past the status byte and the checksum, inside the account id
+    let mut bytes = std::fs::read(dir.join("accounts.bin")).unwrap();
+    bytes[offset as usize + 1 + 4 + 3] ^= 0xff;
+    std::fs::write(dir.join("accounts.bin"), bytes).unwrap();
+
+    let ledger = reload(&dir);
+    assert!(matches!(ledger.storage.read_single::<Account>(offset), Err(ZentryError::ChecksumMismatch)));
+    assert!(ledger.find_account(corrupted).is_none());
+    assert_eq!(ledger.find_account(first).unwrap().name, "Cash");
+    assert_eq!(ledger.find_account(last).unwrap().name, "Loan");
+    assert_eq!(ledger.accounts.len(), 2);
+}
+
+#[test]
+fn unsupported_layout_version_is_a_descriptive_error() {
+    let dir = TempDir::new("old_version");
+    install_at(dir.path()).unwrap();
+
+    // a header can't declare version 1, those files predate headers
+    std::fs::write(dir.join("accounts.bin"), file_header(LEGACY_LAYOUT_VERSION)).unwrap();
+
+    match Ledger::load_from_dir(dir.path()) {
+        Err(ZentryError::InvalidData(message)) => {
+            assert!(message.contains("layout version 1"), "unexpected message: {}", message);
+        }
+        other => panic!("expected InvalidData, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn uncommitted_transaction_is_rolled_back_on_load() {
+    let dir = TempDir::new("partial_write");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    let tx = transaction("interrupted", Utc::now());
+    let mut entries = vec![ledger.new_entry(cash, 100.0).unwrap(), ledger.new_entry(capital, -100.0).unwrap()];
+    for entry in entries.iter_mut() {
+        entry.transaction_id = tx.id;
+    }
+
+    // what record_transaction does up to the commit marker
+    ledger.wal.begin(tx.id, entries.iter().map(|entry| entry.id).collect()).unwrap();
+    let written = ledger.storage.write_batch(entries).unwrap();
+    ledger.flush().unwrap();
+    drop(ledger);
+
+    let mut ledger = reload(&dir);
+    assert!(ledger.entries.is_empty());
+    assert!(ledger.transactions.is_empty());
+    assert!(ledger.wal.pending().unwrap().is_empty());
+    for (offset, _) in written {
+        assert!(matches!(ledger.storage.read_single::<Entry>(offset), Err(ZentryError::DeadRecord)));
+    }
+}
+
+#[test]
+fn flushed_records_are_on_disk_while_the_ledger_is_open() {
+    let dir = TempDir::new("flush");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    ledger.flush().unwrap();
+
+    // a second ledger only sees what reached the files, `ledger` is still open and not dropped
+    let observer = Ledger::load_read_only(dir.path()).unwrap();
+    assert_eq!(observer.find_account(cash).unwrap().name, "Cash");
+    assert!(observer.find_system("USD").is_some());
+
+    ledger.create_account(account("Bank", AccountType::Asset, "USD")).unwrap();
+    ledger.flush().unwrap();
+    assert_eq!(Ledger::load_read_only(dir.path()).unwrap().accounts().count(), 2);
+}
+
+#[test]
+fn repeated_read_single_is_served_from_the_cache() {
+    let dir = TempDir::new("read_cache");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let offset = ledger.account_index.get(&generate_deterministic_uuid(&cash)).unwrap();
+
+    let before = ledger.storage.disk_reads();
+    let first = ledger.storage.read_single::<Account>(offset).unwrap();
+    let second = ledger.storage.read_single::<Account>(offset).unwrap();
+
+    assert_eq!(ledger.storage.disk_reads() - before, 1);
+    assert_eq!(first.id, cash);
+    assert_eq!(second.id, cash);
+}
+
+#[test]
+fn read_range_pages_skip_tombstones() {
+    let dir = TempDir::new("read_range");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let ids: Vec<_> = (0..7)
+        .map(|i| create_account(&mut ledger, &format!("account {}", i), AccountType::Asset, "USD"))
+        .collect();
+    let offsets: Vec<_> = ids.iter()
+        .map(|id| ledger.account_index.get(&generate_deterministic_uuid(id)).unwrap())
+        .collect();
+    ledger.delete_account(ids[1]).unwrap();
+    ledger.delete_account(ids[3]).unwrap();
+    ledger.flush().unwrap();
+
+    let page_ids = |page: Vec<Account>| page.into_iter().map(|account| account.id).collect::<Vec<_>>();
+
+    let (page, next) = ledger.storage.read_range::<Account>(0, 2).unwrap();
+    assert_eq!(page_ids(page), [ids[0], ids[2]]);
+    assert_eq!(next, Some(offsets[3]));
+
+    let (page, next) = ledger.storage.read_range::<Account>(next.unwrap(), 2).unwrap();
+    assert_eq!(page_ids(page), [ids[4], ids[5]]);
+    assert_eq!(next, Some(offsets[6]));
+
+    let (page, next) = ledger.storage.read_range::<Account>(next.unwrap(), 2).unwrap();
+    assert_eq!(page_ids(page), [ids[6]]);
+    assert_eq!(next, None);
+}
+
+#[test]
+fn long_description_round_trips_through_its_u16_prefix() {
+    let layout = transaction_layout();
+    let tx = transaction(&"d".repeat(1000), Utc::now());
+
+    let mut bytes = Vec::new();
+    tx.to_binary(&mut bytes, &layout).unwrap();
+    // a record is the status byte and the checksum ahead of the body
+    let decoded = Transaction::from_binary(&mut &bytes[1 + 4..], &layout).unwrap();
+
+    assert_eq!(decoded.description, tx.description);
+    assert_eq!(decoded.timestamp, tx.timestamp);
+    assert_eq!(compute_object_size(&layout, &bytes, 1 + 4).unwrap(), bytes.len() - 1 - 4);
+    assert_eq!(bytes.len(), 1 + 4 + 16 + 2 + 1000 + 4 + 8);
+}
+
+#[test]
+fn invalid_utf8_in_a_name_is_an_error() {
+    let layout = account_layout();
+    let mut bytes = Vec::new();
+    account("Cash", AccountType::Asset, "USD").to_binary(&mut bytes, &layout).unwrap();
+
+    // status byte, checksum, id and the u16 length of `name`
+    bytes[1 + 4 + 16 + 2] = 0xff;
+
+    match Account::from_binary(&mut &bytes[1 + 4..], &layout) {
+        Err(ZentryError::InvalidData(message)) => assert!(message.contains("`name` is not valid UTF-8"), "unexpected message: {}", message),
+        other => panic!("expected InvalidData, got {:?}", other),
+    }
+}
+
+#[test]
+fn each_model_has_its_own_record_file() {
+    assert_eq!(Account::TYPE_KEY, "accounts");
+    assert_eq!(Transaction::TYPE_KEY, "transactions");
+    assert_eq!(Entry::TYPE_KEY, "entries");
+    assert_eq!(System::TYPE_KEY, "systems");
+    assert_eq!(ConversionGraph::TYPE_KEY, "conversion_graphs");
+
+    let dir = TempDir::new("type_keys");
+    let ledger = ledger(&dir);
+    assert_eq!(ledger.storage.file_layout::<Account>().unwrap().0.name, "Account");
+    assert_eq!(ledger.storage.file_layout::<Transaction>().unwrap().0.name, "Transaction");
+    assert_eq!(ledger.storage.file_layout::<Entry>().unwrap().0.name, "Entry");
+    assert_eq!(ledger.storage.file_layout::<System>().unwrap().0.name, "System");
+    assert_eq!(ledger.storage.file_layout::<ConversionGraph>().unwrap().0.name, "ConversionGraph");
+}
+
+#[test]
+fn write_batch_offsets_are_contiguous() {
+    let dir = TempDir::new("write_batch");
+    let ledger = ledger(&dir);
+    let start = std::fs::metadata(dir.join("accounts.bin")).unwrap().len();
+
+    let accounts: Vec<Account> = ["Cash", "Bank with a longer name", "Loan"].iter()
+        .map(|name| account(name, AccountType::Asset, "USD"))
+        .collect();
+    let sizes: Vec<u64> = accounts.iter()
+        .map(|account| {
+            let mut bytes = Vec::new();
+            account.to_binary(&mut bytes, &account_layout()).unwrap();
+            bytes.len() as u64
+        })
+        .collect();
+
+    let written = ledger.storage.write_batch(accounts).unwrap();
+    ledger.flush().unwrap();
+
+    let mut expected = start;
+    for ((offset, account), size) in written.iter().zip(sizes) {
+        assert_eq!(*offset, expected);
+        assert_eq!(ledger.storage.read_single::<Account>(*offset).unwrap().id, account.id);
+        expected += size;
+    }
+    assert_eq!(std::fs::metadata(dir.join("accounts.bin")).unwrap().len(), expected);
+}
+
+#[test]
+fn huge_length_prefix_errors_instead_of_allocating() {
+    let layout = transaction_layout();
+    let mut bytes = Vec::new();
+    transaction("x", Utc::now()).to_binary(&mut bytes, &layout).unwrap();
+
+    // status byte, checksum, id, the u16 length of `description` and its one byte
+    let metadata_len = 1 + 4 + 16 + 2 + 1;
+    bytes[metadata_len..metadata_len + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    match Transaction::from_binary(&mut &bytes[1 + 4..], &layout) {
+        Err(ZentryError::InvalidData(message)) => {
+            assert!(message.contains("`metadata` declares a length of 4294967295 bytes"), "unexpected message: {}", message);
+        }
+        other => panic!("expected InvalidData, got {:?}", other),
+    }
+
+    // within the field limit, but past the end of the data
+    bytes[metadata_len..metadata_len + 4].copy_from_slice(&1_000_000u32.to_le_bytes());
+    let error = Transaction::from_binary(&mut &bytes[1 + 4..], &layout).unwrap_err();
+    assert!(error.to_string().contains("`metadata` declares 1000000 bytes but only"), "unexpected error: {}", error);
+}
+
+/// Records of every model, with and without their optional fields
+struct Samples {
+    accounts: Vec<Account>,
+    transactions: Vec<Transaction>,
+    entries: Vec<Entry>,
+    systems: Vec<System>,
+    graphs: Vec<ConversionGraph>,
+}
+
+fn samples() -> Samples {
+    let mut child = account("Checking account", AccountType::Asset, "USD");
+    child.parent_id = Some(Uuid::new_v4());
+
+    let mut tx = transaction("opening balance", Utc::now());
+    tx.metadata = Some(serde_json::json!({"ref": "INV-1"}));
+
+    let entry = Entry { id: Uuid::new_v4(), transaction_id: tx.id, account_id: child.id, amount: -12.5 };
+
+    let mut usd = system("USD");
+    usd.symbol = Some("$".to_string());
+    usd.decimal_places = Some(2);
+
+    let mut exact = graph("USD -> EUR", 0.75, Utc::now());
+    exact.exact_rate = ExactRate::new(3, 4);
+
+    Samples {
+        accounts: vec![account("Cash", AccountType::Asset, "USD"), child],
+        transactions: vec![transaction("", Utc::now()), tx],
+        entries: vec![entry.clone(), Entry { id: Uuid::new_v4(), ..entry }],
+        systems: vec![system("EUR"), usd],
+        graphs: vec![graph("USD -> JPY", 150.0, Utc::now()), exact],
+    }
+}
+
+fn encode<T: ToBinary>(record: &T, layout: &BinaryLayout) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    record.to_binary(&mut bytes, layout).unwrap();
+    bytes
+}
+
+fn assert_sizes<T: ToBinary>(records: &[T], layout: BinaryLayout) {
+    for record in records {
+        let bytes = encode(record, &layout);
+        assert_eq!(compute_object_size(&layout, &bytes, 1 + 4).unwrap(), bytes.len() - 1 - 4, "{}", layout.name);
+        assert_eq!(record_size(&layout, &mut Cursor::new(&bytes), 0).unwrap(), bytes.len(), "{}", layout.name);
+    }
+}
+
+#[test]
+fn computed_sizes_match_encoded_lengths() {
+    let samples = samples();
+    assert_sizes(&samples.accounts, account_layout());
+    assert_sizes(&samples.transactions, transaction_layout());
+    assert_sizes(&samples.entries, entry_layout());
+    assert_sizes(&samples.systems, system_layout());
+    assert_sizes(&samples.graphs, conversion_graph_layout());
+}
+
+/// Encodes `records` back to back and skips each body in turn, checking the reader lands on the
+/// next record's status byte
+fn assert_skips<T: ToBinary + FromBinary>(records: &[T], layout: BinaryLayout) {
+    let encoded: Vec<_> = records.iter().map(|record| encode(record, &layout)).collect();
+    let mut reader = Cursor::new(encoded.concat());
+    let mut offset = 0;
+    for bytes in &encoded {
+        reader.set_position(offset + 1 + 4);
+        T::skip_bytes(&mut reader, &layout).unwrap();
+        offset += bytes.len() as u64;
+        assert_eq!(reader.position(), offset, "{}", layout.name);
+    }
+}
+
+#[test]
+fn skip_bytes_lands_on_the_next_record() {
+    let samples = samples();
+    assert_skips(&samples.accounts, account_layout());
+    assert_skips(&samples.transactions, transaction_layout());
+    assert_skips(&samples.entries, entry_layout());
+    assert_skips(&samples.systems, system_layout());
+    assert_skips(&samples.graphs, conversion_graph_layout());
+}
+
+#[test]
+fn install_creates_only_the_missing_files() {
+    let dir = TempDir::new("partial_install");
+    std::fs::write(dir.join("accounts.bin"), b"kept").unwrap();
+
+    let created = install_at(dir.path()).unwrap();
+    assert_eq!(created.len(), 9, "{:?}", created);
+    assert!(!created.contains(&dir.join("accounts.bin")));
+    assert_eq!(std::fs::read(dir.join("accounts.bin")).unwrap(), b"kept");
+
+    for name in ["accounts", "transactions", "entries", "systems", "conversion_graphs"] {
+        for extension in ["bin", "idx"] {
+            assert!(dir.join(&format!("{}.{}", name, extension)).is_file(), "{}.{} is missing", name, extension);
+        }
+    }
+    assert!(install_at(dir.path()).unwrap().is_empty());
+}
+
+#[test]
+fn record_cursor_yields_only_live_records_in_file_order() {
+    let dir = TempDir::new("record_cursor");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let ids: Vec<_> = (0..5)
+        .map(|i| create_account(&mut ledger, &format!("account {}", i), AccountType::Asset, "USD"))
+        .collect();
+    ledger.delete_account(ids[1]).unwrap();
+    ledger.delete_account(ids[4]).unwrap();
+    ledger.flush().unwrap();
+
+    let streamed: Vec<_> = ledger.iter_accounts().unwrap().map(|account| account.unwrap().id).collect();
+    assert_eq!(streamed, [ids[0], ids[2], ids[3]]);
+
+    let (layout, data_offset) = ledger.storage.file_layout::<Account>().unwrap();
+    let mut cursor = RecordCursor::<Account>::open(&dir.join("accounts.bin"), layout, data_offset).unwrap();
+    let offsets: Vec<_> = cursor.by_ref().map(|record| record.unwrap().0).collect();
+    let expected: Vec<_> = [ids[0], ids[2], ids[3]].iter()
+        .map(|id| ledger.account_index.get(&generate_deterministic_uuid(id)).unwrap())
+        .collect();
+    assert_eq!(offsets, expected);
+    assert!(cursor.next().is_none());
+
+    cursor.reset().unwrap();
+    assert_eq!(cursor.next().unwrap().unwrap().1.id, ids[0]);
+}
+
+#[test]
+fn account_type_is_stored_by_name() {
+    let layout = account_layout();
+    let types = [
+        AccountType::Asset,
+        AccountType::Liability,
+        AccountType::Equity,
+        AccountType::Revenue,
+        AccountType::Expense,
+        AccountType::Custom(60),
+    ];
+    for account_type in types {
+        let bytes = encode(&account("Cash", account_type.clone(), "USD"), &layout);
+        assert_eq!(Account::from_binary(&mut &bytes[1 + 4..], &layout).unwrap().account_type, account_type);
+
+        // the byte layout it replaced still reads back the same type
+        let byte_layout = account_layout_for(ACCOUNT_NANOS_LAYOUT_VERSION);
+        let bytes = encode(&account("Cash", account_type.clone(), "USD"), &byte_layout);
+        assert_eq!(Account::from_binary(&mut &bytes[1 + 4..], &byte_layout).unwrap().account_type, account_type);
+    }
+
+    let bytes = encode(&account("Cash", AccountType::Liability, "USD"), &layout);
+    let name_at = bytes.windows(9).position(|window| window == b"Liability").unwrap();
+
+    for unknown in [&b"Liabilitx"[..], b"liability", b"Custom(4)"] {
+        let mut corrupted = bytes.clone();
+        corrupted[name_at..name_at + 9].copy_from_slice(unknown);
+        match Account::from_binary(&mut &corrupted[1 + 4..], &layout) {
+            Err(ZentryError::InvalidData(message)) => assert!(message.contains("unknown account type name"), "{}", message),
+            other => panic!("{} decoded to {:?}", String::from_utf8_lossy(unknown), other.map(|account| account.account_type)),
+        }
+    }
+}
+
+#[test]
+fn unexpected_status_byte_is_corruption() {
+    let dir = TempDir::new("corrupt_status");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let corrupted = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let offset = ledger.account_index.get(&generate_deterministic_uuid(&corrupted)).unwrap();
+    ledger.flush().unwrap();
+    drop(ledger);
+
+    let mut bytes = std::fs::read(dir.join("accounts.bin")).unwrap();
+    assert_eq!(bytes[offset as usize], STATUS_LIVE);
+    assert_ne!(STATUS_LIVE, STATUS_DEAD);
+    bytes[offset as usize] = 0x07;
+    std::fs::write(dir.join("accounts.bin"), bytes).unwrap();
+
+    match Ledger::load_from_dir(dir.path()) {
+        Err(ZentryError::CorruptStatus { offset: at, status }) => assert_eq!((at, status), (offset, 0x07)),
+        other => panic!("expected CorruptStatus, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn format_versions_match_the_layout_constants() {
+    let dir = TempDir::new("format_versions");
+    let ledger = ledger(&dir);
+
+    let versions: Vec<_> = ledger.format_versions().unwrap().into_iter()
+        .map(|version| (version.file, version.on_disk, version.current))
+        .collect();
+    assert_eq!(versions, [
+        ("accounts.bin", Some(ACCOUNT_LAYOUT_VERSION), ACCOUNT_LAYOUT_VERSION),
+        ("transactions.bin", Some(TRANSACTION_LAYOUT_VERSION), TRANSACTION_LAYOUT_VERSION),
+        ("entries.bin", Some(ENTRY_LAYOUT_VERSION), ENTRY_LAYOUT_VERSION),
+        ("systems.bin", Some(SYSTEM_LAYOUT_VERSION), SYSTEM_LAYOUT_VERSION),
+        ("conversion_graphs.bin", Some(CONVERSION_GRAPH_LAYOUT_VERSION), CONVERSION_GRAPH_LAYOUT_VERSION),
+    ]);
+    drop(ledger);
+
+    // a file without a header predates versioning, its records start right away
+    let legacy = encode(&system("USD"), &system_layout_for(LEGACY_LAYOUT_VERSION));
+    std::fs::write(dir.join("systems.bin"), legacy).unwrap();
+    let ledger = reload(&dir);
+    let systems = &ledger.format_versions().unwrap()[3];
+    assert_eq!((systems.on_disk, systems.current), (None, SYSTEM_LAYOUT_VERSION));
+    assert_eq!(ledger.find_system("USD").unwrap().description, "USD system");
+}
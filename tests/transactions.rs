@@ -0,0 +1,559 @@
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use zentry_db::db::Ledger;
+use zentry_db::error::ZentryError;
+use zentry_db::interface::cli::TransactionDraft;
+use zentry_db::model::{register_account_type, AccountType};
+
+use common::{create_account, ledger, post, reload, system, transaction, TempDir};
+
+#[test]
+fn cross_system_transaction_has_to_balance_per_system() {
+    let dir = TempDir::new("cross_system");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    ledger.create_system(system("EUR")).unwrap();
+
+    let dollars = create_account(&mut ledger, "Dollars", AccountType::Asset, "USD");
+    let euros = create_account(&mut ledger, "Euros", AccountType::Asset, "EUR");
+    let dollar_capital = create_account(&mut ledger, "Dollar capital", AccountType::Equity, "USD");
+    let euro_capital = create_account(&mut ledger, "Euro capital", AccountType::Equity, "EUR");
+
+    let entries = vec![
+        ledger.new_entry(dollars, 100.0).unwrap(),
+        ledger.new_entry(euros, -100.0).unwrap(),
+    ];
+    match ledger.record_transaction(transaction("swap", Utc::now()), entries) {
+        Err(ZentryError::UnbalancedSystem { system_id, sum }) => {
+            assert!(system_id == "USD" || system_id == "EUR", "unexpected system {}", system_id);
+            assert_eq!(sum.abs(), 100.0);
+        }
+        other => panic!("expected UnbalancedSystem, got {:?}", other),
+    }
+    assert_eq!(ledger.transactions.len(), 0);
+
+    post(&mut ledger, Utc::now(), &[(dollars, 100.0), (dollar_capital, -100.0), (euros, 90.0), (euro_capital, -90.0)]);
+    assert_eq!(ledger.transactions.len(), 1);
+}
+
+#[test]
+fn transactions_between_returns_the_sub_range() {
+    let dir = TempDir::new("transactions_between");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+    let start = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+    let days: Vec<_> = (0..5)
+        .map(|day| post(&mut ledger, start + Duration::days(day), &[(cash, 10.0), (capital, -10.0)]))
+        .collect();
+    ledger.flush().unwrap();
+    ledger.persist_indexes().unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let between: Vec<_> = ledger.transactions_between(start + Duration::days(1), start + Duration::days(3))
+        .iter()
+        .map(|tx| tx.id)
+        .collect();
+    assert_eq!(between, days[1..3]);
+    assert!(ledger.transactions_between(start - Duration::days(2), start).is_empty());
+}
+
+#[test]
+fn postings_against_the_normal_balance_are_warned_about() {
+    let dir = TempDir::new("sign_warnings");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+
+    let cases = [
+        (AccountType::Asset, true),
+        (AccountType::Expense, true),
+        (AccountType::Liability, false),
+        (AccountType::Equity, false),
+        (AccountType::Revenue, false),
+    ];
+    for (account_type, debit_normal) in cases {
+        let debited = create_account(&mut ledger, &format!("{:?} debited", account_type), account_type.clone(), "USD");
+        let credited = create_account(&mut ledger, &format!("{:?} credited", account_type), account_type.clone(), "USD");
+        let entries = vec![ledger.new_entry(debited, 50.0).unwrap(), ledger.new_entry(credited, -50.0).unwrap()];
+
+        let warnings = ledger.record_transaction_checked(transaction("posting", Utc::now()), entries).unwrap();
+        let unusual = if debit_normal { "credited" } else { "debited" };
+        assert_eq!(warnings.len(), 1, "{:?}: {:?}", account_type, warnings);
+        assert!(warnings[0].contains(&format!("{:?} {} ", account_type, unusual)), "{:?}: {}", account_type, warnings[0]);
+    }
+    assert_eq!(ledger.transactions.len(), 5);
+}
+
+#[test]
+fn new_entry_checks_the_account() {
+    let dir = TempDir::new("new_entry");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+
+    let entry = ledger.new_entry(cash, 12.5).unwrap();
+    assert_eq!(entry.account_id, cash);
+    assert_eq!(entry.amount, 12.5);
+    assert!(entry.transaction_id.is_nil());
+    assert_ne!(entry.id, ledger.new_entry(cash, 12.5).unwrap().id);
+
+    let missing = Uuid::new_v4();
+    assert!(matches!(ledger.new_entry(missing, 1.0), Err(ZentryError::NotFound(_))));
+}
+
+#[test]
+fn transactions_are_filtered_by_metadata() {
+    let dir = TempDir::new("metadata_filter");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+
+    let metadata = [Some(json!({"project": "alpha"})), Some(json!({"project": "beta"})), None, Some(json!(["project"]))];
+    let mut ids = Vec::new();
+    for (i, metadata) in metadata.into_iter().enumerate() {
+        let mut tx = transaction("tagged", Utc::now() + Duration::seconds(i as i64));
+        tx.metadata = metadata;
+        ids.push(tx.id);
+        let entries = vec![ledger.new_entry(cash, 1.0).unwrap(), ledger.new_entry(capital, -1.0).unwrap()];
+        ledger.record_transaction(tx, entries).unwrap();
+    }
+
+    let alpha: Vec<_> = ledger.find_transactions_by_metadata("project", &json!("alpha")).iter().map(|tx| tx.id).collect();
+    assert_eq!(alpha, [ids[0]]);
+    assert!(ledger.find_transactions_by_metadata("project", &json!("gamma")).is_empty());
+    assert!(ledger.find_transactions_by_metadata("project", &Value::Null).is_empty());
+}
+
+#[test]
+fn income_statement_covers_only_the_window() {
+    let dir = TempDir::new("income_statement");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    let rent = create_account(&mut ledger, "Rent", AccountType::Expense, "USD");
+
+    let start = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+    let end = Utc.with_ymd_and_hms(2026, 2, 1, 0, 0, 0).unwrap();
+    post(&mut ledger, start - Duration::seconds(1), &[(cash, 1000.0), (sales, -1000.0)]);
+    post(&mut ledger, start, &[(cash, 500.0), (sales, -500.0)]);
+    post(&mut ledger, start + Duration::days(10), &[(cash, 250.0), (sales, -250.0)]);
+    post(&mut ledger, start + Duration::days(15), &[(rent, 300.0), (cash, -300.0)]);
+    post(&mut ledger, end, &[(rent, 900.0), (cash, -900.0)]);
+
+    assert_eq!(ledger.income_statement(start, end), (750.0, 300.0, 450.0));
+    assert_eq!(ledger.income_statement(end, end + Duration::days(1)), (0.0, 900.0, -900.0));
+}
+
+#[test]
+fn transactions_for_account_lists_each_once() {
+    let dir = TempDir::new("transactions_for_account");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let bank = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let capital = create_account(&mut ledger, "Capital", AccountType::Equity, "USD");
+
+    let now = Utc::now();
+    let first = post(&mut ledger, now, &[(cash, 10.0), (cash, 5.0), (capital, -15.0)]);
+    post(&mut ledger, now + Duration::seconds(1), &[(bank, 20.0), (capital, -20.0)]);
+    let third = post(&mut ledger, now + Duration::seconds(2), &[(bank, -3.0), (cash, 3.0)]);
+
+    let ids: Vec<_> = ledger.transactions_for_account(cash).iter().map(|tx| tx.id).collect();
+    assert_eq!(ids, [first, third]);
+    assert!(ledger.transactions_for_account(Uuid::new_v4()).is_empty());
+}
+
+#[test]
+fn reversal_brings_balances_back_to_zero() {
+    let dir = TempDir::new("reversal");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    let tax = create_account(&mut ledger, "Sales tax", AccountType::Liability, "USD");
+
+    let original = post(&mut ledger, Utc::now() - Duration::hours(1), &[(cash, 110.0), (sales, -100.0), (tax, -10.0)]);
+    let reversal = ledger.reverse_transaction(original).unwrap();
+    assert_eq!(reversal.metadata, Some(json!({ "reverses": original.to_string() })));
+    assert!(matches!(ledger.reverse_transaction(original), Err(ZentryError::AlreadyExists(_))));
+    assert!(matches!(ledger.reverse_transaction(Uuid::new_v4()), Err(ZentryError::NotFound(_))));
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    for account in [cash, sales, tax] {
+        assert_eq!(ledger.account_balance(account), 0.0);
+    }
+    assert!(ledger.transactions.contains_key(&original));
+    assert_eq!(ledger.transactions.len(), 2);
+}
+
+#[test]
+fn idempotency_key_records_the_transaction_once() {
+    let dir = TempDir::new("idempotency_key");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+
+    let record = |ledger: &mut Ledger| {
+        let entries = vec![ledger.new_entry(cash, 10.0).unwrap(), ledger.new_entry(sales, -10.0).unwrap()];
+        ledger.record_transaction_idempotent("order-17", transaction("order 17", Utc::now()), entries).unwrap()
+    };
+
+    let first = record(&mut ledger);
+    assert_eq!(record(&mut ledger).id, first.id);
+    assert_eq!(ledger.transactions.len(), 1);
+    drop(ledger);
+
+    let mut ledger = reload(&dir);
+    assert!(dir.join("transactions_by_idempotency_key.idx").is_file());
+    assert_eq!(ledger.transaction_idempotency_index.get("order-17").collect::<Vec<_>>(), [&first.id]);
+    assert_eq!(record(&mut ledger).id, first.id);
+    assert_eq!(ledger.transactions.len(), 1);
+    assert_eq!(ledger.account_balance(cash), 10.0);
+}
+
+#[test]
+fn closing_a_period_moves_net_income_into_equity() {
+    let dir = TempDir::new("close_period");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    let rent = create_account(&mut ledger, "Rent", AccountType::Expense, "USD");
+    let retained = create_account(&mut ledger, "Retained earnings", AccountType::Equity, "USD");
+
+    let as_of = Utc::now() - Duration::hours(1);
+    post(&mut ledger, as_of - Duration::days(2), &[(cash, 500.0), (sales, -500.0)]);
+    post(&mut ledger, as_of - Duration::days(1), &[(rent, 120.0), (cash, -120.0)]);
+    // after the period, stays open
+    let later = post(&mut ledger, Utc::now(), &[(cash, 40.0), (sales, -40.0)]);
+
+    assert!(matches!(ledger.close_period(as_of, cash), Err(ZentryError::InvalidData(_))));
+    let closing = ledger.close_period(as_of, retained).unwrap();
+    assert_eq!(closing.timestamp, as_of);
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert_eq!(ledger.account_balance_as_of(sales, as_of), 0.0);
+    assert_eq!(ledger.account_balance_as_of(rent, as_of), 0.0);
+    // net income of 380 lands as a credit
+    assert_eq!(ledger.account_balance(retained), -380.0);
+    assert_eq!(ledger.account_balance(sales), -40.0);
+    assert_eq!(ledger.account_balance(cash), 420.0);
+    assert!(ledger.transactions.contains_key(&later));
+}
+
+#[test]
+fn metadata_validator_requires_a_ref() {
+    let dir = TempDir::new("metadata_validator");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+
+    // no validator by default, any metadata goes
+    post(&mut ledger, Utc::now(), &[(cash, 1.0), (sales, -1.0)]);
+
+    fn requires_ref(metadata: &Value) -> Result<(), String> {
+        match metadata.get("ref") {
+            Some(Value::String(_)) => Ok(()),
+            _ => Err("metadata needs a string `ref`".to_string()),
+        }
+    }
+    ledger.set_metadata_validator(Some(requires_ref));
+
+    let record = |ledger: &mut Ledger, metadata: Option<Value>| {
+        let mut tx = transaction("sale", Utc::now());
+        tx.metadata = metadata;
+        let entries = vec![ledger.new_entry(cash, 10.0).unwrap(), ledger.new_entry(sales, -10.0).unwrap()];
+        ledger.record_transaction(tx, entries)
+    };
+
+    for metadata in [None, Some(json!({"reference": "INV-1"})), Some(json!({"ref": 1}))] {
+        match record(&mut ledger, metadata.clone()) {
+            Err(ZentryError::InvalidData(message)) => assert!(message.contains("metadata needs a string `ref`"), "{}", message),
+            other => panic!("{:?} was accepted: {:?}", metadata, other),
+        }
+    }
+    assert_eq!(ledger.transactions.len(), 1);
+
+    record(&mut ledger, Some(json!({"ref": "INV-1"}))).unwrap();
+    assert_eq!(ledger.transactions.len(), 2);
+    assert_eq!(ledger.account_balance(cash), 11.0);
+}
+
+#[test]
+fn legs_balanced_after_rounding_are_accepted() {
+    let dir = TempDir::new("amount_rounding");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let bank = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+
+    let legs = [(cash, 0.101), (bank, 0.202), (sales, -0.3)];
+    let record = |ledger: &mut Ledger| {
+        let tx = transaction("split sale", Utc::now());
+        let id = tx.id;
+        let entries = legs.iter().map(|(account, amount)| ledger.new_entry(*account, *amount).unwrap()).collect();
+        ledger.record_transaction(tx, entries).map(|_| id)
+    };
+
+    // at three places the legs are 0.003 apart
+    ledger.set_amount_scale(3);
+    assert!(matches!(record(&mut ledger), Err(ZentryError::UnbalancedTransaction(_))));
+
+    // at the default two they are 0.10 + 0.20 - 0.30, which only balances within the tolerance
+    ledger.set_amount_scale(2);
+    let id = record(&mut ledger).unwrap();
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let mut amounts: Vec<_> = ledger.entries.iter().filter(|entry| entry.transaction_id == id).map(|entry| entry.amount).collect();
+    amounts.sort_by(f64::total_cmp);
+    assert_eq!(amounts, [-0.3, 0.1, 0.2]);
+}
+
+#[test]
+fn entries_between_windows_by_month_across_reload() {
+    let dir = TempDir::new("entries_between");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+
+    let month = |month| Utc.with_ymd_and_hms(2026, month, 1, 0, 0, 0).unwrap();
+    // posted out of order, the index keeps them sorted by transaction timestamp
+    let postings = [
+        (month(3) + Duration::days(20), 3.0),
+        (month(2) + Duration::days(3), 2.0),
+        (month(3), 4.0),
+        (month(4) - Duration::seconds(1), 5.0),
+        (month(4), 6.0),
+        (month(1), 1.0),
+    ];
+    for (timestamp, amount) in postings {
+        post(&mut ledger, timestamp, &[(cash, amount), (sales, -amount)]);
+    }
+
+    let march_cash = |ledger: &Ledger| {
+        ledger.entries_between(month(3), month(4)).unwrap().into_iter()
+            .filter(|entry| entry.account_id == cash)
+            .map(|entry| entry.amount)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(march_cash(&ledger), [4.0, 3.0, 5.0]);
+    assert_eq!(ledger.entries_between(month(1), month(2)).unwrap().len(), 2);
+    assert!(ledger.entries_between(month(5), month(6)).unwrap().is_empty());
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert_eq!(march_cash(&ledger), [4.0, 3.0, 5.0]);
+    assert_eq!(ledger.entries_between(month(1), month(5)).unwrap().len(), 12);
+}
+
+#[test]
+fn all_balances_matches_each_account_balance() {
+    let dir = TempDir::new("all_balances");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let bank = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    let untouched = create_account(&mut ledger, "Untouched", AccountType::Asset, "USD");
+
+    for i in 0..200 {
+        let amount = (i % 7 + 1) as f64;
+        let debited = if i % 3 == 0 { bank } else { cash };
+        post(&mut ledger, Utc::now(), &[(debited, amount), (sales, -amount)]);
+    }
+
+    // one pass over the 400 entries, summing into every account at once
+    let balances = ledger.all_balances();
+    assert_eq!(balances.len(), 3);
+    assert!(!balances.contains_key(&untouched));
+    for account in [cash, bank, sales] {
+        assert_eq!(balances[&account], ledger.account_balance(account));
+    }
+    assert_eq!(balances[&cash] + balances[&bank], 794.0);
+    assert_eq!(balances[&sales], -794.0);
+}
+
+#[test]
+fn find_entry_takes_the_entry_id() {
+    let dir = TempDir::new("find_entry");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+
+    let debit = ledger.new_entry(cash, 12.5).unwrap();
+    let credit = ledger.new_entry(sales, -12.5).unwrap();
+    let tx = transaction("sale", Utc::now());
+    ledger.record_transaction(tx.clone(), vec![debit.clone(), credit.clone()]).unwrap();
+
+    let found = ledger.find_entry(debit.id).unwrap();
+    assert_eq!((found.transaction_id, found.account_id, found.amount), (tx.id, cash, 12.5));
+    assert!(ledger.entry_index.get(&debit.id).is_some());
+    assert!(ledger.find_entry(tx.id).is_none());
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    assert_eq!(ledger.find_entry(credit.id).unwrap().amount, -12.5);
+    assert!(ledger.find_entry(Uuid::new_v4()).is_none());
+}
+
+#[test]
+fn transaction_draft_commits_once_balanced() {
+    let dir = TempDir::new("transaction_draft");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    create_account(&mut ledger, "Sales tax", AccountType::Liability, "USD");
+
+    let mut draft = TransactionDraft::new("counter sale");
+    assert_eq!(draft.add_leg(&ledger, &format!("{} 110", cash)).unwrap(), 110.0);
+    assert!(matches!(draft.commit(&mut ledger), Err(ZentryError::InvalidData(_))));
+
+    assert!(matches!(draft.add_leg(&ledger, "Sales"), Err(ZentryError::InvalidData(_))));
+    assert!(matches!(draft.add_leg(&ledger, "Sales ten"), Err(ZentryError::InvalidData(_))));
+    assert!(matches!(draft.add_leg(&ledger, "Salez -100"), Err(ZentryError::NotFound(_))));
+    assert_eq!(draft.legs.len(), 1);
+
+    // names may contain spaces, the amount is the last word
+    assert_eq!(draft.add_leg(&ledger, "Sales -100").unwrap(), 10.0);
+    assert!(matches!(draft.commit(&mut ledger), Err(ZentryError::UnbalancedTransaction(_))));
+    assert_eq!(draft.add_leg(&ledger, "  Sales tax   -10 ").unwrap(), 0.0);
+
+    let (id, warnings) = draft.commit(&mut ledger).unwrap();
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(ledger.transactions[&id].description, "counter sale");
+    assert_eq!(ledger.account_balance(cash), 110.0);
+    assert_eq!(ledger.account_balance(sales), -100.0);
+    assert_eq!(ledger.transactions.len(), 1);
+}
+
+#[test]
+fn balance_tolerance_absorbs_float_error() {
+    let dir = TempDir::new("balance_tolerance");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+
+    // ten legs of 0.1 add up to 0.9999999999999999, leaving about 1e-12 against the last leg
+    let mut legs = vec![(cash, 0.1); 10];
+    legs.push((sales, -0.999999999999));
+    let record = |ledger: &mut Ledger, legs: &[(Uuid, f64)]| {
+        let entries = legs.iter().map(|(account, amount)| ledger.new_entry(*account, *amount).unwrap()).collect();
+        ledger.record_transaction(transaction("many legs", Utc::now()), entries)
+    };
+    ledger.set_amount_scale(12);
+
+    ledger.set_balance_tolerance(0.0);
+    assert!(matches!(record(&mut ledger, &legs), Err(ZentryError::UnbalancedTransaction(_))));
+    ledger.set_balance_tolerance(f64::EPSILON);
+    assert!(matches!(record(&mut ledger, &legs), Err(ZentryError::UnbalancedTransaction(_))));
+    assert!(ledger.transactions.is_empty());
+
+    ledger.set_balance_tolerance(zentry_db::db::DEFAULT_BALANCE_TOLERANCE);
+    record(&mut ledger, &legs).unwrap();
+    assert_eq!(ledger.transactions.len(), 1);
+
+    // a real imbalance is still turned away
+    legs.push((cash, 0.01));
+    assert!(matches!(record(&mut ledger, &legs), Err(ZentryError::UnbalancedTransaction(_))));
+}
+
+#[test]
+fn duplicate_entry_id_is_rejected() {
+    let dir = TempDir::new("duplicate_entry");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+
+    let leg = ledger.new_entry(cash, 5.0).unwrap();
+    let entries = vec![leg.clone(), leg.clone(), ledger.new_entry(sales, -10.0).unwrap()];
+    match ledger.record_transaction(transaction("twice", Utc::now()), entries) {
+        Err(ZentryError::InvalidData(message)) => assert!(message.contains(&leg.id.to_string()), "{}", message),
+        other => panic!("expected InvalidData, got {:?}", other),
+    }
+    assert!(ledger.transactions.is_empty());
+    assert!(ledger.entries.is_empty());
+    assert!(ledger.find_entry(leg.id).is_none());
+}
+
+#[test]
+fn legs_on_the_same_account_merge_when_enabled() {
+    let dir = TempDir::new("merge_legs");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+    let legs = [(cash, 7.0), (sales, -10.0), (cash, 3.0)];
+
+    // off by default, every leg is stored as given
+    post(&mut ledger, Utc::now(), &legs);
+    assert_eq!(ledger.entries.len(), 3);
+
+    ledger.set_merge_same_account_legs(true);
+    let id = post(&mut ledger, Utc::now(), &legs);
+    drop(ledger);
+
+    let ledger = reload(&dir);
+    let merged: Vec<_> = ledger.entries.iter()
+        .filter(|entry| entry.transaction_id == id)
+        .map(|entry| (entry.account_id, entry.amount))
+        .collect();
+    assert_eq!(merged, [(cash, 10.0), (sales, -10.0)]);
+    assert_eq!(ledger.account_balance(cash), 20.0);
+}
+
+#[test]
+fn warnings_show_the_rounded_amount() {
+    let dir = TempDir::new("warnings_rounded");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let cash = create_account(&mut ledger, "Cash", AccountType::Asset, "USD");
+    let bank = create_account(&mut ledger, "Bank", AccountType::Asset, "USD");
+    let sales = create_account(&mut ledger, "Sales", AccountType::Revenue, "USD");
+
+    let entries = vec![
+        ledger.new_entry(cash, 0.1 + 0.2).unwrap(),
+        ledger.new_entry(sales, 0.1 + 0.2).unwrap(),
+        ledger.new_entry(bank, -0.6).unwrap(),
+        // stored as 0.00, on neither side
+        ledger.new_entry(sales, 0.004).unwrap(),
+    ];
+    let warnings = ledger.record_transaction_checked(transaction("refund", Utc::now()), entries).unwrap();
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(warnings[0].starts_with("Revenue account Sales ") && warnings[0].ends_with(" is debited 0.3"), "{}", warnings[0]);
+    assert!(warnings[1].starts_with("Asset account Bank ") && warnings[1].ends_with(" is credited 0.6"), "{}", warnings[1]);
+}
+
+#[test]
+fn warnings_name_a_custom_type_by_its_label() {
+    let dir = TempDir::new("warnings_custom_type");
+    let mut ledger = ledger(&dir);
+    ledger.create_system(system("USD")).unwrap();
+    let contra = register_account_type(60, "ContraAsset", false).unwrap();
+    let depreciation = create_account(&mut ledger, "Accumulated depreciation", contra, "USD");
+    let expense = create_account(&mut ledger, "Depreciation", AccountType::Expense, "USD");
+
+    let entries = vec![ledger.new_entry(depreciation, 25.0).unwrap(), ledger.new_entry(expense, -25.0).unwrap()];
+    let warnings = ledger.record_transaction_checked(transaction("write-back", Utc::now()), entries).unwrap();
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(warnings[0].starts_with("ContraAsset account Accumulated depreciation "), "{}", warnings[0]);
+    assert!(warnings[1].starts_with("Expense account Depreciation "), "{}", warnings[1]);
+}